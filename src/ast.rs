@@ -0,0 +1,728 @@
+use crate::lex::{Encoding, OperatorType, Span};
+use crate::types::{
+    integer_promotions, usual_arithmetic_conversions, ArraySize, CType, EnumId, IntSize, StructId,
+    INT, UNSIGNED_LONG,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOpKind {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Shl,
+    Shr,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitOr,
+    BitXor,
+    LogAnd,
+    LogOr,
+    Assign,
+    AddAssign,
+    SubAssign,
+    MulAssign,
+    DivAssign,
+    ModAssign,
+    AndAssign,
+    OrAssign,
+    XorAssign,
+    ShlAssign,
+    ShrAssign,
+    Comma,
+}
+
+impl BinOpKind {
+    /// The binary operator an operator token stands for. The comma is a
+    /// punctuator rather than an operator token, so it has no entry here.
+    pub fn from_operator(op: OperatorType) -> Option<BinOpKind> {
+        let kind = match op {
+            OperatorType::OpAdd => BinOpKind::Add,
+            OperatorType::OpSub => BinOpKind::Sub,
+            OperatorType::OpMul => BinOpKind::Mul,
+            OperatorType::OpDiv => BinOpKind::Div,
+            OperatorType::OpMod => BinOpKind::Mod,
+            OperatorType::OpShl => BinOpKind::Shl,
+            OperatorType::OpShr => BinOpKind::Shr,
+            OperatorType::OpLt => BinOpKind::Lt,
+            OperatorType::OpGt => BinOpKind::Gt,
+            OperatorType::OpLe => BinOpKind::Le,
+            OperatorType::OpGe => BinOpKind::Ge,
+            OperatorType::OpEq => BinOpKind::Eq,
+            OperatorType::OpNe => BinOpKind::Ne,
+            OperatorType::OpBitAnd => BinOpKind::BitAnd,
+            OperatorType::OpBitOr => BinOpKind::BitOr,
+            OperatorType::OpBitXor => BinOpKind::BitXor,
+            OperatorType::OpLogAnd => BinOpKind::LogAnd,
+            OperatorType::OpLogOr => BinOpKind::LogOr,
+            OperatorType::OpAssign => BinOpKind::Assign,
+            OperatorType::OpAddAssign => BinOpKind::AddAssign,
+            OperatorType::OpSubAssign => BinOpKind::SubAssign,
+            OperatorType::OpMulAssign => BinOpKind::MulAssign,
+            OperatorType::OpDivAssign => BinOpKind::DivAssign,
+            OperatorType::OpModAssign => BinOpKind::ModAssign,
+            OperatorType::OpAndAssign => BinOpKind::AndAssign,
+            OperatorType::OpOrAssign => BinOpKind::OrAssign,
+            OperatorType::OpXorAssign => BinOpKind::XorAssign,
+            OperatorType::OpShlAssign => BinOpKind::ShlAssign,
+            OperatorType::OpShrAssign => BinOpKind::ShrAssign,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    /// How the operator is spelled in C, for diagnostics.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinOpKind::Add => "+",
+            BinOpKind::Sub => "-",
+            BinOpKind::Mul => "*",
+            BinOpKind::Div => "/",
+            BinOpKind::Mod => "%",
+            BinOpKind::Shl => "<<",
+            BinOpKind::Shr => ">>",
+            BinOpKind::Lt => "<",
+            BinOpKind::Gt => ">",
+            BinOpKind::Le => "<=",
+            BinOpKind::Ge => ">=",
+            BinOpKind::Eq => "==",
+            BinOpKind::Ne => "!=",
+            BinOpKind::BitAnd => "&",
+            BinOpKind::BitOr => "|",
+            BinOpKind::BitXor => "^",
+            BinOpKind::LogAnd => "&&",
+            BinOpKind::LogOr => "||",
+            BinOpKind::Assign => "=",
+            BinOpKind::AddAssign => "+=",
+            BinOpKind::SubAssign => "-=",
+            BinOpKind::MulAssign => "*=",
+            BinOpKind::DivAssign => "/=",
+            BinOpKind::ModAssign => "%=",
+            BinOpKind::AndAssign => "&=",
+            BinOpKind::OrAssign => "|=",
+            BinOpKind::XorAssign => "^=",
+            BinOpKind::ShlAssign => "<<=",
+            BinOpKind::ShrAssign => ">>=",
+            BinOpKind::Comma => ",",
+        }
+    }
+
+    /// The operator a compound assignment applies, such as `+` for `+=`.
+    pub fn compound_op(self) -> Option<BinOpKind> {
+        let op = match self {
+            BinOpKind::AddAssign => BinOpKind::Add,
+            BinOpKind::SubAssign => BinOpKind::Sub,
+            BinOpKind::MulAssign => BinOpKind::Mul,
+            BinOpKind::DivAssign => BinOpKind::Div,
+            BinOpKind::ModAssign => BinOpKind::Mod,
+            BinOpKind::AndAssign => BinOpKind::BitAnd,
+            BinOpKind::OrAssign => BinOpKind::BitOr,
+            BinOpKind::XorAssign => BinOpKind::BitXor,
+            BinOpKind::ShlAssign => BinOpKind::Shl,
+            BinOpKind::ShrAssign => BinOpKind::Shr,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    pub fn is_assignment(self) -> bool {
+        matches!(
+            self,
+            BinOpKind::Assign
+                | BinOpKind::AddAssign
+                | BinOpKind::SubAssign
+                | BinOpKind::MulAssign
+                | BinOpKind::DivAssign
+                | BinOpKind::ModAssign
+                | BinOpKind::AndAssign
+                | BinOpKind::OrAssign
+                | BinOpKind::XorAssign
+                | BinOpKind::ShlAssign
+                | BinOpKind::ShrAssign
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOpKind {
+    Plus,
+    Neg,
+    LogNot,
+    BitNot,
+    Deref,
+    AddrOf,
+    PreInc,
+    PreDec,
+    PostInc,
+    PostDec,
+}
+
+impl UnaryOpKind {
+    /// How the operator is spelled in C, for diagnostics.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnaryOpKind::Plus => "+",
+            UnaryOpKind::Neg => "-",
+            UnaryOpKind::LogNot => "!",
+            UnaryOpKind::BitNot => "~",
+            UnaryOpKind::Deref => "*",
+            UnaryOpKind::AddrOf => "&",
+            UnaryOpKind::PreInc | UnaryOpKind::PostInc => "++",
+            UnaryOpKind::PreDec | UnaryOpKind::PostDec => "--",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SizeofArg {
+    Type(CType),
+    Expr(Box<Expr>),
+}
+
+/// An expression. Every node records the source span it was parsed from:
+/// the last field of each variant.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// An integer or character constant, with its type.
+    IntLit(u64, CType, Span),
+    FloatLit(f64, Span),
+    /// One or more adjacent string literals: the values of their elements,
+    /// escapes decoded and without the terminating null, and the encoding
+    /// they are stored in.
+    StrLit(Vec<u32>, Encoding, Span),
+    Ident(String, Span),
+    /// A use of an enumeration constant, with its value.
+    Enumerator(String, i64, Span),
+    BinOp {
+        op: BinOpKind,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+        span: Span,
+    },
+    UnaryOp {
+        op: UnaryOpKind,
+        expr: Box<Expr>,
+        span: Span,
+    },
+    Call {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+        span: Span,
+    },
+    Index {
+        base: Box<Expr>,
+        idx: Box<Expr>,
+        span: Span,
+    },
+    Member {
+        obj: Box<Expr>,
+        field: String,
+        arrow: bool,
+        span: Span,
+    },
+    Cast {
+        ty: CType,
+        expr: Box<Expr>,
+        span: Span,
+    },
+    Ternary {
+        cond: Box<Expr>,
+        then: Box<Expr>,
+        els: Box<Expr>,
+        span: Span,
+    },
+    Sizeof(SizeofArg, Span),
+    /// A braced initializer such as `{1, 2}`; only valid as a declaration's
+    /// `init`.
+    InitList(Vec<Expr>, Span),
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::IntLit(_, _, span)
+            | Expr::FloatLit(_, span)
+            | Expr::StrLit(_, _, span)
+            | Expr::Ident(_, span)
+            | Expr::Enumerator(_, _, span)
+            | Expr::BinOp { span, .. }
+            | Expr::UnaryOp { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Index { span, .. }
+            | Expr::Member { span, .. }
+            | Expr::Cast { span, .. }
+            | Expr::Ternary { span, .. }
+            | Expr::Sizeof(_, span)
+            | Expr::InitList(_, span) => *span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageClass {
+    Typedef,
+    Extern,
+    Static,
+    Auto,
+    Register,
+}
+
+/// A declaration of one name, such as `int x = 1;`. A `typedef` is a
+/// declaration too, with `storage` set to `StorageClass::Typedef`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decl {
+    pub name: String,
+    pub ty: CType,
+    pub storage: Option<StorageClass>,
+    pub init: Option<Expr>,
+    pub span: Span,
+}
+
+/// A function parameter. Array and function parameter types have already
+/// been adjusted to pointers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: Option<String>,
+    pub ty: CType,
+    pub span: Span,
+}
+
+/// A function definition; `ty` is the `CType::Function` it defines and
+/// `body` the `Stmt::Block` that follows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionDef {
+    pub name: String,
+    pub ty: CType,
+    pub storage: Option<StorageClass>,
+    pub params: Vec<Param>,
+    pub body: Stmt,
+    pub span: Span,
+}
+
+/// A member of a struct or union. `name` is `None` for an unnamed
+/// bit-field, and for a C11 anonymous struct or union member.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDecl {
+    pub name: Option<String>,
+    pub ty: CType,
+    pub bit_width: Option<Expr>,
+    pub span: Span,
+}
+
+/// The struct or union a `StructId` refers to. `fields` is `None` while it
+/// has only been declared, as in `struct s;`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructDef {
+    pub tag: Option<String>,
+    pub is_union: bool,
+    pub fields: Option<Vec<FieldDecl>>,
+    pub span: Span,
+}
+
+/// An enumeration constant: `init` is the expression after its `=`, if
+/// any, and `value` what the parser worked out it stands for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumConst {
+    pub name: String,
+    pub init: Option<Expr>,
+    pub value: i64,
+    pub span: Span,
+}
+
+/// The enum an `EnumId` refers to; `constants` is `None` until it is defined.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumDef {
+    pub tag: Option<String>,
+    pub constants: Option<Vec<EnumConst>>,
+    pub span: Span,
+}
+
+/// The first clause of a `for` statement.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ForInit {
+    Expr(Expr),
+    Decl(Vec<Decl>),
+}
+
+/// An entry in a compound statement.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockItem {
+    Decl(Decl),
+    Stmt(Stmt),
+}
+
+/// A statement. As with `Expr`, the last field of each variant is its span,
+/// except for `Decl`, whose declaration carries its own.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Expr(Expr, Span),
+    Return(Option<Expr>, Span),
+    If {
+        cond: Expr,
+        then: Box<Stmt>,
+        else_: Option<Box<Stmt>>,
+        span: Span,
+    },
+    While {
+        cond: Expr,
+        body: Box<Stmt>,
+        span: Span,
+    },
+    DoWhile {
+        body: Box<Stmt>,
+        cond: Expr,
+        span: Span,
+    },
+    For {
+        init: Option<ForInit>,
+        cond: Option<Expr>,
+        step: Option<Expr>,
+        body: Box<Stmt>,
+        span: Span,
+    },
+    Break(Span),
+    Continue(Span),
+    Goto(String, Span),
+    Label(String, Box<Stmt>, Span),
+    Switch {
+        expr: Expr,
+        body: Box<Stmt>,
+        span: Span,
+    },
+    Case(Expr, Box<Stmt>, Span),
+    Default(Box<Stmt>, Span),
+    Block(Vec<BlockItem>, Span),
+    Decl(Decl),
+    /// A lone `;`.
+    Empty(Span),
+}
+
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Expr(_, span)
+            | Stmt::Return(_, span)
+            | Stmt::If { span, .. }
+            | Stmt::While { span, .. }
+            | Stmt::DoWhile { span, .. }
+            | Stmt::For { span, .. }
+            | Stmt::Break(span)
+            | Stmt::Continue(span)
+            | Stmt::Goto(_, span)
+            | Stmt::Label(_, _, span)
+            | Stmt::Switch { span, .. }
+            | Stmt::Case(_, _, span)
+            | Stmt::Default(_, span)
+            | Stmt::Block(_, span)
+            | Stmt::Empty(span) => *span,
+            Stmt::Decl(decl) => decl.span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExternalDecl {
+    Decl(Decl),
+    Function(FunctionDef),
+}
+
+/// Everything declared in one source file, in order. `StructId`s and
+/// `EnumId`s in the types index `structs` and `enums`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TranslationUnit {
+    pub decls: Vec<ExternalDecl>,
+    pub structs: Vec<StructDef>,
+    pub enums: Vec<EnumDef>,
+}
+
+/// Why a type has no size known at compile time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayoutError {
+    /// It contains a variable length array.
+    Variable,
+    /// Its size does not fit in 64 bits.
+    TooLarge,
+    /// It is an array of unknown size or a struct or union that is only
+    /// declared.
+    Incomplete,
+    /// It has bit-fields, which are not laid out yet.
+    BitField,
+}
+
+impl TranslationUnit {
+    pub fn struct_def(&self, id: StructId) -> &StructDef {
+        &self.structs[id.0]
+    }
+
+    pub fn enum_def(&self, id: EnumId) -> &EnumDef {
+        &self.enums[id.0]
+    }
+
+    /// The value of an integer constant expression.
+    pub fn const_eval(&self, expr: &Expr) -> Option<i64> {
+        self.const_value(expr).map(|(value, _)| value)
+    }
+
+    /// Like `const_eval`, but also gives the type of the expression. An
+    /// unsigned value is zero-extended to 64 bits and a signed one
+    /// sign-extended, so `-1 < 0u` compares 4294967295 with 0 as in C.
+    pub fn const_value(&self, expr: &Expr) -> Option<(i64, CType)> {
+        match expr {
+            Expr::IntLit(value, ty, _) => Some((convert(*value as i64, ty)?, ty.clone())),
+            Expr::Enumerator(_, value, _) => Some((*value, INT)),
+            Expr::Cast { ty, expr, .. } => {
+                let (value, _) = self.const_value(expr)?;
+                Some((convert(value, ty)?, ty.strip().clone()))
+            }
+            Expr::Sizeof(SizeofArg::Type(ty), _) => {
+                let (size, _) = self.layout(ty).ok()?;
+                Some((size as i64, UNSIGNED_LONG))
+            }
+            Expr::UnaryOp { op, expr, .. } => {
+                let (value, ty) = self.const_value(expr)?;
+                let ty = integer_promotions(&ty);
+                let value = match op {
+                    UnaryOpKind::Plus => value,
+                    UnaryOpKind::Neg => value.wrapping_neg(),
+                    UnaryOpKind::BitNot => !value,
+                    UnaryOpKind::LogNot => return Some(((value == 0) as i64, INT)),
+                    _ => return None,
+                };
+                Some((convert(value, &ty)?, ty))
+            }
+            Expr::BinOp { op, lhs, rhs, .. } => {
+                let (lhs, lhs_ty) = self.const_value(lhs)?;
+                match op {
+                    BinOpKind::LogAnd if lhs == 0 => return Some((0, INT)),
+                    BinOpKind::LogOr if lhs != 0 => return Some((1, INT)),
+                    _ => {}
+                }
+                let (rhs, rhs_ty) = self.const_value(rhs)?;
+                // The shifted operand alone decides the type of a shift.
+                let ty = match op {
+                    BinOpKind::Shl | BinOpKind::Shr => integer_promotions(&lhs_ty),
+                    BinOpKind::Comma => return Some((rhs, rhs_ty)),
+                    _ => usual_arithmetic_conversions(&lhs_ty, &rhs_ty),
+                };
+                let (_, signed) = ty.int_repr()?;
+                let (a, b) = (convert(lhs, &ty)?, convert(rhs, &ty)?);
+                let value = match op {
+                    BinOpKind::Add => a.wrapping_add(b),
+                    BinOpKind::Sub => a.wrapping_sub(b),
+                    BinOpKind::Mul => a.wrapping_mul(b),
+                    BinOpKind::Div if signed => a.checked_div(b)?,
+                    BinOpKind::Div => (a as u64).checked_div(b as u64)? as i64,
+                    BinOpKind::Mod if signed => a.checked_rem(b)?,
+                    BinOpKind::Mod => (a as u64).checked_rem(b as u64)? as i64,
+                    BinOpKind::Shl => a.wrapping_shl(rhs as u32),
+                    BinOpKind::Shr if signed => a.wrapping_shr(rhs as u32),
+                    BinOpKind::Shr => (a as u64).wrapping_shr(rhs as u32) as i64,
+                    BinOpKind::BitAnd => a & b,
+                    BinOpKind::BitOr => a | b,
+                    BinOpKind::BitXor => a ^ b,
+                    _ => {
+                        let (a, b) = if signed {
+                            (a as i128, b as i128)
+                        } else {
+                            (a as u64 as i128, b as u64 as i128)
+                        };
+                        let truth = match op {
+                            BinOpKind::Lt => a < b,
+                            BinOpKind::Gt => a > b,
+                            BinOpKind::Le => a <= b,
+                            BinOpKind::Ge => a >= b,
+                            BinOpKind::Eq => a == b,
+                            BinOpKind::Ne => a != b,
+                            BinOpKind::LogAnd | BinOpKind::LogOr => b != 0,
+                            _ => return None,
+                        };
+                        return Some((truth as i64, INT));
+                    }
+                };
+                Some((convert(value, &ty)?, ty))
+            }
+            Expr::Ternary {
+                cond, then, els, ..
+            } => {
+                let (cond, _) = self.const_value(cond)?;
+                let (taken, other) = if cond != 0 { (then, els) } else { (els, then) };
+                let (value, mut ty) = self.const_value(taken)?;
+                // The other operand is not evaluated, so "1 ? 2 : 1 / 0" is
+                // fine, but it still takes part in the result type.
+                if let Some((_, other_ty)) = self.const_value(other) {
+                    ty = usual_arithmetic_conversions(&ty, &other_ty);
+                }
+                Some((convert(value, &ty)?, ty))
+            }
+            _ => None,
+        }
+    }
+
+    /// The size and alignment of `ty` on an LP64 target.
+    pub fn layout(&self, ty: &CType) -> Result<(u64, u64), LayoutError> {
+        Ok(match ty.strip() {
+            CType::Void | CType::Function { .. } | CType::Bool | CType::Char(_) => (1, 1),
+            CType::Int { size, .. } => match size {
+                IntSize::Short => (2, 2),
+                IntSize::Int => (4, 4),
+                IntSize::Long | IntSize::LongLong => (8, 8),
+            },
+            CType::Enum(_) | CType::Float => (4, 4),
+            CType::Double | CType::Pointer { .. } => (8, 8),
+            CType::LongDouble => (16, 16),
+            CType::Array { elem, size } => {
+                let (elem_size, align) = self.layout(elem)?;
+                match size {
+                    ArraySize::Fixed(n) => (
+                        elem_size.checked_mul(*n).ok_or(LayoutError::TooLarge)?,
+                        align,
+                    ),
+                    ArraySize::Incomplete => return Err(LayoutError::Incomplete),
+                    ArraySize::Variable => return Err(LayoutError::Variable),
+                }
+            }
+            CType::Struct(id) | CType::Union(id) => {
+                let def = self.struct_def(*id);
+                let fields = def.fields.as_ref().ok_or(LayoutError::Incomplete)?;
+                if fields.iter().any(|field| field.bit_width.is_some()) {
+                    return Err(LayoutError::BitField);
+                }
+                let (mut size, mut align) = (0, 1);
+                for field in fields {
+                    let (field_size, field_align) = self.member_layout(&field.ty)?;
+                    align = align.max(field_align);
+                    size = if def.is_union {
+                        size.max(field_size)
+                    } else {
+                        size.next_multiple_of(field_align)
+                            .checked_add(field_size)
+                            .ok_or(LayoutError::TooLarge)?
+                    };
+                }
+                (size.next_multiple_of(align), align)
+            }
+            CType::Typedef(..) | CType::Qualified { .. } => unreachable!(),
+        })
+    }
+
+    /// The layout of a struct or union member of type `ty`. An array of
+    /// unknown size there is a flexible array member and takes no space.
+    pub fn member_layout(&self, ty: &CType) -> Result<(u64, u64), LayoutError> {
+        match ty.strip() {
+            CType::Array {
+                elem,
+                size: ArraySize::Incomplete,
+            } => Ok((0, self.layout(elem)?.1)),
+            _ => self.layout(ty),
+        }
+    }
+}
+
+/// `value` converted to the integer type `ty`, or `None` if `ty` is not one.
+fn convert(value: i64, ty: &CType) -> Option<i64> {
+    if *ty.strip() == CType::Bool {
+        return Some((value != 0) as i64);
+    }
+    let (bits, signed) = ty.int_repr()?;
+    let shift = 64 - bits;
+    Some(if signed {
+        value << shift >> shift
+    } else {
+        ((value as u64) << shift >> shift) as i64
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operator_kinds() {
+        assert_eq!(
+            BinOpKind::from_operator(OperatorType::OpShlAssign),
+            Some(BinOpKind::ShlAssign)
+        );
+        assert_eq!(
+            BinOpKind::from_operator(OperatorType::OpLogOr),
+            Some(BinOpKind::LogOr)
+        );
+        assert_eq!(BinOpKind::from_operator(OperatorType::OpArrow), None);
+        assert_eq!(BinOpKind::from_operator(OperatorType::OpBitNot), None);
+
+        assert!(BinOpKind::OrAssign.is_assignment());
+        assert!(!BinOpKind::Eq.is_assignment());
+    }
+
+    #[test]
+    fn spans() {
+        let span = |start, end| Span { start, end };
+        // a[1] + (char)b
+        let expr = Expr::BinOp {
+            op: BinOpKind::Add,
+            lhs: Box::new(Expr::Index {
+                base: Box::new(Expr::Ident(String::from("a"), span(0, 1))),
+                idx: Box::new(Expr::IntLit(1, crate::types::INT, span(2, 3))),
+                span: span(0, 4),
+            }),
+            rhs: Box::new(Expr::Cast {
+                ty: CType::Char(crate::types::Signedness::Signed),
+                expr: Box::new(Expr::Ident(String::from("b"), span(13, 14))),
+                span: span(7, 14),
+            }),
+            span: span(0, 14),
+        };
+
+        assert_eq!(expr.span(), span(0, 14));
+        if let Expr::BinOp { lhs, rhs, .. } = &expr {
+            assert_eq!(lhs.span(), span(0, 4));
+            assert_eq!(rhs.span(), span(7, 14));
+        }
+    }
+
+    #[test]
+    fn statement_spans() {
+        let span = |start, end| Span { start, end };
+        // while (x) { int y; break; }
+        let stmt = Stmt::While {
+            cond: Expr::Ident(String::from("x"), span(7, 8)),
+            body: Box::new(Stmt::Block(
+                vec![
+                    BlockItem::Decl(Decl {
+                        name: String::from("y"),
+                        ty: CType::Int {
+                            size: crate::types::IntSize::Int,
+                            sign: crate::types::Signedness::Signed,
+                        },
+                        storage: None,
+                        init: None,
+                        span: span(12, 18),
+                    }),
+                    BlockItem::Stmt(Stmt::Break(span(19, 25))),
+                ],
+                span(10, 27),
+            )),
+            span: span(0, 27),
+        };
+
+        assert_eq!(stmt.span(), span(0, 27));
+        if let Stmt::While { body, .. } = &stmt {
+            assert_eq!(body.span(), span(10, 27));
+            if let Stmt::Block(items, _) = body.as_ref() {
+                let spans: Vec<Span> = items
+                    .iter()
+                    .map(|item| match item {
+                        BlockItem::Decl(decl) => Stmt::Decl(decl.clone()).span(),
+                        BlockItem::Stmt(stmt) => stmt.span(),
+                    })
+                    .collect();
+                assert_eq!(spans, vec![span(12, 18), span(19, 25)]);
+            }
+        }
+    }
+}
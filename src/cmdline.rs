@@ -1,5 +1,5 @@
 use std::collections::HashMap;
-use std::process;
+use std::fmt;
 
 #[derive(Debug)]
 pub enum CmdValType {
@@ -10,6 +10,19 @@ pub enum CmdValType {
     ValOptSpace,
 }
 
+impl fmt::Display for CmdValType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let val_type = match self {
+            CmdValType::NoVal => "no value",
+            CmdValType::OptVal => "optional attached value",
+            CmdValType::ValSpace => "separate value",
+            CmdValType::ValNoSpace => "attached value",
+            CmdValType::ValOptSpace => "attached or separate value",
+        };
+        write!(f, "{}", val_type)
+    }
+}
+
 #[derive(Debug)]
 pub struct CmdInfo {
     pub short: String,
@@ -18,6 +31,9 @@ pub struct CmdInfo {
     pub val_type: CmdValType,
     pub val_str: String,
     pub index: usize,
+    /// Value reported when the option is absent from the command line. It
+    /// is kept as a one-element list so it reads like a parsed value.
+    pub default: Option<Vec<String>>,
 }
 
 #[derive(Debug)]
@@ -26,12 +42,52 @@ pub enum CmdMatchType {
     Long,
 }
 
-impl CmdInfo {
-    pub fn eq(&self, other: &Self) -> bool {
+impl PartialEq for CmdInfo {
+    fn eq(&self, other: &Self) -> bool {
         (self.short == other.short) && (self.long == other.long)
     }
 }
 
+/// Errors reported while registering options or parsing the command line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CmdError {
+    /// An option was registered without a short or a long name.
+    MissingCommand,
+    /// An option was registered without help text.
+    MissingHelp(String),
+    /// An option that takes a value was given none.
+    MissingValue(String),
+    /// An option that takes no value was given one with `=`.
+    UnexpectedValue(String),
+    /// An argument starting with `-` matched no registered option.
+    UnknownOption(String),
+}
+
+impl CmdError {
+    /// The option the error refers to, if any.
+    pub fn arg(&self) -> Option<&str> {
+        match self {
+            CmdError::MissingCommand => None,
+            CmdError::MissingHelp(arg)
+            | CmdError::MissingValue(arg)
+            | CmdError::UnexpectedValue(arg)
+            | CmdError::UnknownOption(arg) => Some(arg),
+        }
+    }
+}
+
+impl fmt::Display for CmdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CmdError::MissingCommand => write!(f, "Missing command."),
+            CmdError::MissingHelp(arg) => write!(f, "{}: Missing help info.", arg),
+            CmdError::MissingValue(arg) => write!(f, "{}: Missing value.", arg),
+            CmdError::UnexpectedValue(arg) => write!(f, "{}: Unexpected value.", arg),
+            CmdError::UnknownOption(arg) => write!(f, "{}: Unknown option.", arg),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct CmdLine {
     pub info: Vec<CmdInfo>,
@@ -39,15 +95,19 @@ pub struct CmdLine {
     pub others: Vec<String>,
 }
 
+impl Default for CmdLine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CmdLine {
     pub fn new() -> Self {
-        let cmdline = CmdLine {
+        CmdLine {
             info: Vec::<CmdInfo>::new(),
             args: HashMap::<usize, Vec<String>>::new(),
             others: Vec::<String>::new(),
-        };
-
-        return cmdline;
+        }
     }
 
     pub fn add(
@@ -57,36 +117,58 @@ impl CmdLine {
         help: &str,
         val_type: CmdValType,
         val_str: &str,
-    ) {
+    ) -> Result<(), CmdError> {
+        self.add_with_default(short, long, help, val_type, val_str, None)
+    }
+
+    /// Like `add`, but `get_value*` fall back to `default` when the option is
+    /// not given at all. An option given without a value does not use it.
+    pub fn add_with_default(
+        &mut self,
+        short: &str,
+        long: &str,
+        help: &str,
+        val_type: CmdValType,
+        val_str: &str,
+        default: Option<&str>,
+    ) -> Result<(), CmdError> {
         let cmd = CmdInfo {
             short: String::from(short),
             long: String::from(long),
             help: String::from(help),
-            val_type: val_type,
-            val_str: if val_str.len() == 0 {
+            val_type,
+            val_str: if val_str.is_empty() {
                 String::from("value")
             } else {
                 String::from(val_str)
             },
             index: self.info.len(),
+            default: default.map(|default| vec![String::from(default)]),
         };
 
-        if (short.len() == 0) && (long.len() == 0) {
-            eprintln!("Missing command.");
-            return;
+        if (short.is_empty()) && (long.is_empty()) {
+            return Err(CmdError::MissingCommand);
         }
-        if help.len() == 0 {
-            eprintln!("{} {}: Missing help info.", short, long);
-            return;
+        if help.is_empty() {
+            return Err(CmdError::MissingHelp(Self::name(&cmd).to_string()));
         }
 
         for tmp in &self.info {
-            if tmp.eq(&cmd) {
-                return;
+            if *tmp == cmd {
+                return Ok(());
             }
         }
 
         self.info.push(cmd);
+        Ok(())
+    }
+
+    fn name(cmd: &CmdInfo) -> &str {
+        if !cmd.short.is_empty() {
+            &cmd.short
+        } else {
+            &cmd.long
+        }
     }
 
     pub fn help(&self) -> String {
@@ -95,9 +177,9 @@ impl CmdLine {
         for cmd in &self.info {
             let mut tmp_str = String::from("  ");
 
-            if cmd.short.len() > 0 {
+            if !cmd.short.is_empty() {
                 tmp_str += &cmd.short;
-                if cmd.long.len() > 0 {
+                if !cmd.long.is_empty() {
                     tmp_str += format!("({})", cmd.long).as_str();
                 }
             } else {
@@ -129,7 +211,7 @@ impl CmdLine {
         }
         help.pop();
 
-        return help;
+        help
     }
 
     pub fn get_index(&self, str: &str) -> Option<usize> {
@@ -139,7 +221,7 @@ impl CmdLine {
             }
         }
 
-        return None;
+        None
     }
 
     pub fn cmd_is_meatch(cmd: &CmdInfo, str: &str) -> Option<CmdMatchType> {
@@ -149,46 +231,39 @@ impl CmdLine {
                     return Some(CmdMatchType::Short);
                 }
                 if cmd.long == str {
-                    return Some(CmdMatchType::Long);
+                    Some(CmdMatchType::Long)
                 } else {
-                    return None;
+                    None
                 }
             }
             CmdValType::OptVal | CmdValType::ValNoSpace | CmdValType::ValOptSpace => {
-                if cmd.long.len() > 0 {
-                    let len = if cmd.long.len() < str.len() {
-                        cmd.long.len()
-                    } else {
-                        str.len()
-                    };
-                    if cmd.long[..len] == str[..len] {
-                        return Some(CmdMatchType::Long);
-                    }
+                // The option must be a whole prefix of the argument, so that
+                // "-" or "-s" never match "-std=".
+                if !cmd.long.is_empty() && str.starts_with(&cmd.long) {
+                    return Some(CmdMatchType::Long);
                 }
-
-                if cmd.short.len() > 0 {
-                    let len = if cmd.short.len() < str.len() {
-                        cmd.short.len()
-                    } else {
-                        str.len()
-                    };
-                    if cmd.short[..len] == str[..len] {
-                        return Some(CmdMatchType::Short);
-                    }
+                if !cmd.short.is_empty() && str.starts_with(&cmd.short) {
+                    return Some(CmdMatchType::Short);
                 }
 
-                return None;
+                None
             }
         }
     }
 
-    pub fn parse(&mut self, args: &Vec<String>) {
+    pub fn parse(&mut self, args: &[String]) -> Result<(), CmdError> {
+        self.parse_from(args)
+    }
+
+    pub fn parse_from(&mut self, args: &[impl AsRef<str>]) -> Result<(), CmdError> {
         let mut get_valne = false;
         let mut index: usize = 0;
-        let mut meatched = false;
+        let mut operands = false;
 
-        for arg in args {
-            if get_valne {
+        for arg in args.iter().map(|arg| arg.as_ref()) {
+            if operands {
+                self.others.push(arg.to_string());
+            } else if get_valne {
                 get_valne = false;
                 let arg_tmp = self.args.get(&index);
                 if let Some(arg_val) = arg_tmp {
@@ -198,158 +273,162 @@ impl CmdLine {
                 } else {
                     self.args.insert(index, vec![arg.to_string()]);
                 }
-            } else {
-                for cmd in &self.info {
-                    if let Some(match_type) = Self::cmd_is_meatch(cmd, arg) {
-                        meatched = true;
-                        match cmd.val_type {
-                            CmdValType::NoVal => {
-                                self.args.insert(cmd.index, Vec::<String>::new());
-                            }
-                            CmdValType::ValSpace => {
-                                index = cmd.index;
-                                get_valne = true;
-                            }
-                            CmdValType::OptVal => {
-                                let mut value = String::new();
-                                match match_type {
-                                    CmdMatchType::Long => {
-                                        if cmd.long.len() < arg.len() {
-                                            value = arg[cmd.long.len()..].to_string();
-                                        }
-                                    }
-                                    CmdMatchType::Short => {
-                                        if cmd.short.len() < arg.len() {
-                                            value = arg[cmd.short.len()..].to_string();
-                                        }
-                                    }
-                                }
-
-                                let arg_tmp = self.args.get(&cmd.index);
-                                if let Some(arg_val) = arg_tmp {
-                                    if value.len() > 0 {
-                                        let mut val_tmp: Vec<String> = arg_val.to_vec();
-                                        val_tmp.push(value);
-                                        self.args.insert(cmd.index, val_tmp);
-                                    }
-                                } else {
-                                    if value.len() > 0 {
-                                        self.args.insert(cmd.index, vec![value]);
-                                    } else {
-                                        self.args.insert(cmd.index, Vec::<String>::new());
-                                    }
-                                }
-                            }
-                            CmdValType::ValNoSpace => {
-                                let value: String;
-                                match match_type {
-                                    CmdMatchType::Long => {
-                                        if cmd.long.len() < arg.len() {
-                                            value = arg[cmd.long.len()..].to_string();
-                                        } else {
-                                            process::exit(-1);
-                                        }
-                                    }
-                                    CmdMatchType::Short => {
-                                        if cmd.short.len() < arg.len() {
-                                            value = arg[cmd.short.len()..].to_string();
-                                        } else {
-                                            process::exit(-1);
-                                        }
-                                    }
-                                }
-
-                                let arg_tmp = self.args.get(&cmd.index);
-                                if let Some(arg_val) = arg_tmp {
-                                    let mut val_tmp: Vec<String> = arg_val.to_vec();
-                                    val_tmp.push(value);
-                                    self.args.insert(cmd.index, val_tmp);
-                                } else {
-                                    self.args.insert(cmd.index, vec![value]);
-                                }
-                            }
-                            CmdValType::ValOptSpace => {
-                                let mut value = String::new();
-                                match match_type {
-                                    CmdMatchType::Long => {
-                                        if cmd.long.len() < arg.len() {
-                                            value = arg[cmd.long.len()..].to_string();
-                                        } else {
-                                            index = cmd.index;
-                                            get_valne = true;
-                                            break;
-                                        }
-                                    }
-                                    CmdMatchType::Short => {
-                                        if cmd.short.len() < arg.len() {
-                                            value = arg[cmd.short.len()..].to_string();
-                                        } else {
-                                            index = cmd.index;
-                                            get_valne = true;
-                                            break;
-                                        }
-                                    }
-                                }
-
-                                let arg_tmp = self.args.get(&cmd.index);
-                                if let Some(arg_val) = arg_tmp {
-                                    let mut val_tmp: Vec<String> = arg_val.to_vec();
-                                    val_tmp.push(value);
-                                    self.args.insert(cmd.index, val_tmp);
-                                } else {
-                                    self.args.insert(cmd.index, vec![value]);
-                                }
-                            }
+            } else if arg == "--" {
+                // Everything after "--" is an operand, even if it looks like an option.
+                operands = true;
+            } else if let Some((index, value)) = self.long_value(arg) {
+                let cmd = &self.info[index];
+                match cmd.val_type {
+                    CmdValType::NoVal => {
+                        return Err(CmdError::UnexpectedValue(Self::name(cmd).to_string()))
+                    }
+                    CmdValType::OptVal => {
+                        let vals = self.args.entry(index).or_default();
+                        if !value.is_empty() {
+                            vals.push(value.to_string());
+                        }
+                    }
+                    _ => {
+                        if value.is_empty() {
+                            return Err(CmdError::MissingValue(Self::name(cmd).to_string()));
+                        }
+                        self.args.entry(index).or_default().push(value.to_string());
+                    }
+                }
+            } else if let Some((cmd, match_type)) = Self::longest_match(&self.info, arg) {
+                // What follows the option name, for the types that may take an
+                // attached value.
+                let name = match match_type {
+                    CmdMatchType::Long => &cmd.long,
+                    CmdMatchType::Short => &cmd.short,
+                };
+                let value = &arg[name.len()..];
+                match cmd.val_type {
+                    CmdValType::NoVal => {
+                        self.args.insert(cmd.index, Vec::<String>::new());
+                    }
+                    CmdValType::ValSpace => {
+                        index = cmd.index;
+                        get_valne = true;
+                    }
+                    CmdValType::OptVal => {
+                        let vals = self.args.entry(cmd.index).or_default();
+                        if !value.is_empty() {
+                            vals.push(value.to_string());
+                        }
+                    }
+                    CmdValType::ValNoSpace => {
+                        if value.is_empty() {
+                            return Err(CmdError::MissingValue(Self::name(cmd).to_string()));
+                        }
+                        self.args
+                            .entry(cmd.index)
+                            .or_default()
+                            .push(value.to_string());
+                    }
+                    CmdValType::ValOptSpace => {
+                        if value.is_empty() {
+                            index = cmd.index;
+                            get_valne = true;
+                        } else {
+                            self.args
+                                .entry(cmd.index)
+                                .or_default()
+                                .push(value.to_string());
                         }
-                        break;
                     }
                 }
-                if !meatched {
-                    self.others.push(arg.to_string());
+            } else if let Some(indexes) = self.short_group(arg) {
+                for index in indexes {
+                    self.args.insert(index, Vec::<String>::new());
                 }
-                meatched = false;
+            } else if arg.starts_with('-') && arg != "-" {
+                // A lone "-" conventionally names stdin, so it is an operand.
+                return Err(CmdError::UnknownOption(arg.to_string()));
+            } else {
+                self.others.push(arg.to_string());
             }
         }
 
         if get_valne {
-            eprintln!(
-                "{}: Missing value.",
-                if self.info[index].short.len() > 0 {
-                    &self.info[index].short
+            return Err(CmdError::MissingValue(
+                Self::name(&self.info[index]).to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    // The option `arg` refers to. When several match, as "-I" and "-include"
+    // both match "-include", the one with the longest name wins, whatever
+    // order they were registered in.
+    fn longest_match<'a>(info: &'a [CmdInfo], arg: &str) -> Option<(&'a CmdInfo, CmdMatchType)> {
+        info.iter()
+            .filter_map(|cmd| Self::cmd_is_meatch(cmd, arg).map(|match_type| (cmd, match_type)))
+            .max_by_key(|(cmd, match_type)| match match_type {
+                CmdMatchType::Long => cmd.long.len(),
+                CmdMatchType::Short => cmd.short.len(),
+            })
+    }
+
+    // Splits "--name=value" on the first '=' and looks up the long option
+    // "--name", which may also have been registered as "--name=". A short
+    // option that takes a separate value, such as "-o", also accepts
+    // "-o=value"; the value itself may contain further '='.
+    fn long_value<'a>(&self, arg: &'a str) -> Option<(usize, &'a str)> {
+        let (name, value) = arg.split_once('=')?;
+        let long = arg.starts_with("--");
+        self.info
+            .iter()
+            .find(|cmd| {
+                if long {
+                    cmd.long.strip_suffix('=').unwrap_or(&cmd.long) == name
                 } else {
-                    &self.info[index].long
+                    matches!(cmd.val_type, CmdValType::ValSpace) && cmd.short == name
                 }
-            );
-            process::exit(-1);
+            })
+            .map(|cmd| (cmd.index, value))
+    }
+
+    // Splits a group such as "-vc" into "-v -c". Every letter must be a
+    // NoVal short option, otherwise the group is not an option at all.
+    fn short_group(&self, arg: &str) -> Option<Vec<usize>> {
+        if arg.len() < 3 || !arg.starts_with('-') || arg.starts_with("--") {
+            return None;
         }
+
+        arg[1..]
+            .chars()
+            .map(|c| {
+                let short = format!("-{}", c);
+                self.info
+                    .iter()
+                    .find(|cmd| cmd.short == short && matches!(cmd.val_type, CmdValType::NoVal))
+                    .map(|cmd| cmd.index)
+            })
+            .collect()
     }
 
     pub fn is_include(&self, str: &str) -> bool {
         if let Some(index) = self.get_index(str) {
-            if let Some(_) = self.args.get(&index) {
+            if self.args.contains_key(&index) {
                 return true;
             }
         }
 
-        return false;
+        false
     }
 
     pub fn get_value(&self, cmd: &CmdInfo) -> Option<&Vec<String>> {
-        if let Some(vals) = self.args.get(&cmd.index) {
-            return Some(vals);
-        }
-
-        return None;
+        self.get_value_by_index(cmd.index)
     }
 
     pub fn get_value_by_name(&self, str: &str) -> Option<&Vec<String>> {
         if let Some(index) = self.get_index(str) {
-            if let Some(vals) = self.args.get(&index) {
-                return Some(vals);
-            }
+            return self.get_value_by_index(index);
         }
 
-        return None;
+        None
     }
 
     pub fn get_value_by_index(&self, index: usize) -> Option<&Vec<String>> {
@@ -357,6 +436,293 @@ impl CmdLine {
             return Some(vals);
         }
 
-        return None;
+        self.info.get(index)?.default.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_cmdline() -> CmdLine {
+        let mut cmdline = CmdLine::new();
+        cmdline
+            .add("-o", "", "Output file.", CmdValType::ValSpace, "file")
+            .unwrap();
+        cmdline
+            .add("-D", "", "Macro.", CmdValType::ValOptSpace, "macro")
+            .unwrap();
+        cmdline
+            .add("-std=", "", "Standard.", CmdValType::ValNoSpace, "")
+            .unwrap();
+        cmdline
+            .add("-c", "", "Compile only.", CmdValType::NoVal, "")
+            .unwrap();
+        cmdline
+            .add("-v", "", "Verbose.", CmdValType::NoVal, "")
+            .unwrap();
+        cmdline
+            .add("-E", "", "Preprocess only.", CmdValType::NoVal, "")
+            .unwrap();
+        cmdline
+            .add("", "--help", "Help.", CmdValType::NoVal, "")
+            .unwrap();
+        cmdline
+            .add_with_default("-x", "", "Language.", CmdValType::OptVal, "", Some("c"))
+            .unwrap();
+        cmdline
+            .add("", "--output", "Output file.", CmdValType::ValSpace, "file")
+            .unwrap();
+        cmdline
+            .add("", "--std=", "Standard.", CmdValType::ValNoSpace, "")
+            .unwrap();
+        cmdline
+    }
+
+    fn strings(strs: &[&str]) -> Vec<String> {
+        strs.iter().map(|str| str.to_string()).collect()
+    }
+
+    #[test]
+    fn add_errors() {
+        let mut cmdline = CmdLine::new();
+        assert_eq!(
+            cmdline.add("", "", "Help.", CmdValType::NoVal, ""),
+            Err(CmdError::MissingCommand)
+        );
+        assert_eq!(
+            cmdline.add("-x", "", "", CmdValType::NoVal, ""),
+            Err(CmdError::MissingHelp(String::from("-x")))
+        );
+        assert!(cmdline.info.is_empty());
+    }
+
+    #[test]
+    fn unknown_options() {
+        let mut cmdline = test_cmdline();
+        assert_eq!(
+            cmdline.parse_from(&["-c", "-Q", "foo.c"]),
+            Err(CmdError::UnknownOption(String::from("-Q")))
+        );
+        assert!(cmdline.others.is_empty());
+        assert_eq!(
+            test_cmdline().parse_from(&["--verbose=1"]),
+            Err(CmdError::UnknownOption(String::from("--verbose=1")))
+        );
+        assert_eq!(
+            test_cmdline().parse_from(&["-cQ"]),
+            Err(CmdError::UnknownOption(String::from("-cQ")))
+        );
+
+        let mut cmdline = test_cmdline();
+        cmdline.parse_from(&["foo.c", "--", "-Q"]).unwrap();
+        assert_eq!(cmdline.others, strings(&["foo.c", "-Q"]));
+    }
+
+    #[test]
+    fn longest_option_wins() {
+        let mut cmdline = CmdLine::new();
+        cmdline
+            .add("-I", "", "Include path.", CmdValType::ValOptSpace, "dir")
+            .unwrap();
+        cmdline
+            .add(
+                "-include",
+                "",
+                "Include file.",
+                CmdValType::ValSpace,
+                "file",
+            )
+            .unwrap();
+        cmdline
+            .add("-Include=", "", "Made up.", CmdValType::ValNoSpace, "x")
+            .unwrap();
+        cmdline
+            .parse_from(&[
+                "-include",
+                "a.h",
+                "-Include=b",
+                "-Ic",
+                "-I",
+                "d",
+                "-",
+                "e.c",
+            ])
+            .unwrap();
+        assert_eq!(
+            cmdline.get_value_by_name("-include"),
+            Some(&strings(&["a.h"]))
+        );
+        assert_eq!(
+            cmdline.get_value_by_name("-Include="),
+            Some(&strings(&["b"]))
+        );
+        assert_eq!(cmdline.get_value_by_name("-I"), Some(&strings(&["c", "d"])));
+        assert_eq!(cmdline.others, strings(&["-", "e.c"]));
+
+        // An argument shorter than an option is not that option.
+        assert_eq!(
+            test_cmdline().parse_from(&["-s"]),
+            Err(CmdError::UnknownOption(String::from("-s")))
+        );
+    }
+
+    #[test]
+    fn parse_values() {
+        let mut cmdline = test_cmdline();
+        cmdline
+            .parse_from(&["-o", "a.out", "-DFOO", "-D", "BAR", "-std=c11", "-c", "a.c"])
+            .unwrap();
+        assert_eq!(cmdline.get_value_by_name("-o"), Some(&strings(&["a.out"])));
+        assert_eq!(
+            cmdline.get_value_by_name("-D"),
+            Some(&strings(&["FOO", "BAR"]))
+        );
+        assert_eq!(cmdline.get_value_by_name("-std="), Some(&strings(&["c11"])));
+        assert!(cmdline.is_include("-c"));
+        assert_eq!(cmdline.others, strings(&["a.c"]));
+    }
+
+    #[test]
+    fn parse_missing_value() {
+        for (input, name) in [
+            (&["a.c", "-o"][..], "-o"),
+            (&["-D"][..], "-D"),
+            (&["-std="][..], "-std="),
+        ] {
+            let error = test_cmdline().parse_from(input).unwrap_err();
+            assert_eq!(error, CmdError::MissingValue(String::from(name)));
+            assert_eq!(error.arg(), Some(name));
+            assert_eq!(error.to_string(), format!("{}: Missing value.", name));
+        }
+
+        // The owned-string entry point reports it the same way.
+        let mut cmdline = test_cmdline();
+        assert_eq!(
+            cmdline.parse(&strings(&["-c", "foo.c", "-o"])),
+            Err(CmdError::MissingValue(String::from("-o")))
+        );
+        assert!(cmdline.get_value_by_name("-o").is_none());
+    }
+
+    #[test]
+    fn end_of_options() {
+        let mut cmdline = test_cmdline();
+        cmdline.parse_from(&["--", "-o", "foo.c"]).unwrap();
+        assert_eq!(cmdline.others, strings(&["-o", "foo.c"]));
+        assert!(!cmdline.is_include("-o"));
+
+        let mut cmdline = test_cmdline();
+        cmdline.parse_from(&["file1.c", "--", "file2.c"]).unwrap();
+        assert_eq!(cmdline.others, strings(&["file1.c", "file2.c"]));
+
+        // Only the first "--" is consumed.
+        let mut cmdline = test_cmdline();
+        cmdline.parse_from(&["-c", "--", "-weird.c", "--"]).unwrap();
+        assert_eq!(cmdline.others, strings(&["-weird.c", "--"]));
+        assert!(cmdline.is_include("-c"));
+    }
+
+    #[test]
+    fn short_option_groups() {
+        for (group, first, second) in [("-vc", "-v", "-c"), ("-Ev", "-E", "-v")] {
+            let mut cmdline = test_cmdline();
+            cmdline.parse_from(&[group, "foo.c"]).unwrap();
+            assert!(cmdline.is_include(first), "{}", group);
+            assert!(cmdline.is_include(second), "{}", group);
+            assert_eq!(cmdline.others, strings(&["foo.c"]));
+        }
+
+        for group in ["-vo", "-vx"] {
+            let mut cmdline = test_cmdline();
+            assert_eq!(
+                cmdline.parse_from(&[group]),
+                Err(CmdError::UnknownOption(String::from(group)))
+            );
+            assert!(!cmdline.is_include("-v"));
+        }
+    }
+
+    #[test]
+    fn long_option_values() {
+        let mut cmdline = test_cmdline();
+        cmdline
+            .parse_from(&["--std=c11", "--output=out.o", "a.c"])
+            .unwrap();
+        assert_eq!(
+            cmdline.get_value_by_name("--std="),
+            Some(&strings(&["c11"]))
+        );
+        assert_eq!(
+            cmdline.get_value_by_name("--output"),
+            Some(&strings(&["out.o"]))
+        );
+        assert_eq!(cmdline.others, strings(&["a.c"]));
+
+        assert_eq!(
+            test_cmdline().parse_from(&["--help=anything"]),
+            Err(CmdError::UnexpectedValue(String::from("--help")))
+        );
+        assert_eq!(
+            test_cmdline().parse_from(&["--output="]),
+            Err(CmdError::MissingValue(String::from("--output")))
+        );
+
+        let mut cmdline = test_cmdline();
+        cmdline
+            .parse_from(&["-o=a.o", "--output=b=c.o", "-D=X"])
+            .unwrap();
+        assert_eq!(cmdline.get_value_by_name("-o"), Some(&strings(&["a.o"])));
+        assert_eq!(
+            cmdline.get_value_by_name("--output"),
+            Some(&strings(&["b=c.o"]))
+        );
+        assert_eq!(cmdline.get_value_by_name("-D"), Some(&strings(&["=X"])));
+        assert_eq!(
+            test_cmdline().parse_from(&["-o="]),
+            Err(CmdError::MissingValue(String::from("-o")))
+        );
+    }
+
+    #[test]
+    fn default_values() {
+        let mut cmdline = test_cmdline();
+        cmdline.parse_from(&["a.c"]).unwrap();
+        assert!(!cmdline.is_include("-x"));
+        assert_eq!(cmdline.get_value_by_name("-x"), Some(&strings(&["c"])));
+        assert_eq!(cmdline.get_value_by_name("-o"), None);
+
+        let mut cmdline = test_cmdline();
+        cmdline.parse_from(&["-xasm"]).unwrap();
+        assert_eq!(cmdline.get_value_by_name("-x"), Some(&strings(&["asm"])));
+
+        let mut cmdline = test_cmdline();
+        cmdline.parse_from(&["-x"]).unwrap();
+        assert_eq!(cmdline.get_value_by_name("-x"), Some(&Vec::new()));
+    }
+
+    #[test]
+    fn combined_flags() {
+        let mut cmdline = test_cmdline();
+        cmdline.parse_from(&["-cv"]).unwrap();
+        let c = cmdline.get_index("-c").unwrap();
+        let v = cmdline.get_index("-v").unwrap();
+        assert!(cmdline.args.contains_key(&c));
+        assert!(cmdline.args.contains_key(&v));
+        assert!(cmdline.others.is_empty());
+
+        let mut cmdline = test_cmdline();
+        cmdline.parse_from(&["-cvE"]).unwrap();
+        assert!(["-c", "-v", "-E"]
+            .iter()
+            .all(|flag| cmdline.is_include(flag)));
+
+        // One unknown letter makes the whole group unknown.
+        let mut cmdline = test_cmdline();
+        assert_eq!(
+            cmdline.parse_from(&["-cq"]),
+            Err(CmdError::UnknownOption(String::from("-cq")))
+        );
+        assert!(!cmdline.is_include("-c"));
     }
 }
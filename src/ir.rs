@@ -0,0 +1,1789 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::{
+    BinOpKind, BlockItem, Decl, Expr, ExternalDecl, ForInit, FunctionDef, LayoutError, SizeofArg,
+    Stmt, StorageClass, TranslationUnit, UnaryOpKind,
+};
+use crate::lex::Span;
+use crate::types::{
+    integer_promotions, string_elem, usual_arithmetic_conversions, ArraySize, CType, IntSize,
+    Signedness, StructId, TypeQuals, INT, LONG,
+};
+
+/// Identifies a basic block; it is also the block's index in
+/// `IrFunction::blocks`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockId(pub usize);
+
+/// An operand. Values are untyped machine words; the width of a memory
+/// access is recorded in its `IrAddr` instead.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum IrVal {
+    Const(i64),
+    /// A virtual register, numbered within its function.
+    Temp(usize),
+    /// The value the caller passed as the nth argument.
+    Param(usize),
+    /// The address of the function's nth stack slot.
+    Local(usize),
+    /// The address of a global object, string literal or function.
+    Global(String),
+}
+
+/// The `size` bytes of memory at the address `base`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IrAddr {
+    pub base: IrVal,
+    pub size: u64,
+}
+
+/// A three-address instruction. Where there is a destination, it comes
+/// first.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IrInstr {
+    Assign(IrVal, IrVal),
+    /// Operators work on signed 64-bit words, wrapping on overflow. Values of
+    /// narrower or unsigned types are brought into range by explicit masks.
+    BinOp(IrVal, BinOpKind, IrVal, IrVal),
+    /// Like `BinOp`, but `/`, `%`, `>>` and the ordered comparisons treat
+    /// both words as unsigned.
+    UnsignedBinOp(IrVal, BinOpKind, IrVal, IrVal),
+    UnaryOp(IrVal, UnaryOpKind, IrVal),
+    Load(IrVal, IrAddr),
+    Store(IrAddr, IrVal),
+    Jump(BlockId),
+    /// Jumps to the first block if the value is non-zero, else to the second.
+    CondJump(IrVal, BlockId, BlockId),
+    /// Calls the function at the address in the second operand.
+    Call(IrVal, IrVal, Vec<IrVal>),
+    Return(Option<IrVal>),
+    /// Starts the block; it is always a block's first instruction.
+    Label(BlockId),
+}
+
+impl IrInstr {
+    /// Whether the instruction ends a basic block.
+    pub fn is_terminator(&self) -> bool {
+        matches!(
+            self,
+            IrInstr::Jump(_) | IrInstr::CondJump(..) | IrInstr::Return(_)
+        )
+    }
+}
+
+/// A straight run of instructions: a `Label`, then instructions that do not
+/// transfer control, then exactly one terminator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub id: BlockId,
+    pub instrs: Vec<IrInstr>,
+}
+
+impl BasicBlock {
+    pub fn terminator(&self) -> Option<&IrInstr> {
+        self.instrs.last().filter(|instr| instr.is_terminator())
+    }
+}
+
+/// A lowered function. `blocks[0]` is the entry block.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct IrFunction {
+    pub name: String,
+    pub params: usize,
+    /// The size in bytes of each stack slot.
+    pub locals: Vec<u64>,
+    /// How many temporaries the function uses.
+    pub temps: usize,
+    pub blocks: Vec<BasicBlock>,
+}
+
+/// A global object. `init` holds its initial bytes; it is zero-filled
+/// where that is shorter than `size`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IrGlobal {
+    pub name: String,
+    pub size: u64,
+    pub align: u64,
+    pub init: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct IrModule {
+    pub functions: Vec<IrFunction>,
+    pub globals: Vec<IrGlobal>,
+}
+
+/// Something the IR cannot express yet, such as floating-point code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IrError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl fmt::Display for IrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Error: \"{}\" at bytes {}..{}",
+            self.message, self.span.start, self.span.end
+        )
+    }
+}
+
+type IrResult<T> = Result<T, IrError>;
+
+impl fmt::Display for IrVal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IrVal::Const(value) => write!(f, "{}", value),
+            IrVal::Temp(n) => write!(f, "t{}", n),
+            IrVal::Param(n) => write!(f, "arg{}", n),
+            IrVal::Local(n) => write!(f, "local{}", n),
+            IrVal::Global(name) => write!(f, "@{}", name),
+        }
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "b{}", self.0)
+    }
+}
+
+impl fmt::Display for IrInstr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IrInstr::Assign(dst, src) => write!(f, "{} = {}", dst, src),
+            IrInstr::BinOp(dst, op, lhs, rhs) => {
+                write!(f, "{} = {} {} {}", dst, lhs, op.symbol(), rhs)
+            }
+            IrInstr::UnsignedBinOp(dst, op, lhs, rhs) => {
+                write!(f, "{} = {} {}u {}", dst, lhs, op.symbol(), rhs)
+            }
+            IrInstr::UnaryOp(dst, op, src) => write!(f, "{} = {}{}", dst, op.symbol(), src),
+            IrInstr::Load(dst, addr) => write!(f, "{} = load{} [{}]", dst, addr.size, addr.base),
+            IrInstr::Store(addr, src) => write!(f, "store{} [{}], {}", addr.size, addr.base, src),
+            IrInstr::Jump(target) => write!(f, "jump {}", target),
+            IrInstr::CondJump(cond, then, els) => {
+                write!(f, "if {} jump {} else {}", cond, then, els)
+            }
+            IrInstr::Call(dst, callee, args) => {
+                let args: Vec<String> = args.iter().map(IrVal::to_string).collect();
+                write!(f, "{} = call {}({})", dst, callee, args.join(", "))
+            }
+            IrInstr::Return(None) => write!(f, "ret"),
+            IrInstr::Return(Some(value)) => write!(f, "ret {}", value),
+            IrInstr::Label(id) => write!(f, "{}:", id),
+        }
+    }
+}
+
+impl fmt::Display for BasicBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for instr in &self.instrs {
+            match instr {
+                IrInstr::Label(_) => writeln!(f, "{}", instr)?,
+                _ => writeln!(f, "  {}", instr)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for IrFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "function {}({} params, {} locals):",
+            self.name,
+            self.params,
+            self.locals.len()
+        )?;
+        for block in &self.blocks {
+            write!(f, "{}", block)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for IrModule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for global in &self.globals {
+            writeln!(f, "global @{}: {} bytes", global.name, global.size)?;
+        }
+        for function in &self.functions {
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
+
+fn pointer_to(ty: CType) -> CType {
+    CType::Pointer {
+        pointee: Box::new(ty),
+        quals: TypeQuals::NONE,
+    }
+}
+
+fn unsupported(span: Span, what: &str) -> IrError {
+    IrError {
+        span,
+        message: format!("{} are not supported by the IR yet", what),
+    }
+}
+
+/// How a name in a function body is reached.
+#[derive(Debug, Clone)]
+enum Binding {
+    Local(usize, CType),
+    Global(String, CType),
+}
+
+/// Lowers a type-checked translation unit to three-address code. Locals all
+/// live in stack slots, read with `Load` and written with `Store`; pointer
+/// arithmetic is scaled here, so the IR only sees byte offsets.
+pub struct IrGen<'a> {
+    unit: &'a TranslationUnit,
+    module: IrModule,
+    /// The file-scope objects and functions.
+    globals: HashMap<String, CType>,
+    function: IrFunction,
+    /// The index of the block being filled.
+    current: usize,
+    scopes: Vec<HashMap<String, Binding>>,
+    breaks: Vec<BlockId>,
+    continues: Vec<BlockId>,
+    labels: HashMap<String, BlockId>,
+    /// The block of each `case` or `default` label, by where it starts.
+    cases: HashMap<usize, BlockId>,
+    /// The statement or declaration being lowered, for errors that have no
+    /// expression of their own.
+    span: Span,
+}
+
+impl<'a> IrGen<'a> {
+    pub fn lower_translation_unit(unit: &'a TranslationUnit) -> Result<IrModule, IrError> {
+        let mut gen = IrGen {
+            unit,
+            module: IrModule::default(),
+            globals: HashMap::new(),
+            function: IrFunction::default(),
+            current: 0,
+            scopes: Vec::new(),
+            breaks: Vec::new(),
+            continues: Vec::new(),
+            labels: HashMap::new(),
+            cases: HashMap::new(),
+            span: Span { start: 0, end: 0 },
+        };
+        for decl in &unit.decls {
+            match decl {
+                ExternalDecl::Decl(decl) => gen.global(decl)?,
+                ExternalDecl::Function(def) => {
+                    gen.globals.insert(def.name.clone(), def.ty.clone());
+                    let function = gen.function(def)?;
+                    gen.module.functions.push(function);
+                }
+            }
+        }
+        Ok(gen.module)
+    }
+
+    fn constant(&self, expr: &Expr) -> IrResult<i64> {
+        self.unit.const_eval(expr).ok_or_else(|| IrError {
+            span: expr.span(),
+            message: String::from("Expression is not an integer constant"),
+        })
+    }
+
+    /// The size and alignment of `ty`.
+    fn layout(&self, ty: &CType) -> IrResult<(u64, u64)> {
+        self.unit
+            .layout(ty)
+            .map_err(|error| self.layout_error(error))
+    }
+
+    fn layout_error(&self, error: LayoutError) -> IrError {
+        match error {
+            LayoutError::Variable => unsupported(self.span, "Variable length arrays"),
+            LayoutError::BitField => unsupported(self.span, "Bit-fields"),
+            LayoutError::TooLarge => IrError {
+                span: self.span,
+                message: String::from("Type is too large"),
+            },
+            LayoutError::Incomplete => IrError {
+                span: self.span,
+                message: String::from("Type is incomplete"),
+            },
+        }
+    }
+
+    /// The size and alignment of a struct or union member of type `ty`.
+    fn member_layout(&self, ty: &CType) -> IrResult<(u64, u64)> {
+        self.unit
+            .member_layout(ty)
+            .map_err(|error| self.layout_error(error))
+    }
+
+    fn size_of(&self, ty: &CType) -> IrResult<u64> {
+        Ok(self.layout(ty)?.0)
+    }
+
+    /// The byte offset and type of the member `name`, looking inside
+    /// anonymous members too.
+    fn field(&self, id: StructId, name: &str) -> IrResult<Option<(u64, CType)>> {
+        let def = self.unit.struct_def(id);
+        let mut offset: u64 = 0;
+        for field in def.fields.iter().flatten() {
+            let (size, align) = self.member_layout(&field.ty)?;
+            if !def.is_union {
+                offset = offset.next_multiple_of(align);
+            }
+            match (&field.name, field.ty.strip()) {
+                (Some(field_name), _) if field_name == name => {
+                    return Ok(Some((offset, field.ty.clone())))
+                }
+                (None, CType::Struct(inner) | CType::Union(inner)) => {
+                    if let Some((inner_offset, ty)) = self.field(*inner, name)? {
+                        return Ok(Some((offset + inner_offset, ty)));
+                    }
+                }
+                _ => {}
+            }
+            if !def.is_union {
+                offset += size;
+            }
+        }
+        Ok(None)
+    }
+
+    /// The members of a struct or union that an initializer list fills, in
+    /// order, with their offsets. A union's list only fills its first.
+    fn members(&self, id: StructId) -> IrResult<Vec<(u64, CType)>> {
+        let def = self.unit.struct_def(id);
+        let mut members = Vec::new();
+        let mut offset: u64 = 0;
+        for field in def.fields.iter().flatten() {
+            let (size, align) = self.member_layout(&field.ty)?;
+            if def.is_union {
+                members.push((0, field.ty.clone()));
+                break;
+            }
+            offset = offset.next_multiple_of(align);
+            if field.name.is_some()
+                || matches!(field.ty.strip(), CType::Struct(_) | CType::Union(_))
+            {
+                members.push((offset, field.ty.clone()));
+            }
+            offset += size;
+        }
+        Ok(members)
+    }
+
+    /// `ty` with an incomplete array size taken from its initializer, as in
+    /// `int a[] = {1, 2}`.
+    fn complete(ty: &CType, init: Option<&Expr>) -> CType {
+        match (ty.strip(), init) {
+            (
+                CType::Array {
+                    elem,
+                    size: ArraySize::Incomplete,
+                },
+                Some(init),
+            ) => {
+                let n = match init {
+                    Expr::InitList(items, _) => items.len(),
+                    Expr::StrLit(values, ..) => values.len() + 1,
+                    _ => return ty.clone(),
+                };
+                CType::Array {
+                    elem: elem.clone(),
+                    size: ArraySize::Fixed(n as u64),
+                }
+            }
+            _ => ty.clone(),
+        }
+    }
+
+    fn global(&mut self, decl: &Decl) -> IrResult<()> {
+        self.span = decl.span;
+        if decl.storage == Some(StorageClass::Typedef) {
+            return Ok(());
+        }
+        let ty = Self::complete(&decl.ty, decl.init.as_ref());
+        self.globals.insert(decl.name.clone(), ty.clone());
+        if matches!(ty.strip(), CType::Function { .. })
+            || decl.storage == Some(StorageClass::Extern) && decl.init.is_none()
+        {
+            return Ok(());
+        }
+
+        let (size, align) = self.layout(&ty)?;
+        let mut init = Vec::new();
+        if let Some(expr) = &decl.init {
+            init = vec![0; size as usize];
+            self.global_init(&ty, expr, &mut init, 0)?;
+        }
+        // A tentative definition may be followed by the real one.
+        match self.module.globals.iter_mut().find(|g| g.name == decl.name) {
+            Some(global) if decl.init.is_some() => global.init = init,
+            Some(_) => {}
+            None => self.module.globals.push(IrGlobal {
+                name: decl.name.clone(),
+                size,
+                align,
+                init,
+            }),
+        }
+        Ok(())
+    }
+
+    /// Writes the bytes `expr` initializes an object of type `ty` to into
+    /// `bytes` at `offset`.
+    fn global_init(&self, ty: &CType, expr: &Expr, bytes: &mut [u8], offset: u64) -> IrResult<()> {
+        match (ty.strip(), expr) {
+            (CType::Array { elem, size }, Expr::StrLit(values, ..)) if elem.is_integer() => {
+                let len = match size {
+                    ArraySize::Fixed(n) => *n as usize,
+                    _ => values.len() + 1,
+                };
+                let elem_size = self.size_of(elem)? as usize;
+                for (i, value) in values.iter().take(len).enumerate() {
+                    let start = offset as usize + i * elem_size;
+                    bytes[start..start + elem_size]
+                        .copy_from_slice(&value.to_le_bytes()[..elem_size]);
+                }
+            }
+            (CType::Array { elem, size }, Expr::InitList(items, _)) => {
+                let len = match size {
+                    ArraySize::Fixed(n) => *n as usize,
+                    _ => items.len(),
+                };
+                let elem_size = self.size_of(elem)?;
+                for (i, item) in items.iter().take(len).enumerate() {
+                    self.global_init(elem, item, bytes, offset + i as u64 * elem_size)?;
+                }
+            }
+            (CType::Struct(id) | CType::Union(id), Expr::InitList(items, _)) => {
+                for ((field_offset, field_ty), item) in self.members(*id)?.into_iter().zip(items) {
+                    self.global_init(&field_ty, item, bytes, offset + field_offset)?;
+                }
+            }
+            (_, Expr::InitList(items, _)) => {
+                if let Some(item) = items.first() {
+                    self.global_init(ty, item, bytes, offset)?;
+                }
+            }
+            _ if ty.is_float() => return Err(unsupported(expr.span(), "Floating-point values")),
+            _ => {
+                let value = self.unit.const_eval(expr).ok_or_else(|| IrError {
+                    span: expr.span(),
+                    message: String::from("Initializer element is not a compile-time constant"),
+                })?;
+                let size = self.size_of(ty)? as usize;
+                let start = offset as usize;
+                bytes[start..start + size].copy_from_slice(&value.to_le_bytes()[..size]);
+            }
+        }
+        Ok(())
+    }
+
+    fn function(&mut self, def: &FunctionDef) -> IrResult<IrFunction> {
+        self.function = IrFunction {
+            name: def.name.clone(),
+            params: def.params.len(),
+            ..IrFunction::default()
+        };
+        self.labels.clear();
+        self.span = def.span;
+        self.current = self.new_block().0;
+        self.scopes = vec![HashMap::new()];
+
+        for (i, param) in def.params.iter().enumerate() {
+            let slot = self.local(&param.ty)?;
+            self.store(IrVal::Local(slot), &param.ty, IrVal::Param(i))?;
+            if let Some(name) = &param.name {
+                self.bind(name, Binding::Local(slot, param.ty.clone()));
+            }
+        }
+        self.stmt(&def.body)?;
+
+        // Falling off the end returns, with 0 from main.
+        let value = (def.name == "main").then_some(IrVal::Const(0));
+        for block in &mut self.function.blocks {
+            if block.terminator().is_none() {
+                block.instrs.push(IrInstr::Return(value.clone()));
+            }
+        }
+        Ok(std::mem::take(&mut self.function))
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        let id = BlockId(self.function.blocks.len());
+        self.function.blocks.push(BasicBlock {
+            id,
+            instrs: vec![IrInstr::Label(id)],
+        });
+        id
+    }
+
+    fn terminated(&self) -> bool {
+        self.function.blocks[self.current].terminator().is_some()
+    }
+
+    /// Appends `instr` to the current block. Code that follows a jump or
+    /// return is unreachable, but still gets a block of its own.
+    fn emit(&mut self, instr: IrInstr) {
+        if self.terminated() {
+            self.current = self.new_block().0;
+        }
+        self.function.blocks[self.current].instrs.push(instr);
+    }
+
+    /// Continues in `block`, falling through to it from the current one.
+    fn start_block(&mut self, block: BlockId) {
+        if !self.terminated() {
+            self.emit(IrInstr::Jump(block));
+        }
+        self.current = block.0;
+    }
+
+    fn temp(&mut self) -> IrVal {
+        self.function.temps += 1;
+        IrVal::Temp(self.function.temps - 1)
+    }
+
+    fn local(&mut self, ty: &CType) -> IrResult<usize> {
+        let size = self.size_of(ty)?;
+        self.function.locals.push(size);
+        Ok(self.function.locals.len() - 1)
+    }
+
+    fn bind(&mut self, name: &str, binding: Binding) {
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(String::from(name), binding);
+    }
+
+    fn lookup(&self, name: &str) -> Option<Binding> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .cloned()
+            .or_else(|| {
+                self.globals
+                    .get(name)
+                    .map(|ty| Binding::Global(String::from(name), ty.clone()))
+            })
+    }
+
+    fn label(&mut self, name: &str) -> BlockId {
+        if let Some(block) = self.labels.get(name) {
+            return *block;
+        }
+        let block = self.new_block();
+        self.labels.insert(String::from(name), block);
+        block
+    }
+
+    fn block(&mut self, items: &[BlockItem]) -> IrResult<()> {
+        self.scopes.push(HashMap::new());
+        for item in items {
+            match item {
+                BlockItem::Decl(decl) => self.decl(decl)?,
+                BlockItem::Stmt(stmt) => self.stmt(stmt)?,
+            }
+        }
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn decl(&mut self, decl: &Decl) -> IrResult<()> {
+        self.span = decl.span;
+        let ty = Self::complete(&decl.ty, decl.init.as_ref());
+        match decl.storage {
+            Some(StorageClass::Typedef) => {}
+            Some(StorageClass::Extern) => {
+                self.bind(&decl.name, Binding::Global(decl.name.clone(), ty));
+            }
+            _ if matches!(ty.strip(), CType::Function { .. }) => {
+                self.bind(&decl.name, Binding::Global(decl.name.clone(), ty));
+            }
+            Some(StorageClass::Static) => {
+                // A static local is a global under a name no C identifier can
+                // have.
+                let name = format!("{}.{}", self.function.name, decl.name);
+                let global = Decl {
+                    name: name.clone(),
+                    ty: ty.clone(),
+                    storage: None,
+                    init: decl.init.clone(),
+                    span: decl.span,
+                };
+                self.global(&global)?;
+                self.bind(&decl.name, Binding::Global(name, ty));
+            }
+            _ => {
+                let slot = self.local(&ty)?;
+                self.bind(&decl.name, Binding::Local(slot, ty.clone()));
+                if let Some(init) = &decl.init {
+                    self.init(IrVal::Local(slot), &ty, init)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Initializes the object of type `ty` at `addr`. Whatever an
+    /// initializer list leaves out is zeroed.
+    fn init(&mut self, addr: IrVal, ty: &CType, init: &Expr) -> IrResult<()> {
+        match (ty.strip(), init) {
+            (CType::Array { elem, size }, Expr::StrLit(values, ..)) if elem.is_integer() => {
+                let len = match size {
+                    ArraySize::Fixed(n) => *n,
+                    _ => values.len() as u64 + 1,
+                };
+                let elem_size = self.size_of(elem)?;
+                let values = values.iter().copied().chain(std::iter::repeat(0));
+                for (i, value) in values.take(len as usize).enumerate() {
+                    let at = self.offset(addr.clone(), i as u64 * elem_size);
+                    self.store(at, elem, IrVal::Const(value as i64))?;
+                }
+            }
+            (CType::Array { elem, size }, Expr::InitList(items, _)) => {
+                let len = match size {
+                    ArraySize::Fixed(n) => *n as usize,
+                    _ => items.len(),
+                };
+                let elem_size = self.size_of(elem)?;
+                for i in 0..len {
+                    let at = self.offset(addr.clone(), i as u64 * elem_size);
+                    match items.get(i) {
+                        Some(item) => self.init(at, elem, item)?,
+                        None => self.zero(at, elem)?,
+                    }
+                }
+            }
+            (CType::Struct(id) | CType::Union(id), Expr::InitList(items, _)) => {
+                for (i, (offset, field_ty)) in self.members(*id)?.into_iter().enumerate() {
+                    let at = self.offset(addr.clone(), offset);
+                    match items.get(i) {
+                        Some(item) => self.init(at, &field_ty, item)?,
+                        None => self.zero(at, &field_ty)?,
+                    }
+                }
+            }
+            (_, Expr::InitList(items, _)) => match items.first() {
+                Some(item) => self.init(addr, ty, item)?,
+                None => self.zero(addr, ty)?,
+            },
+            _ => {
+                let (value, _) = self.rvalue(init)?;
+                self.store(addr, ty, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn zero(&mut self, addr: IrVal, ty: &CType) -> IrResult<()> {
+        match ty.strip() {
+            CType::Array {
+                elem,
+                size: ArraySize::Fixed(n),
+            } => {
+                let elem_size = self.size_of(elem)?;
+                for i in 0..*n {
+                    let at = self.offset(addr.clone(), i * elem_size);
+                    self.zero(at, elem)?;
+                }
+                Ok(())
+            }
+            CType::Struct(id) | CType::Union(id) => {
+                for (offset, field_ty) in self.members(*id)? {
+                    let at = self.offset(addr.clone(), offset);
+                    self.zero(at, &field_ty)?;
+                }
+                Ok(())
+            }
+            _ => self.store(addr, ty, IrVal::Const(0)),
+        }
+    }
+
+    /// `addr` moved on by `offset` bytes.
+    fn offset(&mut self, addr: IrVal, offset: u64) -> IrVal {
+        if offset == 0 {
+            return addr;
+        }
+        let dst = self.temp();
+        self.emit(IrInstr::BinOp(
+            dst.clone(),
+            BinOpKind::Add,
+            addr,
+            IrVal::Const(offset as i64),
+        ));
+        dst
+    }
+
+    fn store(&mut self, addr: IrVal, ty: &CType, value: IrVal) -> IrResult<()> {
+        if matches!(ty.strip(), CType::Struct(_) | CType::Union(_)) {
+            return Err(unsupported(self.span, "Struct and union values"));
+        }
+        let size = self.size_of(ty)?;
+        self.emit(IrInstr::Store(IrAddr { base: addr, size }, value));
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> IrResult<()> {
+        self.span = stmt.span();
+        match stmt {
+            Stmt::Expr(expr, _) => {
+                self.rvalue(expr)?;
+            }
+            Stmt::Return(value, _) => {
+                let value = match value {
+                    Some(expr) => Some(self.rvalue(expr)?.0),
+                    None => None,
+                };
+                self.emit(IrInstr::Return(value));
+            }
+            Stmt::If {
+                cond, then, else_, ..
+            } => {
+                let then_block = self.new_block();
+                let else_block = else_.as_ref().map(|_| self.new_block());
+                let end = self.new_block();
+                let (cond, _) = self.rvalue(cond)?;
+                self.emit(IrInstr::CondJump(
+                    cond,
+                    then_block,
+                    else_block.unwrap_or(end),
+                ));
+                self.start_block(then_block);
+                self.stmt(then)?;
+                if let (Some(block), Some(els)) = (else_block, else_) {
+                    self.start_block(end);
+                    self.current = block.0;
+                    self.stmt(els)?;
+                }
+                self.start_block(end);
+            }
+            Stmt::While { cond, body, .. } => {
+                let test = self.new_block();
+                let body_block = self.new_block();
+                let end = self.new_block();
+                self.start_block(test);
+                let (cond, _) = self.rvalue(cond)?;
+                self.emit(IrInstr::CondJump(cond, body_block, end));
+                self.start_block(body_block);
+                self.loop_body(body, end, test)?;
+                self.start_block(test);
+                self.current = end.0;
+            }
+            Stmt::DoWhile { body, cond, .. } => {
+                let body_block = self.new_block();
+                let test = self.new_block();
+                let end = self.new_block();
+                self.start_block(body_block);
+                self.loop_body(body, end, test)?;
+                self.start_block(test);
+                let (cond, _) = self.rvalue(cond)?;
+                self.emit(IrInstr::CondJump(cond, body_block, end));
+                self.current = end.0;
+            }
+            Stmt::For {
+                init,
+                cond,
+                step,
+                body,
+                ..
+            } => {
+                self.scopes.push(HashMap::new());
+                match init {
+                    Some(ForInit::Expr(expr)) => {
+                        self.rvalue(expr)?;
+                    }
+                    Some(ForInit::Decl(decls)) => {
+                        for decl in decls {
+                            self.decl(decl)?;
+                        }
+                    }
+                    None => {}
+                }
+                let test = self.new_block();
+                let body_block = self.new_block();
+                let step_block = self.new_block();
+                let end = self.new_block();
+                self.start_block(test);
+                match cond {
+                    Some(cond) => {
+                        let (cond, _) = self.rvalue(cond)?;
+                        self.emit(IrInstr::CondJump(cond, body_block, end));
+                    }
+                    None => self.emit(IrInstr::Jump(body_block)),
+                }
+                self.start_block(body_block);
+                self.loop_body(body, end, step_block)?;
+                self.start_block(step_block);
+                if let Some(step) = step {
+                    self.rvalue(step)?;
+                }
+                self.start_block(test);
+                self.current = end.0;
+                self.scopes.pop();
+            }
+            Stmt::Break(span) => {
+                let end = *self.breaks.last().ok_or_else(|| IrError {
+                    span: *span,
+                    message: String::from("'break' statement not in loop or switch statement"),
+                })?;
+                self.emit(IrInstr::Jump(end));
+            }
+            Stmt::Continue(span) => {
+                let next = *self.continues.last().ok_or_else(|| IrError {
+                    span: *span,
+                    message: String::from("'continue' statement not in loop statement"),
+                })?;
+                self.emit(IrInstr::Jump(next));
+            }
+            Stmt::Goto(name, _) => {
+                let target = self.label(name);
+                self.emit(IrInstr::Jump(target));
+            }
+            Stmt::Label(name, stmt, _) => {
+                let block = self.label(name);
+                self.start_block(block);
+                self.stmt(stmt)?;
+            }
+            Stmt::Switch { expr, body, .. } => self.switch(expr, body)?,
+            Stmt::Case(_, stmt, span) | Stmt::Default(stmt, span) => {
+                let block = *self.cases.get(&span.start).ok_or_else(|| IrError {
+                    span: *span,
+                    message: String::from("Case label not within a switch statement"),
+                })?;
+                self.start_block(block);
+                self.stmt(stmt)?;
+            }
+            Stmt::Block(items, _) => self.block(items)?,
+            Stmt::Decl(decl) => self.decl(decl)?,
+            Stmt::Empty(_) => {}
+        }
+        Ok(())
+    }
+
+    fn loop_body(&mut self, body: &Stmt, end: BlockId, next: BlockId) -> IrResult<()> {
+        self.breaks.push(end);
+        self.continues.push(next);
+        let result = self.stmt(body);
+        self.breaks.pop();
+        self.continues.pop();
+        result
+    }
+
+    /// Lowers a switch to a chain of comparisons, one per case, that ends by
+    /// jumping to the default label or past the switch.
+    fn switch(&mut self, expr: &Expr, body: &Stmt) -> IrResult<()> {
+        let (value, _) = self.rvalue(expr)?;
+        let end = self.new_block();
+        let mut cases = Vec::new();
+        let mut default = None;
+        collect_cases(body, &mut cases, &mut default);
+
+        for (expr, start) in cases {
+            let block = self.new_block();
+            self.cases.insert(start, block);
+            let case = self.constant(expr)?;
+            let equal = self.temp();
+            self.emit(IrInstr::BinOp(
+                equal.clone(),
+                BinOpKind::Eq,
+                value.clone(),
+                IrVal::Const(case),
+            ));
+            let next = self.new_block();
+            self.emit(IrInstr::CondJump(equal, block, next));
+            self.current = next.0;
+        }
+        match default {
+            Some(start) => {
+                let block = self.new_block();
+                self.cases.insert(start, block);
+                self.emit(IrInstr::Jump(block));
+            }
+            None => self.emit(IrInstr::Jump(end)),
+        }
+
+        self.breaks.push(end);
+        let result = self.stmt(body);
+        self.breaks.pop();
+        result?;
+        self.start_block(end);
+        Ok(())
+    }
+
+    /// Lowers `expr` for its value, along with the type of that value.
+    fn rvalue(&mut self, expr: &Expr) -> IrResult<(IrVal, CType)> {
+        match expr {
+            Expr::IntLit(value, ty, _) => Ok((IrVal::Const(*value as i64), ty.clone())),
+            Expr::FloatLit(_, span) => Err(unsupported(*span, "Floating-point values")),
+            Expr::Enumerator(_, value, _) => Ok((IrVal::Const(*value), INT)),
+            Expr::StrLit(..) | Expr::Ident(..) | Expr::Index { .. } | Expr::Member { .. } => {
+                self.load_lvalue(expr)
+            }
+            Expr::BinOp { op, lhs, rhs, span } => self.binop(*op, lhs, rhs, *span),
+            Expr::UnaryOp { op, expr, span } => self.unary(*op, expr, *span),
+            Expr::Call { callee, args, span } => {
+                let (callee, ty) = self.rvalue(callee)?;
+                let ret = match ty.pointee().map(CType::strip) {
+                    Some(CType::Function { ret, .. }) => (**ret).clone(),
+                    _ => INT,
+                };
+                if ret.is_float() || matches!(ret.strip(), CType::Struct(_) | CType::Union(_)) {
+                    return Err(unsupported(*span, "Floating-point and struct returns"));
+                }
+                let mut values = Vec::new();
+                for arg in args {
+                    values.push(self.rvalue(arg)?.0);
+                }
+                let dst = self.temp();
+                self.emit(IrInstr::Call(dst.clone(), callee, values));
+                Ok((dst, ret))
+            }
+            Expr::Cast { ty, expr, span } => {
+                if ty.is_float() {
+                    return Err(unsupported(*span, "Floating-point values"));
+                }
+                let (value, from) = self.rvalue(expr)?;
+                Ok((self.convert(value, &from, ty), ty.strip().clone()))
+            }
+            Expr::Ternary {
+                cond, then, els, ..
+            } => {
+                let dst = self.temp();
+                let then_block = self.new_block();
+                let else_block = self.new_block();
+                let end = self.new_block();
+                let (cond, _) = self.rvalue(cond)?;
+                self.emit(IrInstr::CondJump(cond, then_block, else_block));
+                self.current = then_block.0;
+                let (value, ty) = self.rvalue(then)?;
+                self.emit(IrInstr::Assign(dst.clone(), value));
+                self.start_block(end);
+                self.current = else_block.0;
+                let (value, _) = self.rvalue(els)?;
+                self.emit(IrInstr::Assign(dst.clone(), value));
+                self.start_block(end);
+                Ok((dst, ty))
+            }
+            Expr::Sizeof(arg, _) => {
+                let ty = match arg {
+                    SizeofArg::Type(ty) => ty.clone(),
+                    SizeofArg::Expr(expr) => self.type_of(expr)?,
+                };
+                let size = self.size_of(&ty)?;
+                Ok((
+                    IrVal::Const(size as i64),
+                    CType::Int {
+                        size: IntSize::Long,
+                        sign: Signedness::Unsigned,
+                    },
+                ))
+            }
+            Expr::InitList(_, span) => Err(IrError {
+                span: *span,
+                message: String::from("Initializer list outside an initializer"),
+            }),
+        }
+    }
+
+    /// The type of `expr`, without keeping the code that computes it, since
+    /// the operand of `sizeof` is not evaluated.
+    fn type_of(&mut self, expr: &Expr) -> IrResult<CType> {
+        let function = self.function.clone();
+        let current = self.current;
+        let ty = match expr {
+            Expr::Ident(..) | Expr::StrLit(..) | Expr::Index { .. } | Expr::Member { .. } => {
+                self.lvalue(expr).map(|(_, ty)| ty)
+            }
+            Expr::UnaryOp {
+                op: UnaryOpKind::Deref,
+                ..
+            } => self.lvalue(expr).map(|(_, ty)| ty),
+            _ => self.rvalue(expr).map(|(_, ty)| ty),
+        };
+        self.function = function;
+        self.current = current;
+        ty
+    }
+
+    fn load_lvalue(&mut self, expr: &Expr) -> IrResult<(IrVal, CType)> {
+        let (addr, ty) = self.lvalue(expr)?;
+        self.load(addr, &ty, expr.span())
+    }
+
+    /// Reads the object of type `ty` at `addr`. Arrays and functions are
+    /// not read but decay to their address.
+    fn load(&mut self, addr: IrVal, ty: &CType, span: Span) -> IrResult<(IrVal, CType)> {
+        match ty.strip() {
+            CType::Array { elem, .. } => Ok((addr, pointer_to((**elem).clone()))),
+            function @ CType::Function { .. } => Ok((addr, pointer_to(function.clone()))),
+            CType::Struct(_) | CType::Union(_) => Err(unsupported(span, "Struct and union values")),
+            ty if ty.is_float() => Err(unsupported(span, "Floating-point values")),
+            ty => {
+                let dst = self.temp();
+                let size = self.size_of(ty)?;
+                self.emit(IrInstr::Load(dst.clone(), IrAddr { base: addr, size }));
+                Ok((dst, ty.clone()))
+            }
+        }
+    }
+
+    /// Lowers `expr` for the address of the object it designates, along
+    /// with the object's type.
+    fn lvalue(&mut self, expr: &Expr) -> IrResult<(IrVal, CType)> {
+        match expr {
+            Expr::Ident(name, span) => match self.lookup(name) {
+                Some(Binding::Local(slot, ty)) => Ok((IrVal::Local(slot), ty)),
+                Some(Binding::Global(name, ty)) => Ok((IrVal::Global(name), ty)),
+                None => Err(IrError {
+                    span: *span,
+                    message: String::from("Use of undeclared identifier"),
+                }),
+            },
+            Expr::StrLit(values, encoding, _) => {
+                let name = format!(".str.{}", self.module.globals.len());
+                let elem = string_elem(*encoding);
+                let elem_size = self.size_of(&elem)?;
+                let init: Vec<u8> = values
+                    .iter()
+                    .chain([0].iter())
+                    .flat_map(|value| value.to_le_bytes()[..elem_size as usize].to_vec())
+                    .collect();
+                self.module.globals.push(IrGlobal {
+                    name: name.clone(),
+                    size: init.len() as u64,
+                    align: elem_size,
+                    init,
+                });
+                let ty = CType::Array {
+                    elem: Box::new(elem),
+                    size: ArraySize::Fixed(values.len() as u64 + 1),
+                };
+                Ok((IrVal::Global(name), ty))
+            }
+            Expr::UnaryOp {
+                op: UnaryOpKind::Deref,
+                expr,
+                span,
+            } => {
+                let (addr, ty) = self.rvalue(expr)?;
+                let ty = ty.pointee().cloned().ok_or_else(|| IrError {
+                    span: *span,
+                    message: String::from("Indirection requires pointer operand"),
+                })?;
+                Ok((addr, ty))
+            }
+            Expr::Index { base, idx, span } => {
+                let (mut base, mut base_ty) = self.rvalue(base)?;
+                let (mut idx, mut idx_ty) = self.rvalue(idx)?;
+                if base_ty.pointee().is_none() {
+                    std::mem::swap(&mut base, &mut idx);
+                    std::mem::swap(&mut base_ty, &mut idx_ty);
+                }
+                let elem = base_ty.pointee().cloned().ok_or_else(|| IrError {
+                    span: *span,
+                    message: String::from("Subscripted value is not an array or pointer"),
+                })?;
+                let offset = self.scale(idx, &elem)?;
+                let addr = self.temp();
+                self.emit(IrInstr::BinOp(addr.clone(), BinOpKind::Add, base, offset));
+                Ok((addr, elem))
+            }
+            Expr::Member {
+                obj,
+                field,
+                arrow,
+                span,
+            } => {
+                let (base, ty) = if *arrow {
+                    let (base, ty) = self.rvalue(obj)?;
+                    (base, ty.pointee().cloned().unwrap_or(CType::Void))
+                } else {
+                    self.lvalue(obj)?
+                };
+                let found = match ty.strip() {
+                    CType::Struct(id) | CType::Union(id) => self.field(*id, field)?,
+                    _ => None,
+                };
+                let (offset, ty) = found.ok_or_else(|| IrError {
+                    span: *span,
+                    message: format!("No member named '{}'", field),
+                })?;
+                Ok((self.offset(base, offset), ty))
+            }
+            _ => Err(IrError {
+                span: expr.span(),
+                message: String::from("Expression is not assignable"),
+            }),
+        }
+    }
+
+    /// `index` times the size of `elem`, the byte offset of an array
+    /// element.
+    fn scale(&mut self, index: IrVal, elem: &CType) -> IrResult<IrVal> {
+        let size = self.size_of(elem)? as i64;
+        if size == 1 {
+            return Ok(index);
+        }
+        let dst = self.temp();
+        self.emit(IrInstr::BinOp(
+            dst.clone(),
+            BinOpKind::Mul,
+            index,
+            IrVal::Const(size),
+        ));
+        Ok(dst)
+    }
+
+    fn binop(
+        &mut self,
+        op: BinOpKind,
+        lhs: &Expr,
+        rhs: &Expr,
+        span: Span,
+    ) -> IrResult<(IrVal, CType)> {
+        match op {
+            BinOpKind::Comma => {
+                self.rvalue(lhs)?;
+                self.rvalue(rhs)
+            }
+            BinOpKind::LogAnd | BinOpKind::LogOr => self.logical(op, lhs, rhs),
+            _ if op == BinOpKind::Assign || op.compound_op().is_some() => {
+                self.assignment(op, lhs, rhs, span)
+            }
+            _ => {
+                let lhs = self.rvalue(lhs)?;
+                let rhs = self.rvalue(rhs)?;
+                self.arith(op, lhs, rhs, span)
+            }
+        }
+    }
+
+    /// `&&` and `||`, which only evaluate their right operand when the left
+    /// one does not settle the result.
+    fn logical(&mut self, op: BinOpKind, lhs: &Expr, rhs: &Expr) -> IrResult<(IrVal, CType)> {
+        let dst = self.temp();
+        let rhs_block = self.new_block();
+        let short = self.new_block();
+        let end = self.new_block();
+        let (lhs, _) = self.rvalue(lhs)?;
+        if op == BinOpKind::LogAnd {
+            self.emit(IrInstr::CondJump(lhs, rhs_block, short));
+        } else {
+            self.emit(IrInstr::CondJump(lhs, short, rhs_block));
+        }
+        self.current = short.0;
+        let settled = (op == BinOpKind::LogOr) as i64;
+        self.emit(IrInstr::Assign(dst.clone(), IrVal::Const(settled)));
+        self.start_block(end);
+        self.current = rhs_block.0;
+        let (rhs, _) = self.rvalue(rhs)?;
+        self.emit(IrInstr::BinOp(
+            dst.clone(),
+            BinOpKind::Ne,
+            rhs,
+            IrVal::Const(0),
+        ));
+        self.start_block(end);
+        Ok((dst, INT))
+    }
+
+    /// An arithmetic, bitwise or comparison operator applied to two values.
+    fn arith(
+        &mut self,
+        op: BinOpKind,
+        (lhs, lhs_ty): (IrVal, CType),
+        (rhs, rhs_ty): (IrVal, CType),
+        span: Span,
+    ) -> IrResult<(IrVal, CType)> {
+        if lhs_ty.is_float() || rhs_ty.is_float() {
+            return Err(unsupported(span, "Floating-point values"));
+        }
+        if lhs_ty.is_integer() && rhs_ty.is_integer() {
+            return Ok(self.integer_arith(op, (lhs, lhs_ty), (rhs, rhs_ty)));
+        }
+        let dst = self.temp();
+        let lhs_elem = lhs_ty.pointee().cloned();
+        let rhs_elem = rhs_ty.pointee().cloned();
+        let ty = match (op, lhs_elem, rhs_elem) {
+            (BinOpKind::Sub, Some(elem), Some(_)) => {
+                // The difference of two pointers counts elements.
+                let bytes = self.temp();
+                self.emit(IrInstr::BinOp(bytes.clone(), BinOpKind::Sub, lhs, rhs));
+                let size = self.size_of(&elem)? as i64;
+                self.emit(IrInstr::BinOp(
+                    dst.clone(),
+                    BinOpKind::Div,
+                    bytes,
+                    IrVal::Const(size),
+                ));
+                return Ok((dst, LONG));
+            }
+            (BinOpKind::Add | BinOpKind::Sub, Some(elem), None) => {
+                let offset = self.scale(rhs, &elem)?;
+                self.emit(IrInstr::BinOp(dst.clone(), op, lhs, offset));
+                return Ok((dst, lhs_ty));
+            }
+            (BinOpKind::Add, None, Some(elem)) => {
+                let offset = self.scale(lhs, &elem)?;
+                self.emit(IrInstr::BinOp(dst.clone(), op, rhs, offset));
+                return Ok((dst, rhs_ty));
+            }
+            (
+                BinOpKind::Lt
+                | BinOpKind::Gt
+                | BinOpKind::Le
+                | BinOpKind::Ge
+                | BinOpKind::Eq
+                | BinOpKind::Ne,
+                _,
+                _,
+            ) => INT,
+            _ => lhs_ty,
+        };
+        self.emit(IrInstr::BinOp(dst.clone(), op, lhs, rhs));
+        Ok((dst, ty))
+    }
+
+    /// An operator applied to two integers, which are first converted to
+    /// the type the operation is done in.
+    fn integer_arith(
+        &mut self,
+        op: BinOpKind,
+        (lhs, lhs_ty): (IrVal, CType),
+        (rhs, rhs_ty): (IrVal, CType),
+    ) -> (IrVal, CType) {
+        let (lhs_common, rhs_common) = match op {
+            BinOpKind::Shl | BinOpKind::Shr => {
+                (integer_promotions(&lhs_ty), integer_promotions(&rhs_ty))
+            }
+            _ => {
+                let common = usual_arithmetic_conversions(&lhs_ty, &rhs_ty);
+                (common.clone(), common)
+            }
+        };
+        let lhs = self.convert(lhs, &lhs_ty, &lhs_common);
+        let rhs = self.convert(rhs, &rhs_ty, &rhs_common);
+        let dst = self.temp();
+        // Unsigned values are kept zero-extended, so one unsigned operation
+        // on words serves every width.
+        let unsigned = lhs_common.int_repr().is_some_and(|(_, signed)| !signed);
+        let unsigned_op = matches!(
+            op,
+            BinOpKind::Div
+                | BinOpKind::Mod
+                | BinOpKind::Shr
+                | BinOpKind::Lt
+                | BinOpKind::Gt
+                | BinOpKind::Le
+                | BinOpKind::Ge
+        );
+        if unsigned && unsigned_op {
+            self.emit(IrInstr::UnsignedBinOp(dst.clone(), op, lhs, rhs));
+        } else {
+            self.emit(IrInstr::BinOp(dst.clone(), op, lhs, rhs));
+        }
+        match op {
+            BinOpKind::Lt
+            | BinOpKind::Gt
+            | BinOpKind::Le
+            | BinOpKind::Ge
+            | BinOpKind::Eq
+            | BinOpKind::Ne => (dst, INT),
+            _ => (self.wrap(dst, &lhs_common), lhs_common),
+        }
+    }
+
+    /// Converts `value` from the type `from` to the integer or pointer type
+    /// `to`. Narrowing and changes of signedness take the value modulo the
+    /// new width, then sign- or zero-extend it back to a word.
+    fn convert(&mut self, value: IrVal, from: &CType, to: &CType) -> IrVal {
+        let (Some((from_bits, from_signed)), Some((bits, signed))) =
+            (from.int_repr(), to.int_repr())
+        else {
+            return value;
+        };
+        let widening = from_bits < bits && (from_signed == signed || !from_signed);
+        if bits == 64 || widening || (from_bits, from_signed) == (bits, signed) {
+            return value;
+        }
+        let dst = self.temp();
+        if *to.strip() == CType::Bool {
+            self.emit(IrInstr::BinOp(
+                dst.clone(),
+                BinOpKind::Ne,
+                value,
+                IrVal::Const(0),
+            ));
+            return dst;
+        }
+        self.emit(IrInstr::BinOp(
+            dst.clone(),
+            BinOpKind::BitAnd,
+            value,
+            IrVal::Const((1 << bits) - 1),
+        ));
+        if !signed {
+            return dst;
+        }
+        // Flipping the sign bit and subtracting it back extends it.
+        let sign = IrVal::Const(1 << (bits - 1));
+        let flipped = self.temp();
+        self.emit(IrInstr::BinOp(
+            flipped.clone(),
+            BinOpKind::BitXor,
+            dst,
+            sign.clone(),
+        ));
+        let extended = self.temp();
+        self.emit(IrInstr::BinOp(
+            extended.clone(),
+            BinOpKind::Sub,
+            flipped,
+            sign,
+        ));
+        extended
+    }
+
+    /// Brings the word-sized result of an operation back into the range of
+    /// its type `ty`. Overflowing a signed `int` or wider is undefined, so
+    /// those are left as they are.
+    fn wrap(&mut self, value: IrVal, ty: &CType) -> IrVal {
+        match ty.int_repr() {
+            Some((bits, true)) if bits >= 32 => value,
+            _ => self.convert(value, &LONG, ty),
+        }
+    }
+
+    fn assignment(
+        &mut self,
+        op: BinOpKind,
+        lhs: &Expr,
+        rhs: &Expr,
+        span: Span,
+    ) -> IrResult<(IrVal, CType)> {
+        let (addr, ty) = self.lvalue(lhs)?;
+        let (value, value_ty) = match op.compound_op() {
+            None => self.rvalue(rhs)?,
+            Some(op) => {
+                let old = self.load(addr.clone(), &ty, span)?;
+                let rhs = self.rvalue(rhs)?;
+                self.arith(op, old, rhs, span)?
+            }
+        };
+        let value = self.convert(value, &value_ty, &ty);
+        self.store(addr, &ty, value.clone())?;
+        Ok((value, ty.strip().clone()))
+    }
+
+    fn unary(&mut self, op: UnaryOpKind, expr: &Expr, span: Span) -> IrResult<(IrVal, CType)> {
+        match op {
+            UnaryOpKind::Plus => {
+                let (value, ty) = self.rvalue(expr)?;
+                Ok((value, integer_promotions(&ty)))
+            }
+            UnaryOpKind::Neg | UnaryOpKind::BitNot | UnaryOpKind::LogNot => {
+                let (value, ty) = self.rvalue(expr)?;
+                if ty.is_float() {
+                    return Err(unsupported(span, "Floating-point values"));
+                }
+                let dst = self.temp();
+                self.emit(IrInstr::UnaryOp(dst.clone(), op, value));
+                if op == UnaryOpKind::LogNot {
+                    return Ok((dst, INT));
+                }
+                let ty = integer_promotions(&ty);
+                Ok((self.wrap(dst, &ty), ty))
+            }
+            UnaryOpKind::Deref => {
+                let (addr, ty) = self.lvalue(&Expr::UnaryOp {
+                    op,
+                    expr: Box::new(expr.clone()),
+                    span,
+                })?;
+                self.load(addr, &ty, span)
+            }
+            UnaryOpKind::AddrOf => {
+                let (addr, ty) = self.lvalue(expr)?;
+                Ok((addr, pointer_to(ty)))
+            }
+            UnaryOpKind::PreInc
+            | UnaryOpKind::PreDec
+            | UnaryOpKind::PostInc
+            | UnaryOpKind::PostDec => {
+                let (addr, ty) = self.lvalue(expr)?;
+                let (old, value_ty) = self.load(addr.clone(), &ty, span)?;
+                let step = match value_ty.pointee() {
+                    Some(elem) => self.size_of(elem)? as i64,
+                    None => 1,
+                };
+                let kind = match op {
+                    UnaryOpKind::PreInc | UnaryOpKind::PostInc => BinOpKind::Add,
+                    _ => BinOpKind::Sub,
+                };
+                let new = self.temp();
+                self.emit(IrInstr::BinOp(
+                    new.clone(),
+                    kind,
+                    old.clone(),
+                    IrVal::Const(step),
+                ));
+                let new = self.wrap(new, &value_ty);
+                self.store(addr, &ty, new.clone())?;
+                match op {
+                    UnaryOpKind::PreInc | UnaryOpKind::PreDec => Ok((new, value_ty)),
+                    _ => Ok((old, value_ty)),
+                }
+            }
+        }
+    }
+}
+
+/// The `case` labels of a switch body, with where each starts, and where
+/// its `default` label starts; labels of nested switches are left out.
+fn collect_cases<'s>(
+    stmt: &'s Stmt,
+    cases: &mut Vec<(&'s Expr, usize)>,
+    default: &mut Option<usize>,
+) {
+    match stmt {
+        Stmt::Case(value, stmt, span) => {
+            cases.push((value, span.start));
+            collect_cases(stmt, cases, default);
+        }
+        Stmt::Default(stmt, span) => {
+            *default = Some(span.start);
+            collect_cases(stmt, cases, default);
+        }
+        Stmt::If { then, else_, .. } => {
+            collect_cases(then, cases, default);
+            if let Some(els) = else_ {
+                collect_cases(els, cases, default);
+            }
+        }
+        Stmt::While { body, .. } | Stmt::DoWhile { body, .. } | Stmt::For { body, .. } => {
+            collect_cases(body, cases, default)
+        }
+        Stmt::Label(_, stmt, _) => collect_cases(stmt, cases, default),
+        Stmt::Block(items, _) => {
+            for item in items {
+                if let BlockItem::Stmt(stmt) = item {
+                    collect_cases(stmt, cases, default);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex::Lex;
+    use crate::parser::Parser;
+
+    fn lower(src: &str) -> IrModule {
+        let mut lex = Lex::from_source("test.c", src);
+        lex.parse().unwrap();
+        let unit = Parser::new(lex.token_stream()).parse().unwrap();
+        IrGen::lower_translation_unit(&unit).unwrap()
+    }
+
+    #[test]
+    fn straight_line_code() {
+        let module = lower("int add(int a, int b) { return a + b * 2; }");
+        assert_eq!(
+            module.to_string(),
+            "function add(2 params, 2 locals):\n\
+             b0:\n  \
+               store4 [local0], arg0\n  \
+               store4 [local1], arg1\n  \
+               t0 = load4 [local0]\n  \
+               t1 = load4 [local1]\n  \
+               t2 = t1 * 2\n  \
+               t3 = t0 + t2\n  \
+               ret t3\n"
+        );
+    }
+
+    #[test]
+    fn memory() {
+        let module = lower(
+            "struct s { char c; int n; };\n\
+             int g = 3; char name[] = \"ab\";\n\
+             int f(struct s *p, int *q) { p->n = q[2]; return g; }",
+        );
+        assert_eq!(
+            module.globals,
+            vec![
+                IrGlobal {
+                    name: String::from("g"),
+                    size: 4,
+                    align: 4,
+                    init: vec![3, 0, 0, 0],
+                },
+                IrGlobal {
+                    name: String::from("name"),
+                    size: 3,
+                    align: 1,
+                    init: vec![b'a', b'b', 0],
+                },
+            ]
+        );
+        assert_eq!(
+            module.functions[0].to_string(),
+            "function f(2 params, 2 locals):\n\
+             b0:\n  \
+               store8 [local0], arg0\n  \
+               store8 [local1], arg1\n  \
+               t0 = load8 [local0]\n  \
+               t1 = t0 + 4\n  \
+               t2 = load8 [local1]\n  \
+               t3 = 2 * 4\n  \
+               t4 = t2 + t3\n  \
+               t5 = load4 [t4]\n  \
+               store4 [t1], t5\n  \
+               t6 = load4 [@g]\n  \
+               ret t6\n"
+        );
+    }
+
+    #[test]
+    fn control_flow() {
+        let module = lower(
+            "int f(int n) {\n\
+                 int total = 0;\n\
+                 for (int i = 0; i < n && i != 7; i++) {\n\
+                     if (i % 2) continue;\n\
+                     switch (i) { case 1: total++; break; case 4: goto out; default: total += i; }\n\
+                 }\n\
+                 while (n) { n--; }\n\
+                 do total--; while (total > 100);\n\
+             out:\n\
+                 return total ? total : -1;\n\
+             }\n\
+             int main(void) { f(3); }",
+        );
+        for function in &module.functions {
+            for (i, block) in function.blocks.iter().enumerate() {
+                assert_eq!(block.id, BlockId(i));
+                assert_eq!(block.instrs.first(), Some(&IrInstr::Label(block.id)));
+                assert!(block.terminator().is_some(), "{}", function);
+                let terminators = block.instrs.iter().filter(|i| i.is_terminator());
+                assert_eq!(terminators.count(), 1, "{}", function);
+            }
+        }
+
+        // main falls off its end, which returns 0.
+        let main = &module.functions[1];
+        assert_eq!(
+            main.blocks[0].instrs,
+            vec![
+                IrInstr::Label(BlockId(0)),
+                IrInstr::Call(
+                    IrVal::Temp(0),
+                    IrVal::Global(String::from("f")),
+                    vec![IrVal::Const(3)]
+                ),
+                IrInstr::Return(Some(IrVal::Const(0))),
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported_code() {
+        let mut lex = Lex::from_source("test.c", "double f(double x) { return x * 2.0; }");
+        lex.parse().unwrap();
+        let unit = Parser::new(lex.token_stream()).parse().unwrap();
+        let error = IrGen::lower_translation_unit(&unit).unwrap_err();
+        assert_eq!(
+            error.message,
+            "Floating-point values are not supported by the IR yet"
+        );
+    }
+
+    #[test]
+    fn unsigned_arithmetic() {
+        let module = lower("int f(unsigned x, int y) { return x / 2 % y >> 1 < y / 2; }");
+        assert_eq!(
+            module.to_string(),
+            "function f(2 params, 2 locals):\n\
+             b0:\n  \
+               store4 [local0], arg0\n  \
+               store4 [local1], arg1\n  \
+               t0 = load4 [local0]\n  \
+               t1 = 2 & 4294967295\n  \
+               t2 = t0 /u t1\n  \
+               t3 = t2 & 4294967295\n  \
+               t4 = load4 [local1]\n  \
+               t5 = t4 & 4294967295\n  \
+               t6 = t3 %u t5\n  \
+               t7 = t6 & 4294967295\n  \
+               t8 = t7 >>u 1\n  \
+               t9 = t8 & 4294967295\n  \
+               t10 = load4 [local1]\n  \
+               t11 = t10 / 2\n  \
+               t12 = t11 & 4294967295\n  \
+               t13 = t9 <u t12\n  \
+               ret t13\n"
+        );
+
+        let module = lower(
+            "long g(void) { int a[4]; long n = 0;\n\
+             for (unsigned i = 0; i < sizeof a / sizeof a[0]; i++) n += i; return n; }",
+        );
+        assert_eq!(
+            module.functions[0].blocks[1].instrs[2..4],
+            [
+                IrInstr::UnsignedBinOp(
+                    IrVal::Temp(1),
+                    BinOpKind::Div,
+                    IrVal::Const(16),
+                    IrVal::Const(4)
+                ),
+                IrInstr::UnsignedBinOp(
+                    IrVal::Temp(2),
+                    BinOpKind::Lt,
+                    IrVal::Temp(0),
+                    IrVal::Temp(1)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn array_bounds() {
+        // Initializers past the end of the array are dropped.
+        let module = lower(
+            "int a[2] = {1, 2, 3};
+int b[3][2] = {{1, 2}, {3, 4}, {5, 6}, {7, 8}};",
+        );
+        assert_eq!(module.globals[0].init, [1, 0, 0, 0, 2, 0, 0, 0]);
+        let init: Vec<u8> = module.globals[1].init.chunks(4).map(|n| n[0]).collect();
+        assert_eq!(init, [1, 2, 3, 4, 5, 6]);
+
+        // Constant initializers follow the usual arithmetic conversions.
+        let module = lower("int g = -1 / 2u; long h = -1 < 0u; int a[sizeof(int) - 5 > 0];");
+        assert_eq!(module.globals[0].init, [0xff, 0xff, 0xff, 0x7f]);
+        assert_eq!(module.globals[1].init, [0; 8]);
+        assert_eq!(module.globals[2].size, 4);
+
+        let mut lex = Lex::from_source("test.c", "int a[0x7fffffffffffffff];");
+        lex.parse().unwrap();
+        let unit = Parser::new(lex.token_stream()).parse().unwrap();
+        let error = IrGen::lower_translation_unit(&unit).unwrap_err();
+        assert_eq!(error.message, "Type is too large");
+    }
+
+    #[test]
+    fn enumerators() {
+        let module = lower(
+            "enum { N = 3 };\n\
+             void f(void) { enum { N = 5 }; }\n\
+             int g(int N) { return N; }\n\
+             int h(void) { switch (N) { case N: return N; } return 0; }",
+        );
+        let returns: Vec<String> = module
+            .functions
+            .iter()
+            .flat_map(|function| &function.blocks)
+            .filter_map(|block| block.terminator())
+            .filter(|instr| matches!(instr, IrInstr::Return(Some(_))))
+            .map(IrInstr::to_string)
+            .collect();
+        assert_eq!(returns, ["ret t0", "ret 0", "ret 3"]);
+    }
+
+    #[test]
+    fn string_literals() {
+        let module = lower(
+            "char s[] = \"\\xff\" \"\\303\\251\";\n\
+             int w[] = L\"\\x100\" \"\u{e9}\";\n\
+             unsigned short u[] = u\"\\U0001F600\";\n\
+             int *f(void) { return L\"\\xffffffff\"; }",
+        );
+        let inits: Vec<(&str, u64, &[u8])> = module
+            .globals
+            .iter()
+            .map(|global| (global.name.as_str(), global.size, &global.init[..]))
+            .collect();
+        assert_eq!(
+            inits,
+            vec![
+                ("s", 4, &[0xff, 0xc3, 0xa9, 0][..]),
+                ("w", 12, &[0, 1, 0, 0, 0xe9, 0, 0, 0, 0, 0, 0, 0][..]),
+                ("u", 6, &[0x3d, 0xd8, 0x00, 0xde, 0, 0][..]),
+                (".str.3", 8, &[0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0][..]),
+            ]
+        );
+    }
+
+    #[test]
+    fn incomplete_types_and_bit_fields() {
+        let lower_error = |src: &str| {
+            let mut lex = Lex::from_source("test.c", src);
+            lex.parse().unwrap();
+            let unit = Parser::new(lex.token_stream()).parse().unwrap();
+            IrGen::lower_translation_unit(&unit).unwrap_err().message
+        };
+        assert_eq!(lower_error("struct S; struct S g;"), "Type is incomplete");
+        assert_eq!(
+            lower_error("struct S; long f(void) { return sizeof(struct S); }"),
+            "Type is incomplete"
+        );
+        assert_eq!(
+            lower_error("int f(void) { int a[]; return 0; }"),
+            "Type is incomplete"
+        );
+        assert_eq!(
+            lower_error("struct B { int a : 3, b : 5; } b;"),
+            "Bit-fields are not supported by the IR yet"
+        );
+
+        // A flexible array member takes no space.
+        let module = lower("struct F { int n; char d[]; } g;");
+        assert_eq!((module.globals[0].size, module.globals[0].align), (4, 4));
+    }
+
+    #[test]
+    fn conversions() {
+        let module = lower(
+            "int f(int x) { return (char)300 + (unsigned char)x; }\n\
+             unsigned g(unsigned u) { return u + -1; }",
+        );
+        assert_eq!(
+            module.to_string(),
+            "function f(1 params, 1 locals):\n\
+             b0:\n  \
+               store4 [local0], arg0\n  \
+               t0 = 300 & 255\n  \
+               t1 = t0 ^ 128\n  \
+               t2 = t1 - 128\n  \
+               t3 = load4 [local0]\n  \
+               t4 = t3 & 255\n  \
+               t5 = t2 + t4\n  \
+               ret t5\n\
+             function g(1 params, 1 locals):\n\
+             b0:\n  \
+               store4 [local0], arg0\n  \
+               t0 = load4 [local0]\n  \
+               t1 = -1\n  \
+               t2 = t1 & 4294967295\n  \
+               t3 = t0 + t2\n  \
+               t4 = t3 & 4294967295\n  \
+               ret t4\n"
+        );
+    }
+}
@@ -1,66 +1,953 @@
-use std::fs::File;
-use std::io::prelude::*;
-use std::path::Path;
+use std::fmt;
+use std::fs;
+use std::io;
 
-#[derive(Debug)]
+/// C99 keywords. Every variant is the keyword in CamelCase behind a `K`
+/// prefix, with the leading underscore dropped (`_Bool` is `KBool`).
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum KeyWordType {
-    KVoid,
+    KAuto,
+    KBool,
+    KBreak,
+    KCase,
     KChar,
-    KInt,
+    KComplex,
+    KConst,
+    KContinue,
+    KDefault,
+    KDo,
+    KDouble,
+    KElse,
+    KEnum,
+    KExtern,
     KFloat,
-    Kdouble,
+    KFor,
+    KGoto,
+    KIf,
+    KImaginary,
+    KInline,
+    KInt,
+    KLong,
+    KRegister,
+    KRestrict,
+    KReturn,
+    KShort,
+    KSigned,
+    KSizeof,
+    KStatic,
+    KStruct,
+    KSwitch,
+    KTypedef,
+    KUnion,
+    KUnsigned,
+    KVoid,
+    KVolatile,
+    KWhile,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum OperatorType {
+    OpAdd,
+    OpSub,
+    OpMul,
+    OpDiv,
+    OpMod,
+    OpInc,
+    OpDec,
     OpEq,
+    OpNe,
+    OpLt,
+    OpGt,
+    OpLe,
+    OpGe,
+    OpLogAnd,
+    OpLogOr,
+    OpLogNot,
+    OpBitAnd,
+    OpBitOr,
+    OpBitXor,
+    OpBitNot,
+    OpShl,
+    OpShr,
     OpAssign,
+    OpAddAssign,
+    OpSubAssign,
+    OpMulAssign,
+    OpDivAssign,
+    OpModAssign,
+    OpAndAssign,
+    OpOrAssign,
+    OpXorAssign,
+    OpShlAssign,
+    OpShrAssign,
+    OpArrow,
+    OpDot,
+    OpEllipsis,
+    OpQuestion,
+    OpColon,
+    OpHash,
+    OpHashHash,
 }
 
-#[derive(Debug)]
+/// Punctuators that only delimit and never operate on a value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PunctType {
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    LBrace,
+    RBrace,
+    Semicolon,
+    Comma,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DirectiveKind {
+    Include,
+    Define,
+    Undef,
+    Ifdef,
+    Ifndef,
+    If,
+    Elif,
+    Else,
+    Endif,
+    Pragma,
+    Error,
+    Warning,
+    Line,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntSuffix {
+    None,
+    U,
+    L,
+    UL,
+    LL,
+    ULL,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FloatSuffix {
+    None,
+    F,
+    L,
+}
+
+/// The encoding prefix of a string literal or character constant.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    None,
+    /// `L`
+    Wide,
+    /// `u8`
+    Utf8,
+    /// `u`
+    Utf16,
+    /// `U`
+    Utf32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
     Note,
     NewLine,
     Space,
     KeyWord(KeyWordType),
-    Number,
-    FlotNumber,
-    Str,
-    Char,
+    Number(IntSuffix),
+    FlotNumber(FloatSuffix),
+    Str(Encoding),
+    Char(Encoding),
     Identifier,
     Operator(OperatorType),
+    Punct(PunctType),
+    /// A `#` starting a line, together with the directive name after it.
+    Directive(DirectiveKind),
+    /// The `<stdio.h>` or `"local.h"` after `#include`, delimiters included.
+    HeaderName,
+    Eof,
 }
 
-#[derive(Debug)]
+const KEYWORDS: [(&str, KeyWordType); 37] = [
+    ("auto", KeyWordType::KAuto),
+    ("break", KeyWordType::KBreak),
+    ("case", KeyWordType::KCase),
+    ("char", KeyWordType::KChar),
+    ("const", KeyWordType::KConst),
+    ("continue", KeyWordType::KContinue),
+    ("default", KeyWordType::KDefault),
+    ("do", KeyWordType::KDo),
+    ("double", KeyWordType::KDouble),
+    ("else", KeyWordType::KElse),
+    ("enum", KeyWordType::KEnum),
+    ("extern", KeyWordType::KExtern),
+    ("float", KeyWordType::KFloat),
+    ("for", KeyWordType::KFor),
+    ("goto", KeyWordType::KGoto),
+    ("if", KeyWordType::KIf),
+    ("int", KeyWordType::KInt),
+    ("long", KeyWordType::KLong),
+    ("register", KeyWordType::KRegister),
+    ("return", KeyWordType::KReturn),
+    ("short", KeyWordType::KShort),
+    ("signed", KeyWordType::KSigned),
+    ("sizeof", KeyWordType::KSizeof),
+    ("static", KeyWordType::KStatic),
+    ("struct", KeyWordType::KStruct),
+    ("switch", KeyWordType::KSwitch),
+    ("typedef", KeyWordType::KTypedef),
+    ("union", KeyWordType::KUnion),
+    ("unsigned", KeyWordType::KUnsigned),
+    ("void", KeyWordType::KVoid),
+    ("volatile", KeyWordType::KVolatile),
+    ("while", KeyWordType::KWhile),
+    ("inline", KeyWordType::KInline),
+    ("restrict", KeyWordType::KRestrict),
+    ("_Bool", KeyWordType::KBool),
+    ("_Complex", KeyWordType::KComplex),
+    ("_Imaginary", KeyWordType::KImaginary),
+];
+
+pub fn keyword_from_str(str: &str) -> Option<KeyWordType> {
+    KEYWORDS.iter().find(|(keyword, _)| *keyword == str).map(|(_, key_word)| *key_word)
+}
+
+const DIRECTIVES: [(&str, DirectiveKind); 13] = [
+    ("include", DirectiveKind::Include),
+    ("define", DirectiveKind::Define),
+    ("undef", DirectiveKind::Undef),
+    ("ifdef", DirectiveKind::Ifdef),
+    ("ifndef", DirectiveKind::Ifndef),
+    ("if", DirectiveKind::If),
+    ("elif", DirectiveKind::Elif),
+    ("else", DirectiveKind::Else),
+    ("endif", DirectiveKind::Endif),
+    ("pragma", DirectiveKind::Pragma),
+    ("error", DirectiveKind::Error),
+    ("warning", DirectiveKind::Warning),
+    ("line", DirectiveKind::Line),
+];
+
+/// Names not in the table, and the null directive "#", are `Unknown`.
+pub fn directive_from_str(str: &str) -> DirectiveKind {
+    DIRECTIVES.iter().find(|(name, _)| *name == str).map_or(DirectiveKind::Unknown, |(_, kind)| *kind)
+}
+
+pub fn punct_from_char(c: char) -> Option<PunctType> {
+    match c {
+        '(' => Some(PunctType::LParen),
+        ')' => Some(PunctType::RParen),
+        '[' => Some(PunctType::LBracket),
+        ']' => Some(PunctType::RBracket),
+        '{' => Some(PunctType::LBrace),
+        '}' => Some(PunctType::RBrace),
+        ';' => Some(PunctType::Semicolon),
+        ',' => Some(PunctType::Comma),
+        _ => None,
+    }
+}
+
+/// Parses an integer suffix. `u` may come before or after the length part,
+/// in either case, but `ll` must not mix cases.
+pub fn int_suffix_from_str(str: &str) -> Option<IntSuffix> {
+    let (unsigned, long) = if let Some(long) = str.strip_prefix(['u', 'U']) {
+        (true, long)
+    } else if let Some(long) = str.strip_suffix(['u', 'U']) {
+        (true, long)
+    } else {
+        (false, str)
+    };
+
+    match (unsigned, long) {
+        (false, "") => Some(IntSuffix::None),
+        (true, "") => Some(IntSuffix::U),
+        (false, "l" | "L") => Some(IntSuffix::L),
+        (true, "l" | "L") => Some(IntSuffix::UL),
+        (false, "ll" | "LL") => Some(IntSuffix::LL),
+        (true, "ll" | "LL") => Some(IntSuffix::ULL),
+        _ => None,
+    }
+}
+
+// Decodes the escape sequence whose backslash is at `bytes[index]`, returning its
+// value and the index just past it, or a message and the index to report it at.
+// Hex and octal escapes above `max` are out of range. Universal character
+// names give the code point they name.
+fn decode_escape(bytes: &[u8], index: usize, max: u32) -> Result<(u32, usize), (String, usize)> {
+    let at = |index: usize| -> u8 {
+        if index < bytes.len() {
+            bytes[index]
+        } else {
+            0
+        }
+    };
+
+    let value = match at(index + 1) {
+        b'n' => b'\n',
+        b't' => b'\t',
+        b'r' => b'\r',
+        b'a' => 0x07,
+        b'b' => 0x08,
+        b'f' => 0x0c,
+        b'v' => 0x0b,
+        b'\\' | b'\'' | b'"' | b'?' => at(index + 1),
+        b'x' => {
+            let mut end = index + 2;
+            let mut value: u64 = 0;
+            while at(end).is_ascii_hexdigit() {
+                value = value.saturating_mul(16).saturating_add((at(end) as char).to_digit(16).unwrap() as u64);
+                end += 1;
+            }
+            if end == index + 2 {
+                return Err((String::from("\\x used with no following hex digits"), index));
+            }
+            if value > max as u64 {
+                return Err((String::from("Hex escape sequence out of range"), index));
+            }
+            return Ok((value as u32, end));
+        },
+        b'u' | b'U' => {
+            let digits = if at(index + 1) == b'u' { 4 } else { 8 };
+            let end = index + 2 + digits;
+            if end > bytes.len() || !bytes[index + 2..end].iter().all(|byte| byte.is_ascii_hexdigit()) {
+                return Err((format!("\\{} needs {} hexadecimal digits", at(index + 1) as char, digits), index));
+            }
+            let value = u32::from_str_radix(&String::from_utf8_lossy(&bytes[index + 2..end]), 16).unwrap();
+            // Outside the basic character set only $, @ and ` may be written this way.
+            let valid = match value {
+                0x24 | 0x40 | 0x60 => true,
+                0xd800..=0xdfff => false,
+                _ => (0xa0..=0x10ffff).contains(&value),
+            };
+            if !valid {
+                return Err((format!("\\{} is not a valid universal character", String::from_utf8_lossy(&bytes[index + 1..end])), index));
+            }
+            return Ok((value, end));
+        },
+        b'0'..=b'7' => {
+            let mut end = index + 1;
+            let mut value: u32 = 0;
+            while end < index + 4 && matches!(at(end), b'0'..=b'7') {
+                value = value * 8 + (at(end) - b'0') as u32;
+                end += 1;
+            }
+            if value > max {
+                return Err((String::from("Octal escape sequence out of range"), index));
+            }
+            return Ok((value, end));
+        },
+        chr => return Err((format!("Unknown escape sequence '\\{}'", (chr as char).escape_default()), index)),
+    };
+
+    Ok((value as u32, index + 2))
+}
+
+/// Decodes a number spelling without its suffix or digit separators, e.g.
+/// "0x1f" or "1.5e3". Returns `None` if the value does not fit: above
+/// `u64::MAX` for integers, infinite for floating constants.
+fn number_value(text: &str, radix: u32, float: bool) -> Option<LiteralValue> {
+    let digits = if radix == 16 || radix == 2 { &text[2..] } else { text };
+
+    if !float {
+        return u64::from_str_radix(digits, radix).ok().map(LiteralValue::Int);
+    }
+
+    let value = if radix == 16 {
+        let (mantissa, exponent) = digits.split_once(['p', 'P']).unwrap_or((digits, "0"));
+        let mut value = 0.0;
+        let mut fraction_digits = 0;
+        let mut in_fraction = false;
+        for chr in mantissa.chars() {
+            match chr.to_digit(16) {
+                Some(digit) => {
+                    value = value * 16.0 + digit as f64;
+                    if in_fraction {
+                        fraction_digits += 1;
+                    }
+                },
+                None => in_fraction = true,
+            }
+        }
+        let exponent = exponent.parse::<i32>().unwrap_or(if exponent.starts_with('-') { i32::MIN } else { i32::MAX });
+        value * 2f64.powi(exponent.saturating_sub(4 * fraction_digits))
+    } else {
+        digits.parse::<f64>().ok()?
+    };
+
+    if value.is_finite() {
+        Some(LiteralValue::Float(value))
+    } else {
+        None
+    }
+}
+
+// The largest value a hex or octal escape may have in a literal of `encoding`.
+fn escape_max(encoding: Encoding) -> u32 {
+    match encoding {
+        Encoding::None | Encoding::Utf8 => 0xff,
+        Encoding::Utf16 => 0xffff,
+        Encoding::Wide | Encoding::Utf32 => u32::MAX,
+    }
+}
+
+/// Matches an optional encoding prefix at `index` that is directly followed by
+/// `quote`, giving the encoding and the length of the prefix.
+fn encoding_prefix(bytes: &[u8], index: usize, quote: u8) -> Option<(Encoding, usize)> {
+    let (encoding, len) = match &bytes[index..] {
+        [b'u', b'8', ..] => (Encoding::Utf8, 2),
+        [b'u', ..] => (Encoding::Utf16, 1),
+        [b'U', ..] => (Encoding::Utf32, 1),
+        [b'L', ..] => (Encoding::Wide, 1),
+        _ => (Encoding::None, 0),
+    };
+
+    if bytes.get(index + len) == Some(&quote) {
+        Some((encoding, len))
+    } else {
+        None
+    }
+}
+
+const DEFAULT_TAB_STOP: usize = 8;
+
+/// The most characters a plain character constant may hold: as many as fit
+/// in an `int`.
+const MAX_MULTICHAR: usize = 4;
+
+// Sorted longest first so that parse_operator always takes the longest match.
+const OPERATORS: [(&str, OperatorType); 40] = [
+    ("...", OperatorType::OpEllipsis),
+    ("<<=", OperatorType::OpShlAssign),
+    (">>=", OperatorType::OpShrAssign),
+    ("->", OperatorType::OpArrow),
+    ("++", OperatorType::OpInc),
+    ("--", OperatorType::OpDec),
+    ("<<", OperatorType::OpShl),
+    (">>", OperatorType::OpShr),
+    ("<=", OperatorType::OpLe),
+    (">=", OperatorType::OpGe),
+    ("==", OperatorType::OpEq),
+    ("!=", OperatorType::OpNe),
+    ("&&", OperatorType::OpLogAnd),
+    ("||", OperatorType::OpLogOr),
+    ("+=", OperatorType::OpAddAssign),
+    ("-=", OperatorType::OpSubAssign),
+    ("*=", OperatorType::OpMulAssign),
+    ("/=", OperatorType::OpDivAssign),
+    ("%=", OperatorType::OpModAssign),
+    ("&=", OperatorType::OpAndAssign),
+    ("|=", OperatorType::OpOrAssign),
+    ("^=", OperatorType::OpXorAssign),
+    ("##", OperatorType::OpHashHash),
+    ("+", OperatorType::OpAdd),
+    ("-", OperatorType::OpSub),
+    ("*", OperatorType::OpMul),
+    ("/", OperatorType::OpDiv),
+    ("%", OperatorType::OpMod),
+    ("<", OperatorType::OpLt),
+    (">", OperatorType::OpGt),
+    ("=", OperatorType::OpAssign),
+    ("!", OperatorType::OpLogNot),
+    ("&", OperatorType::OpBitAnd),
+    ("|", OperatorType::OpBitOr),
+    ("^", OperatorType::OpBitXor),
+    ("~", OperatorType::OpBitNot),
+    (".", OperatorType::OpDot),
+    ("?", OperatorType::OpQuestion),
+    (":", OperatorType::OpColon),
+    ("#", OperatorType::OpHash),
+];
+
+// The C95 digraphs, lexed as the tokens they stand for. "%:%:" comes first so
+// that it is not taken as two "%:".
+const DIGRAPHS: [(&str, TokenType); 6] = [
+    ("%:%:", TokenType::Operator(OperatorType::OpHashHash)),
+    ("<:", TokenType::Punct(PunctType::LBracket)),
+    (":>", TokenType::Punct(PunctType::RBracket)),
+    ("<%", TokenType::Punct(PunctType::LBrace)),
+    ("%>", TokenType::Punct(PunctType::RBrace)),
+    ("%:", TokenType::Operator(OperatorType::OpHash)),
+];
+
+impl fmt::Display for KeyWordType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (keyword, _) = KEYWORDS.iter().find(|(_, key_word)| key_word == self).unwrap();
+        write!(f, "{}", keyword)
+    }
+}
+
+impl fmt::Display for OperatorType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (op, _) = OPERATORS.iter().find(|(_, op_type)| op_type == self).unwrap();
+        write!(f, "{}", op)
+    }
+}
+
+impl fmt::Display for DirectiveKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match DIRECTIVES.iter().find(|(_, kind)| kind == self) {
+            Some((name, _)) => write!(f, "#{}", name),
+            None => write!(f, "unknown directive"),
+        }
+    }
+}
+
+impl fmt::Display for PunctType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let punct = match self {
+            PunctType::LParen => "(",
+            PunctType::RParen => ")",
+            PunctType::LBracket => "[",
+            PunctType::RBracket => "]",
+            PunctType::LBrace => "{",
+            PunctType::RBrace => "}",
+            PunctType::Semicolon => ";",
+            PunctType::Comma => ",",
+        };
+        write!(f, "{}", punct)
+    }
+}
+
+/// Describes the kind of token the way a diagnostic would, e.g. "identifier" or "';'".
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenType::Note => write!(f, "comment"),
+            TokenType::NewLine => write!(f, "newline"),
+            TokenType::Space => write!(f, "whitespace"),
+            TokenType::KeyWord(key_word) => write!(f, "'{}'", key_word),
+            TokenType::Number(_) => write!(f, "integer constant"),
+            TokenType::FlotNumber(_) => write!(f, "floating constant"),
+            TokenType::Str(_) => write!(f, "string literal"),
+            TokenType::Char(_) => write!(f, "character constant"),
+            TokenType::Identifier => write!(f, "identifier"),
+            TokenType::Operator(op_type) => write!(f, "'{}'", op_type),
+            TokenType::Punct(punct) => write!(f, "'{}'", punct),
+            TokenType::Directive(DirectiveKind::Unknown) => write!(f, "preprocessing directive"),
+            TokenType::Directive(kind) => write!(f, "'{}'", kind),
+            TokenType::HeaderName => write!(f, "header name"),
+            TokenType::Eof => write!(f, "end of file"),
+        }
+    }
+}
+
+impl TokenType {
+    /// A short, stable name for the token's category, for machine-readable output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TokenType::Note => "comment",
+            TokenType::NewLine => "newline",
+            TokenType::Space => "space",
+            TokenType::KeyWord(_) => "keyword",
+            TokenType::Number(_) => "integer",
+            TokenType::FlotNumber(_) => "float",
+            TokenType::Str(_) => "string",
+            TokenType::Char(_) => "char",
+            TokenType::Identifier => "identifier",
+            TokenType::Operator(_) => "operator",
+            TokenType::Punct(_) => "punct",
+            TokenType::Directive(_) => "directive",
+            TokenType::HeaderName => "header-name",
+            TokenType::Eof => "eof",
+        }
+    }
+}
+
+/// Where a token or error starts, plus the byte range it covers in the source.
+#[derive(Debug, Clone)]
 pub struct Location {
     file: String,
     line: usize,
     column: usize,
+    span: Span,
 }
 
 impl Location {
-    pub fn show(&self) -> String {
-        format!("{}:{}:{}", &self.file, self.line, self.column)
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
     }
 }
 
-#[derive(Debug)]
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", &self.file, self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LexError {
+    pub message: String,
+    pub location: Location,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Error: \"{}\" at ({})", self.message, self.location)
+    }
+}
+
+/// The value a literal token denotes, decoded from its spelling.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LiteralValue {
+    Char(u32),
+    Int(u64),
+    Float(f64),
+}
+
+impl fmt::Display for LiteralValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LiteralValue::Char(value) => write!(f, "{}", value),
+            LiteralValue::Int(value) => write!(f, "{}", value),
+            LiteralValue::Float(value) => write!(f, "{:?}", value),
+        }
+    }
+}
+
+/// Byte offsets of a token in its source buffer, `end` being exclusive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone)]
 pub struct Token {
     loc: Location,
     token_type: TokenType,
     source: String,
+    value: Option<LiteralValue>,
 }
 
 impl Token {
-    pub fn show(&self) -> String {
-        format!("'{}' [{:?}] Loc:({})", &self.source, self.token_type, self.loc.show())
+    pub fn new(loc: Location, token_type: TokenType, source: &str) -> Self {
+        Token {
+            loc,
+            token_type,
+            source: String::from(source),
+            value: None,
+        }
+    }
+
+    /// The same token placed at `loc`, e.g. a macro body token at the point of expansion.
+    pub fn with_loc(mut self, loc: Location) -> Self {
+        self.loc = loc;
+        self
+    }
+
+    pub fn token_type(&self) -> &TokenType {
+        &self.token_type
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn loc(&self) -> &Location {
+        &self.loc
+    }
+
+    pub fn span(&self) -> Span {
+        self.loc.span
+    }
+
+    /// Returns the token text as a view into `src`, the buffer it was lexed from.
+    pub fn source_slice<'a>(&self, src: &'a str) -> &'a str {
+        &src[self.loc.span.start..self.loc.span.end]
+    }
+
+    /// The decoded value of a literal, if the lexer computed one.
+    pub fn value(&self) -> Option<LiteralValue> {
+        self.value
     }
+
+    /// The characters a string literal denotes, with escapes decoded and
+    /// without quotes or the terminating null. Plain and `u8` strings give
+    /// bytes, universal character names encoded as UTF-8; wide strings give
+    /// one code point per element. `None` if this is not a string literal or
+    /// it holds an invalid escape.
+    pub fn string_value(&self) -> Option<Vec<u32>> {
+        let encoding = match self.token_type {
+            TokenType::Str(encoding) => encoding,
+            _ => return None,
+        };
+        let narrow = matches!(encoding, Encoding::None | Encoding::Utf8);
+        let bytes = self.source.as_bytes();
+        let end = bytes.len() - 1;
+        let mut index = self.source.find('"')? + 1;
+        let mut values = Vec::new();
+        while index < end {
+            match bytes[index] {
+                b'\\' => {
+                    let (value, next) = decode_escape(bytes, index, escape_max(encoding)).ok()?;
+                    if narrow && matches!(bytes[index + 1], b'u' | b'U') {
+                        let mut buf = [0; 4];
+                        values.extend(char::from_u32(value)?.encode_utf8(&mut buf).bytes().map(u32::from));
+                    } else {
+                        values.push(value);
+                    }
+                    index = next;
+                },
+                byte if narrow || byte.is_ascii() => {
+                    values.push(byte as u32);
+                    index += 1;
+                },
+                _ => {
+                    let chr = self.source[index..].chars().next()?;
+                    values.push(chr as u32);
+                    index += chr.len_utf8();
+                },
+            }
+        }
+        Some(values)
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.value {
+            Some(value) => write!(f, "'{}' = {} [{:?}] Loc:({})", &self.source, value, self.token_type, self.loc),
+            None => write!(f, "'{}' [{:?}] Loc:({})", &self.source, self.token_type, self.loc),
+        }
+    }
+}
+
+/// Walks the tokens of a `Lex` in order, with lookahead. Whitespace, comment
+/// and newline tokens are included; the last token is always `Eof`.
+pub struct TokenStream<'a> {
+    iter: std::slice::Iter<'a, Token>,
+}
+
+impl<'a> TokenStream<'a> {
+    /// The token `next` would return, without consuming it.
+    pub fn peek(&self) -> Option<&'a Token> {
+        self.peek_nth(0)
+    }
+
+    /// The token `n` places ahead; `peek_nth(0)` is `peek()`.
+    pub fn peek_nth(&self, n: usize) -> Option<&'a Token> {
+        self.iter.as_slice().get(n)
+    }
+}
+
+impl<'a> From<&'a [Token]> for TokenStream<'a> {
+    fn from(tokens: &'a [Token]) -> Self {
+        TokenStream { iter: tokens.iter() }
+    }
+}
+
+impl<'a> Iterator for TokenStream<'a> {
+    type Item = &'a Token;
+
+    fn next(&mut self) -> Option<&'a Token> {
+        self.iter.next()
+    }
+}
+
+/// Owning counterpart of `TokenStream`, for passes that outlive the `Lex`.
+pub struct IntoTokenStream {
+    iter: std::vec::IntoIter<Token>,
+}
+
+impl IntoTokenStream {
+    pub fn peek(&self) -> Option<&Token> {
+        self.peek_nth(0)
+    }
+
+    pub fn peek_nth(&self, n: usize) -> Option<&Token> {
+        self.iter.as_slice().get(n)
+    }
+}
+
+impl From<Vec<Token>> for IntoTokenStream {
+    fn from(tokens: Vec<Token>) -> Self {
+        IntoTokenStream { iter: tokens.into_iter() }
+    }
+}
+
+impl Iterator for IntoTokenStream {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.iter.next()
+    }
+}
+
+/// One line per token, skipping whitespace, comments and the end of file.
+pub fn show_tokens<'a>(tokens: impl IntoIterator<Item = &'a Token>) -> String {
+    let mut str = String::new();
+
+    for token in tokens {
+        match token.token_type {
+            TokenType::Note | TokenType::NewLine | TokenType::Space | TokenType::Eof => continue,
+            _ => str += format!("{}\n", token).as_str(),
+        }
+    }
+    str.pop();
+    str
+}
+
+/// One token per line as tab-separated `line column kind source`, whitespace and
+/// comments included. Tabs, newlines and backslashes in the source are escaped
+/// as `\t`, `\n` and `\\` so every row has exactly four fields.
+pub fn dump_tokens<'a>(tokens: impl IntoIterator<Item = &'a Token>) -> String {
+    let mut str = String::new();
+
+    for token in tokens {
+        let mut source = String::new();
+        for chr in token.source.chars() {
+            match chr {
+                '\t' => source += "\\t",
+                '\n' => source += "\\n",
+                '\r' => source += "\\r",
+                '\\' => source += "\\\\",
+                _ => source.push(chr),
+            }
+        }
+        str += format!("{}\t{}\t{}\t{}\n", token.loc.line, token.loc.column, token.token_type.kind(), source).as_str();
+    }
+    str
+}
+
+fn json_string(text: &str) -> String {
+    let mut str = String::from("\"");
+    for chr in text.chars() {
+        match chr {
+            '"' => str += "\\\"",
+            '\\' => str += "\\\\",
+            '\n' => str += "\\n",
+            '\r' => str += "\\r",
+            '\t' => str += "\\t",
+            _ if (chr as u32) < 0x20 => str += format!("\\u{:04x}", chr as u32).as_str(),
+            _ => str.push(chr),
+        }
+    }
+    str.push('"');
+    str
+}
+
+/// The tokens as a JSON array, one object per token with its `kind` (as in
+/// `TokenType::kind`), `source`, `file`, `line`, `column`, and byte span
+/// `start` and `end`.
+pub fn tokens_to_json<'a>(tokens: impl IntoIterator<Item = &'a Token>) -> String {
+    let objects: Vec<String> = tokens
+        .into_iter()
+        .map(|token| {
+            format!(
+                "{{\"kind\":\"{}\",\"source\":{},\"file\":{},\"line\":{},\"column\":{},\"start\":{},\"end\":{}}}",
+                token.token_type.kind(),
+                json_string(&token.source),
+                json_string(&token.loc.file),
+                token.loc.line,
+                token.loc.column,
+                token.loc.span.start,
+                token.loc.span.end
+            )
+        })
+        .collect();
+    format!("[{}]\n", objects.join(",\n"))
+}
+
+// The character a trigraph "??x" stands for, given its last character.
+fn trigraph(chr: u8) -> Option<char> {
+    let replacement = match chr {
+        b'=' => '#',
+        b'(' => '[',
+        b'/' => '\\',
+        b')' => ']',
+        b'\'' => '^',
+        b'<' => '{',
+        b'!' => '|',
+        b'>' => '}',
+        b'-' => '~',
+        _ => return None,
+    };
+    Some(replacement)
+}
+
+// Bytes removed from the source before lexing. `at` is the offset in the
+// rewritten text right after which they were.
+#[derive(Debug)]
+struct SourceEdit {
+    at: usize,
+    removed: usize,
+    // A line splice, rather than the last two bytes of a trigraph.
+    splice: bool,
+}
+
+// Translation phases 1 and 2: replaces trigraphs when `trigraphs` is set,
+// then deletes every backslash that ends a line, along with the line break,
+// so that a token can continue on the next line. Returns the rewritten text,
+// the edits made and the source offset of every trigraph replaced.
+fn rewrite_source(src: &str, trigraphs: bool) -> (String, Vec<SourceEdit>, Vec<usize>) {
+    let bytes = src.as_bytes();
+    let mut text = String::with_capacity(src.len());
+    let mut edits = Vec::new();
+    let mut replaced = Vec::new();
+    let mut copied = 0;
+    let mut index = 0;
+    while index < bytes.len() {
+        let (chr, len) = match bytes.get(index + 2).copied().and_then(trigraph) {
+            Some(chr) if trigraphs && bytes[index..].starts_with(b"??") => (chr, 3),
+            _ if bytes[index] == b'\\' => ('\\', 1),
+            _ => {
+                index += 1;
+                continue;
+            },
+        };
+        let line_break = match (bytes.get(index + len), bytes.get(index + len + 1)) {
+            (Some(b'\r'), Some(b'\n')) => 2,
+            (Some(b'\n' | b'\r'), _) => 1,
+            _ => 0,
+        };
+
+        text += &src[copied..index];
+        if len == 3 {
+            replaced.push(index);
+        }
+        if chr == '\\' && line_break > 0 {
+            edits.push(SourceEdit { at: text.len(), removed: len + line_break, splice: true });
+            index += len + line_break;
+        } else if len == 3 {
+            text.push(chr);
+            edits.push(SourceEdit { at: text.len(), removed: 2, splice: false });
+            index += len;
+        } else {
+            text.push(chr);
+            index += len;
+        }
+        copied = index;
+    }
+    text += &src[copied..];
+    (text, edits, replaced)
 }
 
 #[derive(Debug)]
 pub struct Lex {
     file: String,
+    src: String,
     tokens: Vec<Token>,
+    errors: Vec<LexError>,
+    warnings: Vec<LexError>,
+    digit_separators: bool,
+    trigraphs: bool,
+    directives: bool,
+    tab_stop: usize,
 
     index: usize,
     line: usize,
@@ -68,62 +955,109 @@ pub struct Lex {
 }
 
 impl Lex {
-    pub fn new(file: &str) -> Self {
-        let lex = Lex {
+    /// Reads `file` for lexing; failing to read it is left to the caller to
+    /// report.
+    pub fn new(file: &str) -> io::Result<Self> {
+        let src = fs::read_to_string(file)?;
+        Ok(Self::from_source(file, &src))
+    }
+
+    /// Lexes `src` from memory; `file` is only used in token locations.
+    pub fn from_source(file: &str, src: &str) -> Self {
+        Lex {
             file: String::from(file),
+            src: String::from(src),
             tokens: Vec::<Token>::new(),
+            errors: Vec::<LexError>::new(),
+            warnings: Vec::<LexError>::new(),
+            digit_separators: false,
+            trigraphs: false,
+            directives: true,
+            tab_stop: DEFAULT_TAB_STOP,
             index: 0,
             line: 1,
             column: 1,
-        };
-        lex
+        }
     }
 
     pub fn add_token(&mut self, loc: Location, token_type: TokenType, source: &str) {
         let token = Token {
-            loc: loc,
-            token_type: token_type,
+            loc,
+            token_type,
             source: String::from(source),
+            value: None,
         };
 
         self.tokens.push(token);
     }
 
-    pub fn show(&self) -> String {
-        let mut str = String::new();
+    /// Accepts C23 digit separators, as in `1'000'000`. Off by default, since
+    /// before C23 the quote after a number starts a character constant.
+    pub fn set_digit_separators(&mut self, enabled: bool) {
+        self.digit_separators = enabled;
+    }
 
-        for token in &self.tokens {
-            match token.token_type {
-                TokenType::Note | TokenType::NewLine | TokenType::Space => continue,
-                _ => str += &format!("{}\n", token.show()).as_str(),
-            }
-        }
-        str.pop();
-        str
+    /// Replaces the nine trigraphs, such as `??=` for `#`, before lexing, with
+    /// a warning for each. Off by default, as in GCC; C23 drops them.
+    pub fn set_trigraphs(&mut self, enabled: bool) {
+        self.trigraphs = enabled;
     }
 
-    pub fn parse(&mut self) {
-        let path = Path::new(&self.file);
+    /// Makes a `#` at the start of a line begin a `Directive` token. On by
+    /// default; text made by the `#` and `##` operators is lexed without.
+    pub fn set_directives(&mut self, enabled: bool) {
+        self.directives = enabled;
+    }
 
-        let mut file = match File::open(&path) {
-            Err(why) => {
-                eprintln!("couldn't open {}: {:?}", &self.file, why);
-                std::process::exit(-1);
-            },
-            Ok(file) => file,
-        };
+    /// Sets how many columns a tab spans in token and error locations; the
+    /// default is 8. A width of 0 is treated as 1.
+    pub fn set_tab_stop(&mut self, width: usize) {
+        self.tab_stop = width.max(1);
+    }
 
-        let mut src = String::new();
-        match file.read_to_string(&mut src) {
-            Err(why) => {
-                eprintln!("couldn't read {}: {:?}", &self.file, why);
-                std::process::exit(-1);
-            },
-            Ok(_) => (),
-        };
+    /// Every token in source order, including whitespace and comments.
+    pub fn tokens(&self) -> impl Iterator<Item = &Token> {
+        self.tokens.iter()
+    }
+
+    /// Errors found by `parse` so far, in source order.
+    pub fn errors(&self) -> &[LexError] {
+        &self.errors
+    }
+
+    /// Diagnostics that do not stop compilation, such as multi-character
+    /// constants, in source order.
+    pub fn warnings(&self) -> &[LexError] {
+        &self.warnings
+    }
 
+    pub fn token_stream(&self) -> TokenStream<'_> {
+        TokenStream { iter: self.tokens.iter() }
+    }
+
+    pub fn into_token_stream(self) -> IntoTokenStream {
+        IntoTokenStream { iter: self.tokens.into_iter() }
+    }
+
+    pub fn show(&self) -> String {
+        show_tokens(&self.tokens)
+    }
+
+    /// Lexes the whole source. Errors do not stop the scan: the lexer skips
+    /// past the offending text and carries on, so every problem in the file is
+    /// reported at once. Only the buffer given to `from_source` (or read by
+    /// `new`) is used; `parse` never touches the file system itself.
+    pub fn parse(&mut self) -> Result<(), Vec<LexError>> {
+        let original = std::mem::take(&mut self.src);
+        let (src, edits, trigraphs) = rewrite_source(&original, self.trigraphs);
         let bytes = src.as_bytes();
         while self.index < bytes.len() {
+            if self.parse_directive(bytes) {
+                continue;
+            }
+            if self.parse_header_name(bytes) {
+                continue;
+            }
             if self.parse_note(bytes) {
                 continue;
             }
@@ -139,40 +1073,145 @@ impl Lex {
             if self.parse_char(bytes) {
                 continue;
             }
-            if self.parse_identifier(bytes) {
+            if self.parse_keyword(bytes) {
                 continue;
             }
-            if self.parse_operator(bytes) {
+            if self.parse_identifier(bytes) {
                 continue;
             }
             if self.parse_number(bytes) {
                 continue;
             }
+            if self.parse_operator(bytes) {
+                continue;
+            }
 
-            self.index += 1;
+            // Form feeds and vertical tabs are whitespace; anything else left over is not C.
+            let chr = src[self.index..].chars().next().unwrap();
+            if chr != '\x0B' && chr != '\x0C' {
+                let span = Span { start: self.index, end: self.index + chr.len_utf8() };
+                self.error(self.line, self.column, span, format!("Stray '{}' in program", chr.escape_default()));
+            }
+            self.index += chr.len_utf8();
             self.column += 1;
         }
-    }
+        self.src = original;
 
-    fn parse_note(&mut self, bytes: &[u8]) -> bool {
-        if bytes.len() - self.index < 2 {
-            return false;
+        let loc = Location {
+            file: String::from(&self.file),
+            line: self.line,
+            column: self.column,
+            span: Span { start: self.index, end: self.index },
+        };
+        self.add_token(loc, TokenType::Eof, "");
+        if !edits.is_empty() {
+            self.unsplice(&edits, &trigraphs);
         }
+        for at in trigraphs {
+            let span = Span { start: at, end: at + 3 };
+            let (line, column) = self.source_position(at);
+            let message = format!("Trigraph {} converted to {}", &self.src[at..at + 3], trigraph(self.src.as_bytes()[at + 2]).unwrap());
+            self.warning(line, column, span, message);
+        }
+        self.warnings.sort_by_key(|warning| warning.location.span.start);
 
-        if (bytes[self.index] as char == '/') && (bytes[self.index + 1] as char == '*') {
-            let start = self.index;
-            self.index += 2;
-            let line = self.line;
-            let column = self.column;
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors.clone())
+        }
+    }
+
+    // The line and column of a byte offset into the source, counted the way
+    // the lexer does: one column per byte, with tabs going to the next stop.
+    fn source_position(&self, offset: usize) -> (usize, usize) {
+        let bytes = &self.src.as_bytes()[..offset];
+        let line_start = bytes.iter().rposition(|byte| matches!(byte, b'\n' | b'\r')).map_or(0, |index| index + 1);
+        let line = 1 + bytes.iter().enumerate().filter(|(index, byte)| **byte == b'\n' || **byte == b'\r' && bytes.get(index + 1) != Some(&b'\n')).count();
+        let column = bytes[line_start..].iter().fold(1, |column, byte| if *byte == b'\t' { self.next_tab_stop(column) } else { column + 1 });
+        (line, column)
+    }
+
+    // Moves locations found in the rewritten text back to where they are in
+    // the source: spans are shifted past the removed bytes and lines counted
+    // physically, and columns recounted on lines edited before the location.
+    fn unsplice(&mut self, edits: &[SourceEdit], trigraphs: &[usize]) {
+        let mut splice_ends = Vec::new();
+        let mut removed = 0;
+        for edit in edits {
+            removed += edit.removed;
+            if edit.splice {
+                splice_ends.push(edit.at + removed);
+            }
+        }
+        // A location ending right where a splice was leaves it out, but one
+        // ending after a trigraph's replacement takes in the whole trigraph.
+        let shift = |offset: usize, end: bool| edits.iter().take_while(|edit| edit.at < offset || edit.at == offset && !(end && edit.splice)).map(|edit| edit.removed).sum::<usize>();
+
+        let mut locations: Vec<&mut Location> = self.tokens.iter_mut().map(|token| &mut token.loc).chain(self.errors.iter_mut().chain(self.warnings.iter_mut()).map(|error| &mut error.location)).collect();
+        for loc in locations.iter_mut() {
+            let start = loc.span.start;
+            loc.line += edits.iter().take_while(|edit| edit.at <= start).filter(|edit| edit.splice).count();
+            loc.span.start += shift(start, false);
+            loc.span.end += shift(loc.span.end, true);
+        }
+
+        let bytes = self.src.as_bytes();
+        for loc in locations {
+            let line_start = bytes[..loc.span.start].iter().rposition(|byte| matches!(byte, b'\n' | b'\r')).map_or(0, |index| index + 1);
+            if splice_ends.contains(&line_start) || trigraphs.iter().any(|at| (line_start..loc.span.start).contains(at)) {
+                loc.column = bytes[line_start..loc.span.start].iter().fold(1, |column, byte| if *byte == b'\t' { (column - 1) / self.tab_stop * self.tab_stop + self.tab_stop + 1 } else { column + 1 });
+            }
+        }
+    }
+
+    fn error(&mut self, line: usize, column: usize, span: Span, message: String) {
+        let error = LexError {
+            message,
+            location: Location {
+                file: String::from(&self.file),
+                line,
+                column,
+                span,
+            },
+        };
+        self.errors.push(error);
+    }
+
+    fn warning(&mut self, line: usize, column: usize, span: Span, message: String) {
+        let warning = LexError {
+            message,
+            location: Location {
+                file: String::from(&self.file),
+                line,
+                column,
+                span,
+            },
+        };
+        self.warnings.push(warning);
+    }
+
+    fn parse_note(&mut self, bytes: &[u8]) -> bool {
+        if bytes.len() - self.index < 2 {
+            return false;
+        }
+
+        if (bytes[self.index] as char == '/') && (bytes[self.index + 1] as char == '*') {
+            let start = self.index;
+            let line = self.line;
+            let column = self.column;
+            self.index += 2;
+            self.column += 2;
 
             loop {
                 if bytes.len() - self.index < 2 {
-                    eprintln!("'/*' Missing ending");
-                    std::process::exit(-1);
+                    self.error(line, column, Span { start, end: bytes.len() }, String::from("'/*' Missing ending"));
+                    self.index = bytes.len();
+                    return true;
                 }
 
                 let chr = bytes[self.index] as char;
-                if chr == '\n' {
+                if chr == '\n' || (chr == '\r' && bytes[self.index + 1] as char != '\n') {
                     self.line += 1;
                     self.column = 1;
                     self.index += 1;
@@ -184,48 +1223,66 @@ impl Lex {
                     let token = Token {
                         loc: Location {
                             file: String::from(&self.file),
-                            line: line,
-                            column: column,
+                            line,
+                            column,
+                            span: Span { start, end: self.index },
                         },
                         token_type: TokenType::Note,
-                        source: String::from_utf8_lossy(&bytes[start..self.index].to_vec()).to_string(),
+                        source: String::from_utf8_lossy(&bytes[start..self.index]).to_string(),
+                        value: None,
                     };
                     self.tokens.push(token);
 
                     self.column += 2;
                     break;
                 }
-                self.column += 1;
+                if chr == '\t' {
+                    self.column = self.next_tab_stop(self.column);
+                } else {
+                    self.column += 1;
+                }
                 self.index += 1;
             }
             return true;
         } else if (bytes[self.index] as char == '/') && (bytes[self.index + 1] as char == '/') {
             let start = self.index;
-            self.index += 2;
             let column = self.column;
+            self.index += 2;
+            self.column += 2;
 
             loop {
-                if bytes.len() >= self.index {
-                    eprintln!("'//' Missing ending");
-                    std::process::exit(-1);
+                if self.index >= bytes.len() {
+                    // A comment on the last line does not need a trailing newline.
+                    let token = Token {
+                        loc: Location {
+                            file: String::from(&self.file),
+                            line: self.line,
+                            column,
+                            span: Span { start, end: self.index },
+                        },
+                        token_type: TokenType::Note,
+                        source: String::from_utf8_lossy(&bytes[start..self.index]).to_string(),
+                        value: None,
+                    };
+                    self.tokens.push(token);
+                    break;
                 }
 
+                // The newline itself is left for parse_new_line.
                 let chr = bytes[self.index] as char;
-                if chr == '\n' {
-                    self.index += 1;
+                if chr == '\n' || chr == '\r' {
                     let token = Token {
                         loc: Location {
                             file: String::from(&self.file),
                             line: self.line,
-                            column: column,
+                            column,
+                            span: Span { start, end: self.index },
                         },
                         token_type: TokenType::Note,
-                        source: String::from_utf8_lossy(&bytes[start..self.index].to_vec()).to_string(),
+                        source: String::from_utf8_lossy(&bytes[start..self.index]).to_string(),
+                        value: None,
                     };
                     self.tokens.push(token);
-
-                    self.line += 1;
-                    self.column = 1;
                     break;
                 }
                 self.column += 1;
@@ -237,29 +1294,41 @@ impl Lex {
         false
     }
 
+    // Accepts "\n", "\r\n" and a lone "\r" as one line break each.
     fn parse_new_line(&mut self, bytes: &[u8]) -> bool {
-        if !(bytes[self.index] as char == '\n') {
-            return false;
-        }
+        let len = match bytes[self.index] as char {
+            '\n' => 1,
+            '\r' if bytes.get(self.index + 1) == Some(&b'\n') => 2,
+            '\r' => 1,
+            _ => return false,
+        };
 
         let token = Token {
             loc: Location {
                 file: String::from(&self.file),
                 line: self.line,
                 column: self.column,
+                span: Span { start: self.index, end: self.index + len },
             },
             token_type: TokenType::NewLine,
-            source: String::from("\n"),
+            source: String::from_utf8_lossy(&bytes[self.index..(self.index + len)]).to_string(),
+            value: None,
         };
         self.tokens.push(token);
-        self.index += 1;
+        self.index += len;
         self.line += 1;
         self.column = 1;
         true
     }
 
+    // The column a tab at `column` moves to.
+    fn next_tab_stop(&self, column: usize) -> usize {
+        (column - 1) / self.tab_stop * self.tab_stop + self.tab_stop + 1
+    }
+
     fn parse_space(&mut self, bytes: &[u8]) -> bool {
-        if !(bytes[self.index] as char == ' ') {
+        let chr = bytes[self.index] as char;
+        if chr != ' ' && chr != '\t' {
             return false;
         }
 
@@ -268,32 +1337,39 @@ impl Lex {
                 file: String::from(&self.file),
                 line: self.line,
                 column: self.column,
+                span: Span { start: self.index, end: self.index + 1 },
             },
             token_type: TokenType::Space,
-            source: String::from(" "),
+            source: String::from(chr),
+            value: None,
         };
         self.tokens.push(token);
         self.index += 1;
-        self.column += 1;
+        if chr == '\t' {
+            // Tabs advance to the next tab stop, as GCC does for its diagnostics.
+            self.column = self.next_tab_stop(self.column);
+        } else {
+            self.column += 1;
+        }
         true
     }
 
     fn parse_string(&mut self, bytes: &[u8]) -> bool {
-        if !(bytes[self.index] as char == '\"') {
-            return false;
-        }
+        let (encoding, prefix) = match encoding_prefix(bytes, self.index, b'"') {
+            Some(prefix) => prefix,
+            None => return false,
+        };
 
         let mut skip = false;
         let start = self.index;
-        let mut column = self.column;
+        let mut column = self.column + prefix + 1;
         let mut line = self.line;
-        self.index += 1;
+        self.index += prefix + 1;
 
         loop {
             if bytes.len() <= self.index {
-                eprintln!("Error: \"Missing '\"' at the end\" at ({}:{}:{})",
-                    self.file, self.line, self.column);
-                std::process::exit(-1);
+                self.error(self.line, self.column, Span { start, end: bytes.len() }, String::from("Missing '\"' at the end"));
+                return true;
             }
 
             let chr = bytes[self.index] as char;
@@ -306,9 +1382,11 @@ impl Lex {
                                 file: String::from(&self.file),
                                 line: self.line,
                                 column: self.column,
+                                span: Span { start, end: self.index },
                             },
-                            token_type: TokenType::Str,
-                            source: String::from_utf8_lossy(&bytes[start..self.index].to_vec()).to_string(),
+                            token_type: TokenType::Str(encoding),
+                            source: String::from_utf8_lossy(&bytes[start..self.index]).to_string(),
+                            value: None,
                         };
                         self.tokens.push(token);
 
@@ -319,14 +1397,23 @@ impl Lex {
                         skip = false;
                     }
                 },
-                '\\' => {
+                '\\' if !skip => {
                     skip = true;
+                    if !matches!(bytes.get(self.index + 1), None | Some(b'\n') | Some(b'\r')) {
+                        if let Err((message, at)) = decode_escape(bytes, self.index, escape_max(encoding)) {
+                            self.error(line, column, Span { start: at, end: at + 2 }, message);
+                        }
+                    }
                 },
                 '\n' => {
                     skip = false;
                     line += 1;
                     column = 0;
                 },
+                '\t' => {
+                    skip = false;
+                    column = self.next_tab_stop(column) - 1;
+                },
                 _ => skip = false,
             }
             self.index += 1;
@@ -335,70 +1422,140 @@ impl Lex {
     }
 
     fn parse_char(&mut self, bytes: &[u8]) -> bool {
-        if !(bytes[self.index] as char == '\'') {
-            return false;
-        }
+        let (encoding, prefix) = match encoding_prefix(bytes, self.index, b'\'') {
+            Some(prefix) => prefix,
+            None => return false,
+        };
 
-        let mut skip = false;
         let start = self.index;
-        let mut column = self.column;
-        self.index += 1;
-        let mut max: usize = 2;
-
+        let mut index = start + prefix + 1;
+        let mut values = Vec::<u32>::new();
         loop {
-            if bytes.len() <= self.index {
-                eprintln!("Error: \"Missing '\'' at the end\" at ({}:{}:{})",
-                    self.file, self.line, self.column);
-                std::process::exit(-1);
-            }
-            if self.index > max {
-                eprintln!("Error: \"There can only be one character between \"''\"\" at ({}:{}:{})",
-                    self.file, self.line, self.column);
-                std::process::exit(-1);
-            }
-
-            let chr = bytes[self.index] as char;
+            let chr = if index < bytes.len() { bytes[index] as char } else { '\n' };
             match chr {
-                '\'' => {
-                    if !skip {
-                        self.index += 1;
-                        let token = Token {
-                            loc: Location {
-                                file: String::from(&self.file),
-                                line: self.line,
-                                column: self.column,
-                            },
-                            token_type: TokenType::Char,
-                            source: String::from_utf8_lossy(&bytes[start..self.index].to_vec()).to_string(),
-                        };
-                        self.tokens.push(token);
-
-                        self.column = column + 1;
-                        return true;
-                    } else {
-                        skip = false;
+                '\'' => break,
+                '\n' | '\r' => {
+                    self.error(self.line, self.column, Span { start, end: index }, String::from("Missing ''' at the end"));
+                    self.skip_char_literal(bytes, start, prefix);
+                    return true;
+                },
+                '\\' if index + 1 < bytes.len() && !matches!(bytes[index + 1], b'\n' | b'\r') => {
+                    match decode_escape(bytes, index, escape_max(encoding)) {
+                        Ok((value, next)) => {
+                            values.push(value);
+                            index = next;
+                        },
+                        Err((message, at)) => {
+                            self.error(self.line, self.column + at - start, Span { start: at, end: at + 2 }, message);
+                            self.skip_char_literal(bytes, start, prefix);
+                            return true;
+                        },
                     }
                 },
-                '\\' => {
-                    skip = true;
-                    max = 3;
+                _ if !chr.is_ascii() => {
+                    let end = index + 1 + bytes[index + 1..].iter().take_while(|byte| **byte & 0xc0 == 0x80).count();
+                    // Wide literals hold any character their type can represent, as its code point.
+                    let wide = match std::str::from_utf8(&bytes[index..end]).ok().and_then(|text| text.chars().next()) {
+                        Some(wide) if !matches!(encoding, Encoding::None | Encoding::Utf8) && wide as u32 <= escape_max(encoding) => Some(wide),
+                        _ => None,
+                    };
+                    if let Some(wide) = wide {
+                        values.push(wide as u32);
+                        index = end;
+                        continue;
+                    }
+                    self.error(self.line, self.column + index - start, Span { start: index, end }, format!("[{}] is not an ascii character", bytes[index]));
+                    self.skip_char_literal(bytes, start, prefix);
+                    return true;
                 },
                 _ => {
-                    skip = false;
-                    if !chr.is_ascii() {
-                        eprintln!("Error: \"[{}] is not an ascii character\" at ({}:{}:{})",
-                         chr as u8, self.file, self.line, column);
-                        std::process::exit(-1);
-                    }
+                    values.push(bytes[index] as u32);
+                    index += 1;
                 },
             }
-            self.index += 1;
-            column += 1;
         }
+        index += 1;
+
+        let multichar = encoding == Encoding::None && (2..=MAX_MULTICHAR).contains(&values.len());
+        if values.len() == 1 || multichar {
+            if multichar {
+                self.warning(self.line, self.column, Span { start, end: index }, String::from("Multi-character character constant"));
+            }
+            // Like GCC, pack the characters of 'ab' big-endian: 'a' << 8 | 'b'.
+            let value = values.iter().fold(0u32, |value, chr| value << 8 | chr);
+            let token = Token {
+                loc: Location {
+                    file: String::from(&self.file),
+                    line: self.line,
+                    column: self.column,
+                    span: Span { start, end: index },
+                },
+                token_type: TokenType::Char(encoding),
+                source: String::from_utf8_lossy(&bytes[start..index]).to_string(),
+                value: Some(LiteralValue::Char(value)),
+            };
+            self.tokens.push(token);
+        } else if values.is_empty() {
+            self.error(self.line, self.column, Span { start, end: index }, String::from("Empty character constant"));
+        } else if encoding == Encoding::None {
+            self.error(self.line, self.column, Span { start, end: index }, String::from("Character constant too long for its type"));
+        } else {
+            self.error(self.line, self.column, Span { start, end: index }, String::from("There can only be one character between \"''\""));
+        }
+
+        self.column += index - start;
+        self.index = index;
+        true
+    }
+
+    // Skips a broken character constant up to its closing quote or the end of the line.
+    fn skip_char_literal(&mut self, bytes: &[u8], start: usize, prefix: usize) {
+        let mut index = start + prefix + 1;
+        while index < bytes.len() && !matches!(bytes[index], b'\'' | b'\n' | b'\r') {
+            index += 1;
+        }
+        if index < bytes.len() && bytes[index] == b'\'' {
+            index += 1;
+        }
+
+        self.column += index - start;
+        self.index = index;
     }
 
     fn parse_keyword(&mut self, bytes: &[u8]) -> bool {
-        false
+        match bytes[self.index] as char {
+            'a'..='z' | 'A'..='Z' | '_' => (),
+            _ => return false,
+        }
+
+        let start = self.index;
+        let mut index = self.index;
+        while index < bytes.len() && (bytes[index].is_ascii_alphanumeric() || bytes[index] == b'_') {
+            index += 1;
+        }
+
+        let source = String::from_utf8_lossy(&bytes[start..index]).to_string();
+        let key_word = match keyword_from_str(&source) {
+            Some(key_word) => key_word,
+            None => return false,
+        };
+
+        let token = Token {
+            loc: Location {
+                file: String::from(&self.file),
+                line: self.line,
+                column: self.column,
+                span: Span { start, end: index },
+            },
+            token_type: TokenType::KeyWord(key_word),
+            source,
+            value: None,
+        };
+        self.tokens.push(token);
+
+        self.column += index - start;
+        self.index = index;
+        true
     }
 
     fn parse_identifier(&mut self, bytes: &[u8]) -> bool {
@@ -409,56 +1566,123 @@ impl Lex {
 
         let start = self.index;
         let mut index = self.index;
-        let mut column = self.column;
+        while index < bytes.len() && (bytes[index].is_ascii_alphanumeric() || bytes[index] == b'_') {
+            index += 1;
+        }
 
-        loop {
-            if bytes.len() <= index {
-                let token = Token {
-                    loc: Location {
-                        file: String::from(&self.file),
-                        line: self.line,
-                        column: self.column,
-                    },
-                    token_type: TokenType::Identifier,
-                    source: String::from_utf8_lossy(&bytes[start..index].to_vec()).to_string(),
-                };
-                self.tokens.push(token);
-
-                self.index = index;
-                self.column = column;
-                return true;
-            }
+        let token = Token {
+            loc: Location {
+                file: String::from(&self.file),
+                line: self.line,
+                column: self.column,
+                span: Span { start, end: index },
+            },
+            token_type: TokenType::Identifier,
+            source: String::from_utf8_lossy(&bytes[start..index]).to_string(),
+            value: None,
+        };
+        self.tokens.push(token);
 
-            let chr = bytes[index] as char;
-            match chr {
-                ' ' | ';' | ',' | '\t' | '\n' | '\x0C' | '\r' | '!'..='/' | ':'..='@' | '['..='`' | '{'..='~' => {
-                    if chr != '_' {
-                        let token = Token {
-                            loc: Location {
-                                file: String::from(&self.file),
-                                line: self.line,
-                                column: self.column,
-                            },
-                            token_type: TokenType::Identifier,
-                            source: String::from_utf8_lossy(&bytes[start..index].to_vec()).to_string(),
-                        };
-                        self.tokens.push(token);
+        self.column += index - start;
+        self.index = index;
+        true
+    }
 
-                        self.index = index;
-                        self.column = column;
-                        return true;
-                    }
-                },
-                'a'..='z' | 'A'..='Z' | '_' | '0'..='9' => (),
-                _ => {
-                    eprintln!("Error: \"'{}' cannot be an identifier\" at ({}:{}:{})",
-                        chr, self.file, self.line, self.column);
-                    std::process::exit(-1);
-                },
-            }
+    // True if only whitespace and comments come before the current position on its line.
+    fn at_line_start(&self) -> bool {
+        match self.tokens.iter().rev().find(|token| !matches!(token.token_type, TokenType::Space | TokenType::Note)) {
+            Some(token) => token.token_type == TokenType::NewLine,
+            None => true,
+        }
+    }
+
+    // A '#' at the start of a line begins a directive. The token takes in the
+    // directive name too, so "#include" and "# define" are single tokens. The
+    // digraph "%:" works the same way.
+    fn parse_directive(&mut self, bytes: &[u8]) -> bool {
+        let hash_len = if bytes[self.index] == b'#' {
+            1
+        } else if bytes[self.index..].starts_with(b"%:") && !bytes[self.index..].starts_with(b"%:%:") {
+            2
+        } else {
+            return false;
+        };
+        if !self.directives || !self.at_line_start() {
+            return false;
+        }
+
+        let start = self.index;
+        let mut index = start + hash_len;
+        while index < bytes.len() && matches!(bytes[index], b' ' | b'\t') {
             index += 1;
-            column += 1;
         }
+        let name_start = index;
+        while index < bytes.len() && (bytes[index].is_ascii_alphanumeric() || bytes[index] == b'_') {
+            index += 1;
+        }
+        if index == name_start {
+            index = start + hash_len;
+        }
+
+        let token = Token {
+            loc: Location {
+                file: String::from(&self.file),
+                line: self.line,
+                column: self.column,
+                span: Span { start, end: index },
+            },
+            token_type: TokenType::Directive(directive_from_str(&String::from_utf8_lossy(&bytes[name_start..index]))),
+            source: String::from_utf8_lossy(&bytes[start..index]).to_string(),
+            value: None,
+        };
+        self.tokens.push(token);
+
+        for &byte in &bytes[start..index] {
+            if byte == b'\t' {
+                self.column = self.next_tab_stop(self.column);
+            } else {
+                self.column += 1;
+            }
+        }
+        self.index = index;
+        true
+    }
+
+    // Inside "#include" a header name is taken verbatim up to its closing
+    // delimiter, since '/' and '.' are not operators and '\' not an escape there.
+    fn parse_header_name(&mut self, bytes: &[u8]) -> bool {
+        let close = match bytes[self.index] {
+            b'<' => b'>',
+            b'"' => b'"',
+            _ => return false,
+        };
+        match self.tokens.iter().rev().find(|token| !matches!(token.token_type, TokenType::Space | TokenType::Note)) {
+            Some(token) if token.token_type == TokenType::Directive(DirectiveKind::Include) => (),
+            _ => return false,
+        }
+
+        let start = self.index;
+        let end = match bytes[start + 1..].iter().position(|byte| *byte == close || *byte == b'\n') {
+            Some(offset) if bytes[start + 1 + offset] == close => start + offset + 2,
+            _ => return false,
+        };
+
+        let token = Token {
+            loc: Location {
+                file: String::from(&self.file),
+                line: self.line,
+                column: self.column,
+                span: Span { start, end },
+            },
+            token_type: TokenType::HeaderName,
+            source: String::from_utf8_lossy(&bytes[start..end]).to_string(),
+            value: None,
+        };
+        self.tokens.push(token);
+
+        self.column += end - start;
+        self.index = end;
+        true
     }
 
     fn parse_operator(&mut self, bytes: &[u8]) -> bool {
@@ -466,232 +1690,1698 @@ impl Lex {
             return false;
         }
 
+        // No operator starts with a punctuator or a digraph, so these never
+        // shadow a longer match.
+        let (token_type, len) = if let Some((digraph, digraph_type)) = DIGRAPHS.iter().find(|(digraph, _)| bytes[self.index..].starts_with(digraph.as_bytes())) {
+            (digraph_type.clone(), digraph.len())
+        } else if let Some(punct) = punct_from_char(bytes[self.index] as char) {
+            (TokenType::Punct(punct), 1)
+        } else if let Some((op, op_type)) = OPERATORS.iter().find(|(op, _)| bytes[self.index..].starts_with(op.as_bytes())) {
+            (TokenType::Operator(*op_type), op.len())
+        } else {
+            return false;
+        };
+
         let token = Token {
             loc: Location {
                 file: String::from(&self.file),
                 line: self.line,
                 column: self.column,
+                span: Span { start: self.index, end: self.index + len },
             },
-            token_type: TokenType::Operator(OperatorType::OpEq),
-            source: String::from_utf8_lossy(&bytes[self.index..(self.index + 1)].to_vec()).to_string(),
+            token_type,
+            source: String::from_utf8_lossy(&bytes[self.index..self.index + len]).to_string(),
+            value: None,
         };
         self.tokens.push(token);
 
-        self.index += 1;
-        self.column += 1;
-        return true;
+        self.index += len;
+        self.column += len;
+        true
+    }
+
+    // Reports a malformed number and skips the rest of it, including any
+    // letters, dots and exponent signs that would otherwise start new tokens.
+    fn number_error(&mut self, bytes: &[u8], start: usize, column: usize, message: &str) -> bool {
+        let mut index = start + 1;
+        while index < bytes.len() {
+            match bytes[index] {
+                b'0'..=b'9' | b'a'..=b'z' | b'A'..=b'Z' | b'_' | b'.' => (),
+                b'+' | b'-' if matches!(bytes[index - 1], b'e' | b'E' | b'p' | b'P') => (),
+                b'\'' if self.digit_separators && bytes[index - 1].is_ascii_alphanumeric() => (),
+                _ => break,
+            }
+            index += 1;
+        }
+
+        self.error(self.line, column, Span { start, end: index }, String::from(message));
+        self.column += index - start;
+        self.index = index;
+        true
     }
 
     fn parse_number(&mut self, bytes: &[u8]) -> bool {
-        let mut f_flag = false;
-        let mut bin_num = false;
-        let mut oct_num = false;
-        let mut hex_num = false;
-        let mut dec_num = false;
-        let mut err_token = false;
+        let at = |index: usize| -> char {
+            if index < bytes.len() {
+                bytes[index] as char
+            } else {
+                '\0'
+            }
+        };
 
-        match bytes[self.index] as char {
+        match at(self.index) {
             '0'..='9' => (),
+            '.' if at(self.index + 1).is_ascii_digit() => (),
             _ => return false,
         }
 
+        // With digit separators a quote may sit between two digits of a sequence.
+        let separators = self.digit_separators;
+        let digits_end = |mut index: usize, radix: u32| -> usize {
+            let begin = index;
+            while at(index).is_digit(radix) || (separators && at(index) == '\'' && index > begin && at(index + 1).is_digit(radix)) {
+                index += 1;
+            }
+            index
+        };
+        let misplaced_separator = |index: usize| separators && at(index) == '\'';
+
         let start = self.index;
         let mut index = self.index;
-        let mut column = self.column;
-
-        loop {
-            if bytes.len() <= index {
-                if err_token {
-                    eprintln!("Error: \"Identifiers cannot start with a number\" at ({}:{}:{})",
-                            self.file, self.line, self.column);
-                    std::process::exit(-1);
-                } else {
-                    let token = Token {
-                        loc: Location {
-                            file: String::from(&self.file),
-                            line: self.line,
-                            column: self.column,
-                        },
-                        token_type: TokenType::Number,
-                        source: String::from_utf8_lossy(&bytes[start..index].to_vec()).to_string(),
-                    };
-                    self.tokens.push(token);
-
-                    self.index = index;
-                    self.column = column;
-                    return true;
-                }
+        let mut radix = 10;
+        if at(index) == '0' {
+            match at(index + 1) {
+                'x' | 'X' => {
+                    radix = 16;
+                    index += 2;
+                },
+                'b' | 'B' => {
+                    radix = 2;
+                    index += 2;
+                },
+                _ => radix = 8,
             }
+        }
 
-            let chr = bytes[index] as char;
-            match chr {
-                '0' => {
-                    if !err_token {
-                        if f_flag {
-                            err_token = true;
-                        } else if start == index {
-                            oct_num = true;
-                        }
-                    }
-                },
-                '1' => {
-                    if !err_token {
-                        if f_flag {
-                            err_token = true;
-                        }else if !bin_num && !oct_num && !hex_num {
-                            dec_num = true;
-                        }
-                    }
-                },
-                '2'..='7' => {
-                    if !err_token {
-                        if f_flag {
-                            err_token = true;
-                        } else {
-                            if !bin_num && !oct_num && !hex_num {
-                                dec_num = true;
-                            }
-                            if bin_num {
-                                eprintln!("Error: \"The number of binary values exceeds 1\" at ({}:{}:{})",
-                                    self.file, self.line, column);
-                                std::process::exit(-1);
-                            }
-                        }
-                    }
-                },
-                '8'..='9' => {
-                    if !err_token {
-                        if f_flag {
-                            err_token = true;
-                        } else {
-                            if !bin_num && !oct_num && !hex_num {
-                                dec_num = true;
-                            }
-                            if bin_num {
-                                eprintln!("Error: \"The number of binary values exceeds 1\" at ({}:{}:{})",
-                                    self.file, self.line, column);
-                                std::process::exit(-1);
-                            } else if oct_num {
-                                eprintln!("Error: \"The number of octal values exceeds 7\" at ({}:{}:{})",
-                                    self.file, self.line, column);
-                                std::process::exit(-1);
-                            }
-                        }
-                    }
-                },
-                'a' | 'A' | 'c'..='e' | 'C'..='E'  => {
-                    if !err_token {
-                        if f_flag {
-                            err_token = true;
-                        } else {
-                            if bin_num {
-                                eprintln!("Error: \"The number of binary values exceeds 1\" at ({}:{}:{})",
-                                    self.file, self.line, column);
-                                std::process::exit(-1);
-                            } else if oct_num {
-                                eprintln!("Error: \"The number of octal values exceeds 7\" at ({}:{}:{})",
-                                    self.file, self.line, column);
-                                std::process::exit(-1);
-                            } else if dec_num {
-                                eprintln!("Error: \"The number of decimal values exceeds 9\" at ({}:{}:{})",
-                                        self.file, self.line, column);
-                                std::process::exit(-1);
-                            } else if !hex_num {
-                                err_token = true;
-                            }
-                        }
-                    }
-                },
-                'x' | 'X' => {
-                    if !err_token {
-                        if !(oct_num && (index - start == 1)) {
-                            err_token = true;
-                        } else {
-                            oct_num = false;
-                            hex_num = true;
-                        }
-                    }
-                },
-                'b' | 'B' => {
-                    if !err_token {
-                        if oct_num {
-                            if index - start == 1 {
-                                oct_num = false;
-                                bin_num = true;
-                            } else {
-                                eprintln!("Error: \"The number of octal values exceeds 7\" at ({}:{}:{})",
-                                    self.file, self.line, column);
-                                std::process::exit(-1);
-                            }
-                        } else if bin_num {
-                            eprintln!("Error: \"The number of binary values exceeds 1\" at ({}:{}:{})",
-                                    self.file, self.line, column);
-                            std::process::exit(-1);
-                        } else if dec_num {
-                            eprintln!("Error: \"The number of decimal values exceeds 9\" at ({}:{}:{})",
-                                    self.file, self.line, column);
-                            std::process::exit(-1);
-                        } else if !hex_num {
-                            err_token = true;
-                        }
-                    }
-                },
-                ' ' | ';' | ',' | '\t' | '\n' | '\x0C' | '\r' | '!'..='/' | ':'..='@' | '['..='`' | '{'..='~' => {
-                    if err_token {
-                        eprintln!("Error: \"Identifiers cannot start with a number\" at ({}:{}:{})",
-                                self.file, self.line, self.column);
-                        std::process::exit(-1);
-                    } else {
-                        let token = Token {
-                            loc: Location {
-                                file: String::from(&self.file),
-                                line: self.line,
-                                column: self.column,
-                            },
-                            token_type: TokenType::Number,
-                            source: String::from_utf8_lossy(&bytes[start..index].to_vec()).to_string(),
-                        };
-                        self.tokens.push(token);
+        // Binary and octal digits are validated once we know the literal is not a float,
+        // since "09.5" is a perfectly good decimal floating constant.
+        let digits_start = index;
+        index = digits_end(index, if radix == 16 { 16 } else { 10 });
+        let mut digits = index - digits_start;
+        if radix == 2 && digits == 0 {
+            return self.number_error(bytes, start, self.column, "Missing digits after binary prefix");
+        }
+        if misplaced_separator(index) {
+            return self.number_error(bytes, start, self.column + index - start, "Digit separator must be between digits");
+        }
+        if radix == 16 && digits == 0 && at(index) != '.' {
+            if at(index).is_ascii_alphanumeric() || at(index) == '_' {
+                return self.number_error(bytes, start, self.column + index - start, &format!("Invalid digit '{}' in hexadecimal constant", at(index)));
+            }
+            return self.number_error(bytes, start, self.column, "Missing digits after hexadecimal prefix");
+        }
 
-                        self.index = index;
-                        self.column = column;
-                        return true;
+        let mut float = false;
+        if at(index) == '.' {
+            if radix == 2 {
+                return self.number_error(bytes, start, self.column + index - start, "Binary constants cannot have a fractional part");
+            }
+            float = true;
+            index += 1;
+            let fraction_start = index;
+            index = digits_end(index, if radix == 16 { 16 } else { 10 });
+            digits += index - fraction_start;
+            if misplaced_separator(index) {
+                return self.number_error(bytes, start, self.column + index - start, "Digit separator must be between digits");
+            }
+            if radix == 16 && digits == 0 {
+                return self.number_error(bytes, start, self.column, "Hexadecimal floating constant has no digits");
+            }
+            if at(index) == '.' {
+                return self.number_error(bytes, start, self.column + index - start, "Too many decimal points in number");
+            }
+        }
+
+        let exponent = matches!((radix, at(index)), (16, 'p' | 'P') | (8 | 10, 'e' | 'E'));
+        if exponent {
+            float = true;
+            index += 1;
+            if at(index) == '+' || at(index) == '-' {
+                index += 1;
+            }
+            let exponent_start = index;
+            index = digits_end(index, 10);
+            if misplaced_separator(index) {
+                return self.number_error(bytes, start, self.column + index - start, "Digit separator must be between digits");
+            }
+            if index == exponent_start {
+                return self.number_error(bytes, start, self.column + index - start, "Exponent has no digits");
+            }
+            if matches!((radix, at(index)), (16, 'p' | 'P') | (8 | 10, 'e' | 'E')) {
+                return self.number_error(bytes, start, self.column + index - start, "Floating constant has more than one exponent");
+            }
+        } else if radix == 16 && float {
+            return self.number_error(bytes, start, self.column, "Hexadecimal floating constant requires an exponent");
+        }
+
+        if !float {
+            for i in digits_start..index {
+                match (radix, at(i)) {
+                    (2, '2'..='9') => return self.number_error(bytes, start, self.column + i - start, &format!("Invalid digit '{}' in binary constant", at(i))),
+                    (8, '8'..='9') => return self.number_error(bytes, start, self.column + i - start, &format!("Invalid digit '{}' in octal constant", at(i))),
+                    _ => (),
+                }
+            }
+        }
+
+        let suffix_start = index;
+        while at(index).is_ascii_alphanumeric() || at(index) == '_' {
+            index += 1;
+        }
+        let suffix = String::from_utf8_lossy(&bytes[suffix_start..index]).to_string();
+        let token_type = if float {
+            match suffix.as_str() {
+                "" => Some(TokenType::FlotNumber(FloatSuffix::None)),
+                "f" | "F" => Some(TokenType::FlotNumber(FloatSuffix::F)),
+                "l" | "L" => Some(TokenType::FlotNumber(FloatSuffix::L)),
+                _ => None,
+            }
+        } else {
+            int_suffix_from_str(&suffix).map(TokenType::Number)
+        };
+        let token_type = match token_type {
+            Some(token_type) => token_type,
+            None => {
+                let message = format!(
+                    "Invalid suffix '{}' on {} constant",
+                    suffix,
+                    if float { "floating" } else { "integer" }
+                );
+                return self.number_error(bytes, start, self.column + suffix_start - start, &message);
+            },
+        };
+
+        let text: String = String::from_utf8_lossy(&bytes[start..suffix_start]).chars().filter(|chr| *chr != '\'').collect();
+        let value = match number_value(&text, radix, float) {
+            Some(value) => value,
+            None if float => return self.number_error(bytes, start, self.column, "Floating constant is out of range"),
+            None => return self.number_error(bytes, start, self.column, "Integer constant is too large"),
+        };
+
+        let token = Token {
+            loc: Location {
+                file: String::from(&self.file),
+                line: self.line,
+                column: self.column,
+                span: Span { start, end: index },
+            },
+            token_type,
+            source: String::from_utf8_lossy(&bytes[start..index]).to_string(),
+            value: Some(value),
+        };
+        self.tokens.push(token);
+
+        self.column += index - start;
+        self.index = index;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    fn lex_source(src: &str) -> Lex {
+        let mut lex = Lex::from_source("test.c", src);
+        lex.parse().unwrap();
+        lex
+    }
+
+    fn lex_errors(src: &str) -> Vec<(String, usize, usize)> {
+        let mut lex = Lex::from_source("test.c", src);
+        lex.parse()
+            .unwrap_err()
+            .into_iter()
+            .map(|error| (error.message, error.location.line, error.location.column))
+            .collect()
+    }
+
+    #[test]
+    fn lex_file() {
+        let path = std::env::temp_dir().join(format!("ncc-lex-{}.c", std::process::id()));
+        fs::write(&path, "int x;").unwrap();
+
+        let mut lex = Lex::new(path.to_str().unwrap()).unwrap();
+        lex.parse().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(lex.tokens.len(), 5);
+        assert_eq!(lex.tokens[0].loc.file, path.to_str().unwrap());
+
+        let missing = Lex::new(path.to_str().unwrap()).err().unwrap();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn line_comment_at_end_of_file() {
+        let lex = lex_source("int x; // comment");
+        let last = &lex.tokens[lex.tokens.len() - 2];
+
+        assert!(matches!(last.token_type, TokenType::Note));
+        assert_eq!(last.source, "// comment");
+        assert_eq!(last.loc.line, 1);
+        assert_eq!(last.loc.column, 8);
+    }
+
+    fn token_types(lex: &Lex) -> Vec<&TokenType> {
+        lex.tokens
+            .iter()
+            .filter(|token| !matches!(token.token_type, TokenType::Note | TokenType::NewLine | TokenType::Space | TokenType::Eof))
+            .map(|token| &token.token_type)
+            .collect()
+    }
+
+    #[test]
+    fn keywords() {
+        let lex = lex_source("void f(char c, int i, float x, double d);\nint interface;");
+
+        assert_eq!(
+            token_types(&lex),
+            vec![
+                &TokenType::KeyWord(KeyWordType::KVoid),
+                &TokenType::Identifier,
+                &TokenType::Punct(PunctType::LParen),
+                &TokenType::KeyWord(KeyWordType::KChar),
+                &TokenType::Identifier,
+                &TokenType::Punct(PunctType::Comma),
+                &TokenType::KeyWord(KeyWordType::KInt),
+                &TokenType::Identifier,
+                &TokenType::Punct(PunctType::Comma),
+                &TokenType::KeyWord(KeyWordType::KFloat),
+                &TokenType::Identifier,
+                &TokenType::Punct(PunctType::Comma),
+                &TokenType::KeyWord(KeyWordType::KDouble),
+                &TokenType::Identifier,
+                &TokenType::Punct(PunctType::RParen),
+                &TokenType::Punct(PunctType::Semicolon),
+                &TokenType::KeyWord(KeyWordType::KInt),
+                &TokenType::Identifier,
+                &TokenType::Punct(PunctType::Semicolon),
+            ]
+        );
+        assert_eq!(lex.tokens[0].to_string(), "'void' [KeyWord(KVoid)] Loc:(test.c:1:1)");
+    }
+    #[test]
+    fn keywords_are_case_sensitive() {
+        let lex = lex_source("int x; float y;\nInt z;");
+        let types = token_types(&lex);
+
+        assert_eq!(types[0], &TokenType::KeyWord(KeyWordType::KInt));
+        assert_eq!(types[3], &TokenType::KeyWord(KeyWordType::KFloat));
+        assert_eq!(types[6], &TokenType::Identifier);
+    }
+    #[test]
+    fn c89_keywords() {
+        let keywords = [
+            ("auto", KeyWordType::KAuto),
+            ("break", KeyWordType::KBreak),
+            ("case", KeyWordType::KCase),
+            ("char", KeyWordType::KChar),
+            ("const", KeyWordType::KConst),
+            ("continue", KeyWordType::KContinue),
+            ("default", KeyWordType::KDefault),
+            ("do", KeyWordType::KDo),
+            ("double", KeyWordType::KDouble),
+            ("else", KeyWordType::KElse),
+            ("enum", KeyWordType::KEnum),
+            ("extern", KeyWordType::KExtern),
+            ("float", KeyWordType::KFloat),
+            ("for", KeyWordType::KFor),
+            ("goto", KeyWordType::KGoto),
+            ("if", KeyWordType::KIf),
+            ("int", KeyWordType::KInt),
+            ("long", KeyWordType::KLong),
+            ("register", KeyWordType::KRegister),
+            ("return", KeyWordType::KReturn),
+            ("short", KeyWordType::KShort),
+            ("signed", KeyWordType::KSigned),
+            ("sizeof", KeyWordType::KSizeof),
+            ("static", KeyWordType::KStatic),
+            ("struct", KeyWordType::KStruct),
+            ("switch", KeyWordType::KSwitch),
+            ("typedef", KeyWordType::KTypedef),
+            ("union", KeyWordType::KUnion),
+            ("unsigned", KeyWordType::KUnsigned),
+            ("void", KeyWordType::KVoid),
+            ("volatile", KeyWordType::KVolatile),
+            ("while", KeyWordType::KWhile),
+        ];
+        let src: Vec<&str> = keywords.iter().map(|(word, _)| *word).collect();
+        let lex = lex_source(&src.join(" "));
+        let types = token_types(&lex);
+
+        assert_eq!(types.len(), keywords.len());
+        for (token_type, (_, key_word)) in types.into_iter().zip(keywords) {
+            assert_eq!(token_type, &TokenType::KeyWord(key_word));
+        }
+    }
+    #[test]
+    fn operators_use_longest_match() {
+        let lex = lex_source("a == b; i++; p->x; a <<= 2; a >>= b; a >>> b; a<=b");
+        let ops: Vec<&Token> = lex
+            .tokens
+            .iter()
+            .filter(|token| matches!(token.token_type, TokenType::Operator(_)))
+            .collect();
+        let expected = [
+            ("==", OperatorType::OpEq),
+            ("++", OperatorType::OpInc),
+            ("->", OperatorType::OpArrow),
+            ("<<=", OperatorType::OpShlAssign),
+            (">>=", OperatorType::OpShrAssign),
+            (">>", OperatorType::OpShr),
+            (">", OperatorType::OpGt),
+            ("<=", OperatorType::OpLe),
+        ];
+
+        assert_eq!(ops.len(), expected.len());
+        for (token, (source, op_type)) in ops.into_iter().zip(expected) {
+            assert_eq!(token.source, source);
+            assert_eq!(token.token_type, TokenType::Operator(op_type));
+        }
+
+        // "a <<= 2" puts the operator at column 22 and the 2 right after it.
+        let shl_assign = lex.tokens.iter().find(|token| token.source == "<<=").unwrap();
+        assert_eq!(shl_assign.loc.column, 22);
+        let two = lex.tokens.iter().find(|token| token.source == "2").unwrap();
+        assert_eq!(two.loc.column, 26);
+    }
+    #[test]
+    fn keywords_take_priority_over_identifiers() {
+        let lex = lex_source("if (x) return returned; else while (if_) while1;");
+        let types = token_types(&lex);
+
+        assert_eq!(types[0], &TokenType::KeyWord(KeyWordType::KIf));
+        assert_eq!(types[4], &TokenType::KeyWord(KeyWordType::KReturn));
+        assert_eq!(types[5], &TokenType::Identifier);
+        assert_eq!(types[7], &TokenType::KeyWord(KeyWordType::KElse));
+        assert_eq!(types[8], &TokenType::KeyWord(KeyWordType::KWhile));
+        assert_eq!(types[10], &TokenType::Identifier);
+        assert_eq!(types[12], &TokenType::Identifier);
+    }
+    #[test]
+    fn c99_keywords() {
+        let keywords = [
+            ("inline", KeyWordType::KInline),
+            ("restrict", KeyWordType::KRestrict),
+            ("_Bool", KeyWordType::KBool),
+            ("_Complex", KeyWordType::KComplex),
+            ("_Imaginary", KeyWordType::KImaginary),
+        ];
+
+        for (word, key_word) in keywords {
+            let lex = lex_source(word);
+            assert_eq!(token_types(&lex), vec![&TokenType::KeyWord(key_word)]);
+        }
+        assert_eq!(token_types(&lex_source("_bool")), vec![&TokenType::Identifier]);
+    }
+    fn operator_types(lex: &Lex) -> Vec<OperatorType> {
+        lex.tokens
+            .iter()
+            .filter_map(|token| match token.token_type {
+                TokenType::Operator(op_type) => Some(op_type),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn expression_operators() {
+        let lex = lex_source("r = a + b - c * d / e % f < g > h <= i >= j == k != l && !m || n & o | p ^ ~q << s >> t ? u->v : w.x");
+
+        assert_eq!(
+            operator_types(&lex),
+            vec![
+                OperatorType::OpAssign,
+                OperatorType::OpAdd,
+                OperatorType::OpSub,
+                OperatorType::OpMul,
+                OperatorType::OpDiv,
+                OperatorType::OpMod,
+                OperatorType::OpLt,
+                OperatorType::OpGt,
+                OperatorType::OpLe,
+                OperatorType::OpGe,
+                OperatorType::OpEq,
+                OperatorType::OpNe,
+                OperatorType::OpLogAnd,
+                OperatorType::OpLogNot,
+                OperatorType::OpLogOr,
+                OperatorType::OpBitAnd,
+                OperatorType::OpBitOr,
+                OperatorType::OpBitXor,
+                OperatorType::OpBitNot,
+                OperatorType::OpShl,
+                OperatorType::OpShr,
+                OperatorType::OpQuestion,
+                OperatorType::OpArrow,
+                OperatorType::OpColon,
+                OperatorType::OpDot,
+            ]
+        );
+    }
+    #[test]
+    fn comparison_operators() {
+        let lex = lex_source("a<=b");
+        assert_eq!(operator_types(&lex), vec![OperatorType::OpLe]);
+        assert_eq!(lex.tokens[1].source, "<=");
+        assert_eq!(lex.tokens[2].loc.column, 4);
+
+        let lex = lex_source("a < =b");
+        assert_eq!(operator_types(&lex), vec![OperatorType::OpLt, OperatorType::OpAssign]);
+
+        let lex = lex_source("a==b==c");
+        assert_eq!(operator_types(&lex), vec![OperatorType::OpEq, OperatorType::OpEq]);
+
+        let lex = lex_source("a!=b>=c>d");
+        assert_eq!(
+            operator_types(&lex),
+            vec![OperatorType::OpNe, OperatorType::OpGe, OperatorType::OpGt]
+        );
+    }
+    #[test]
+    fn every_operator_spelling() {
+        for (op, op_type) in OPERATORS {
+            let lex = lex_source(&format!("a {} b", op));
+            let token = &lex.tokens[2];
+
+            assert_eq!(token.source, op);
+            assert_eq!(token.token_type, TokenType::Operator(op_type));
+            assert_eq!(lex.tokens[3].loc.column, 3 + op.len());
+        }
+    }
+
+    #[test]
+    fn operators_delimit_numbers() {
+        let lex = lex_source("x=0x1f<<3+07-0b101;");
+        let types = token_types(&lex);
+
+        assert_eq!(
+            types,
+            vec![
+                &TokenType::Identifier,
+                &TokenType::Operator(OperatorType::OpAssign),
+                &TokenType::Number(IntSuffix::None),
+                &TokenType::Operator(OperatorType::OpShl),
+                &TokenType::Number(IntSuffix::None),
+                &TokenType::Operator(OperatorType::OpAdd),
+                &TokenType::Number(IntSuffix::None),
+                &TokenType::Operator(OperatorType::OpSub),
+                &TokenType::Number(IntSuffix::None),
+                &TokenType::Punct(PunctType::Semicolon),
+            ]
+        );
+    }
+    #[test]
+    fn line_comment_before_newline() {
+        let lex = lex_source("int x; // trailing\nint y;");
+        let note = lex.tokens.iter().position(|token| token.token_type == TokenType::Note).unwrap();
+
+        assert_eq!(lex.tokens[note].source, "// trailing");
+        assert_eq!(lex.tokens[note + 1].token_type, TokenType::NewLine);
+        assert_eq!(lex.tokens[note + 1].loc.column, 19);
+
+        let last = &lex.tokens[lex.tokens.len() - 2];
+        assert_eq!(last.source, ";");
+        assert_eq!(last.loc.line, 2);
+        assert_eq!(last.loc.column, 6);
+    }
+    #[test]
+    fn logical_operators() {
+        let lex = lex_source("x && y || !z");
+        assert_eq!(
+            operator_types(&lex),
+            vec![OperatorType::OpLogAnd, OperatorType::OpLogOr, OperatorType::OpLogNot]
+        );
+
+        let lex = lex_source("a & b | c");
+        assert_eq!(operator_types(&lex), vec![OperatorType::OpBitAnd, OperatorType::OpBitOr]);
+
+        let lex = lex_source("a&&&b||||c");
+        assert_eq!(
+            operator_types(&lex),
+            vec![
+                OperatorType::OpLogAnd,
+                OperatorType::OpBitAnd,
+                OperatorType::OpLogOr,
+                OperatorType::OpLogOr,
+            ]
+        );
+    }
+    #[test]
+    fn floating_constants() {
+        let cases = [
+            ("3.14", FloatSuffix::None),
+            ("1.", FloatSuffix::None),
+            (".5", FloatSuffix::None),
+            ("0.5f", FloatSuffix::F),
+            ("1.0e-9", FloatSuffix::None),
+            ("1.5E+3", FloatSuffix::None),
+            ("1e10", FloatSuffix::None),
+            ("2.0L", FloatSuffix::L),
+            ("09.5", FloatSuffix::None),
+            ("0x1.8p+1", FloatSuffix::None),
+            ("0X.4P-2f", FloatSuffix::F),
+            ("0x1p3", FloatSuffix::None),
+        ];
+
+        for (src, suffix) in cases {
+            let lex = lex_source(src);
+            assert_eq!(lex.tokens.len(), 2, "{}", src);
+            assert_eq!(lex.tokens[0].token_type, TokenType::FlotNumber(suffix), "{}", src);
+            assert_eq!(lex.tokens[0].source, src);
+        }
+    }
+
+    #[test]
+    fn integer_constants() {
+        for src in ["0", "42", "01234567", "0xaBcdef", "0b10101"] {
+            let lex = lex_source(src);
+            assert_eq!(lex.tokens.len(), 2, "{}", src);
+            assert_eq!(lex.tokens[0].token_type, TokenType::Number(IntSuffix::None), "{}", src);
+            assert_eq!(lex.tokens[0].source, src);
+        }
+    }
+
+    #[test]
+    fn floats_in_context() {
+        let lex = lex_source("double x = 3.4; s.field");
+        let types = token_types(&lex);
+
+        assert_eq!(types[3], &TokenType::FlotNumber(FloatSuffix::None));
+        assert_eq!(lex.tokens.iter().find(|token| token.source == "3.4").unwrap().loc.column, 12);
+        assert_eq!(types[5], &TokenType::Identifier);
+        assert_eq!(types[6], &TokenType::Operator(OperatorType::OpDot));
+        assert_eq!(types[7], &TokenType::Identifier);
+    }
+    #[test]
+    fn increment_and_decrement() {
+        let lex = lex_source("i++; --j;");
+        assert_eq!(
+            operator_types(&lex),
+            vec![
+                OperatorType::OpInc,
+                OperatorType::OpDec,
+            ]
+        );
+        assert_eq!(lex.tokens[1].to_string(), "'++' [Operator(OpInc)] Loc:(test.c:1:2)");
+
+        let lex = lex_source("a+++b");
+        assert_eq!(operator_types(&lex), vec![OperatorType::OpInc, OperatorType::OpAdd]);
+
+        let lex = lex_source("- -a");
+        assert_eq!(operator_types(&lex), vec![OperatorType::OpSub, OperatorType::OpSub]);
+    }
+    #[test]
+    fn tabs_advance_to_next_tab_stop() {
+        let lex = lex_source("\tint x;\nab\t1.5;\n  \t \ty");
+        let at = |source: &str| -> (usize, usize) {
+            let token = lex.tokens.iter().find(|token| token.source == source).unwrap();
+            (token.loc.line, token.loc.column)
+        };
+
+        assert_eq!(lex.tokens[0].token_type, TokenType::Space);
+        assert_eq!(lex.tokens[0].source, "\t");
+        assert_eq!(at("int"), (1, 9));
+        assert_eq!(at("x"), (1, 13));
+        assert_eq!(at("1.5"), (2, 9));
+        assert_eq!(at("y"), (3, 17));
+    }
+    #[test]
+    fn compound_assignment() {
+        let cases = [
+            ("+=", OperatorType::OpAddAssign),
+            ("-=", OperatorType::OpSubAssign),
+            ("*=", OperatorType::OpMulAssign),
+            ("/=", OperatorType::OpDivAssign),
+            ("%=", OperatorType::OpModAssign),
+            ("&=", OperatorType::OpAndAssign),
+            ("|=", OperatorType::OpOrAssign),
+            ("^=", OperatorType::OpXorAssign),
+        ];
+
+        for (op, op_type) in cases {
+            let lex = lex_source(&format!("x{}y", op));
+            assert_eq!(lex.tokens.len(), 4);
+            assert_eq!(lex.tokens[1].token_type, TokenType::Operator(op_type));
+            assert_eq!(lex.tokens[1].source, op);
+            assert_eq!(lex.tokens[2].loc.column, 4);
+        }
+    }
+    fn locations(lex: &Lex) -> Vec<(usize, usize)> {
+        lex.tokens
+            .iter()
+            .filter(|token| token.token_type != TokenType::NewLine)
+            .map(|token| (token.loc.line, token.loc.column))
+            .collect()
+    }
+
+    #[test]
+    fn crlf_and_cr_line_endings() {
+        let lf = lex_source("int x;\n// note\ny = 1;\n/* a\nb */ z\n");
+        let mixed = lex_source("int x;\r\n// note\ny = 1;\r\n/* a\r\nb */ z\r");
+
+        assert_eq!(locations(&mixed), locations(&lf));
+        assert_eq!(mixed.tokens[4].source, "\r\n");
+        assert_eq!(mixed.tokens[5].source, "// note");
+        assert_eq!(mixed.tokens.iter().filter(|token| token.token_type == TokenType::NewLine).count(), 4);
+    }
+    #[test]
+    fn shift_operators() {
+        let cases = [
+            ("1 << 4", vec![OperatorType::OpShl]),
+            ("a >>= 2", vec![OperatorType::OpShrAssign]),
+            ("a <<= 2", vec![OperatorType::OpShlAssign]),
+            ("a >>> b", vec![OperatorType::OpShr, OperatorType::OpGt]),
+            ("a <<< b", vec![OperatorType::OpShl, OperatorType::OpLt]),
+            ("a < < b", vec![OperatorType::OpLt, OperatorType::OpLt]),
+            ("a >> = b", vec![OperatorType::OpShr, OperatorType::OpAssign]),
+            ("a<b>c", vec![OperatorType::OpLt, OperatorType::OpGt]),
+            ("a>>=b>=c", vec![OperatorType::OpShrAssign, OperatorType::OpGe]),
+        ];
+
+        for (src, expected) in cases {
+            assert_eq!(operator_types(&lex_source(src)), expected, "{}", src);
+        }
+    }
+    #[test]
+    fn crlf_matches_lf_token_stream() {
+        let src = "char *s = \"x\";\nint c = 0\n;a+\nb\n1.5\n";
+        let lf = lex_source(src);
+        let crlf = lex_source(&src.replace('\n', "\r\n"));
+
+        let stream = |lex: &Lex| -> Vec<(String, usize, usize)> {
+            lex.tokens
+                .iter()
+                .map(|token| {
+                    let source = if token.token_type == TokenType::NewLine { String::from("\n") } else { token.source.clone() };
+                    (source, token.loc.line, token.loc.column)
+                })
+                .collect()
+        };
+        assert_eq!(stream(&crlf), stream(&lf));
+        assert_eq!(token_types(&crlf), token_types(&lf));
+    }
+    #[test]
+    fn member_access() {
+        let lex = lex_source("p->next->data");
+        assert_eq!(
+            token_types(&lex),
+            vec![
+                &TokenType::Identifier,
+                &TokenType::Operator(OperatorType::OpArrow),
+                &TokenType::Identifier,
+                &TokenType::Operator(OperatorType::OpArrow),
+                &TokenType::Identifier,
+            ]
+        );
+
+        let lex = lex_source("s.field = 1.5");
+        assert_eq!(
+            token_types(&lex),
+            vec![
+                &TokenType::Identifier,
+                &TokenType::Operator(OperatorType::OpDot),
+                &TokenType::Identifier,
+                &TokenType::Operator(OperatorType::OpAssign),
+                &TokenType::FlotNumber(FloatSuffix::None),
+            ]
+        );
+    }
+    #[test]
+    fn token_spans() {
+        let src = "int x = 0x1f; /* c */ s = \"a b\";\r\n";
+        let lex = lex_source(src);
+
+        for token in &lex.tokens {
+            assert_eq!(token.source_slice(src), token.source);
+        }
+        let string = lex.tokens.iter().find(|token| token.token_type == TokenType::Str(Encoding::None)).unwrap();
+        assert_eq!(string.span(), Span { start: 26, end: 31 });
+        assert_eq!(lex.tokens[lex.tokens.len() - 2].span(), Span { start: 32, end: 34 });
+        assert_eq!(lex.tokens.last().unwrap().span(), Span { start: 34, end: 34 });
+    }
+    #[test]
+    fn integer_suffixes() {
+        let cases = [
+            ("100UL", IntSuffix::UL),
+            ("0xFFu", IntSuffix::U),
+            ("42L", IntSuffix::L),
+            ("017ll", IntSuffix::LL),
+            ("0b101LLU", IntSuffix::ULL),
+            ("1uLL", IntSuffix::ULL),
+        ];
+
+        for (src, suffix) in cases {
+            let lex = lex_source(src);
+            assert_eq!(lex.tokens.len(), 2, "{}", src);
+            assert_eq!(lex.tokens[0].token_type, TokenType::Number(suffix), "{}", src);
+            assert_eq!(lex.tokens[0].source, src);
+        }
+
+        for invalid in ["lul", "uu", "lL", "llL", "ulu", "x"] {
+            assert_eq!(int_suffix_from_str(invalid), None, "{}", invalid);
+        }
+    }
+    #[test]
+    fn ellipsis() {
+        let lex = lex_source("int printf(const char *fmt, ...);");
+        assert_eq!(
+            token_types(&lex),
+            vec![
+                &TokenType::KeyWord(KeyWordType::KInt),
+                &TokenType::Identifier,
+                &TokenType::Punct(PunctType::LParen),
+                &TokenType::KeyWord(KeyWordType::KConst),
+                &TokenType::KeyWord(KeyWordType::KChar),
+                &TokenType::Operator(OperatorType::OpMul),
+                &TokenType::Identifier,
+                &TokenType::Punct(PunctType::Comma),
+                &TokenType::Operator(OperatorType::OpEllipsis),
+                &TokenType::Punct(PunctType::RParen),
+                &TokenType::Punct(PunctType::Semicolon),
+            ]
+        );
+        let ellipsis = lex.tokens.iter().find(|token| token.source == "...").unwrap();
+        assert_eq!(ellipsis.loc.column, 29);
+        assert_eq!(lex.tokens[lex.tokens.len() - 2].loc.column, 33);
+
+        let lex = lex_source("a..b");
+        assert_eq!(operator_types(&lex), vec![OperatorType::OpDot, OperatorType::OpDot]);
+    }
+    #[test]
+    fn malformed_numbers() {
+        let cases = [
+            ("1e", "Exponent has no digits", 3),
+            ("1.5e+", "Exponent has no digits", 6),
+            ("0x1.8", "Hexadecimal floating constant requires an exponent", 1),
+            ("0x.p1", "Hexadecimal floating constant has no digits", 1),
+            ("1.0ff", "Invalid suffix 'ff' on floating constant", 4),
+            ("1f", "Invalid suffix 'f' on integer constant", 2),
+            ("5lul", "Invalid suffix 'lul' on integer constant", 2),
+            ("0b102", "Invalid digit '2' in binary constant", 5),
+            ("0b1.1", "Binary constants cannot have a fractional part", 4),
+            ("08", "Invalid digit '8' in octal constant", 2),
+        ];
+
+        for (src, message, column) in cases {
+            assert_eq!(lex_errors(src), vec![(String::from(message), 1, column)], "{}", src);
+        }
+    }
+
+    #[test]
+    fn errors_do_not_stop_lexing() {
+        let src = "int a = 1f;\nb @ c;\nchar s = 'abcde';\nd = 1e+x;\ns = \"open";
+        let mut lex = Lex::from_source("test.c", src);
+        let errors = lex.parse().unwrap_err();
+
+        assert_eq!(
+            errors.iter().map(|error| error.to_string()).collect::<Vec<String>>(),
+            vec![
+                "Error: \"Invalid suffix 'f' on integer constant\" at (test.c:1:10)",
+                "Error: \"Stray '@' in program\" at (test.c:2:3)",
+                "Error: \"Character constant too long for its type\" at (test.c:3:10)",
+                "Error: \"Exponent has no digits\" at (test.c:4:8)",
+                "Error: \"Missing '\"' at the end\" at (test.c:5:5)",
+            ]
+        );
+
+        // Everything around the bad spots is still tokenized.
+        let c = lex.tokens.iter().find(|token| token.source == "c").unwrap();
+        assert_eq!((c.loc.line, c.loc.column), (2, 5));
+        let semicolons = lex.tokens.iter().filter(|token| token.source == ";").count();
+        assert_eq!(semicolons, 4);
+    }
+    #[test]
+    fn exponents() {
+        for src in ["1e10", "1.5e-3", "2E+8", "0e0", "0x1P10"] {
+            let lex = lex_source(src);
+            assert_eq!(token_types(&lex), vec![&TokenType::FlotNumber(FloatSuffix::None)], "{}", src);
+            assert_eq!(lex.tokens[0].source, src);
+        }
+
+        assert_eq!(lex_errors("1e"), vec![(String::from("Exponent has no digits"), 1, 3)]);
+        assert_eq!(lex_errors("x = 1e-;"), vec![(String::from("Exponent has no digits"), 1, 8)]);
+        assert_eq!(
+            lex_errors("1e3e4"),
+            vec![(String::from("Floating constant has more than one exponent"), 1, 4)]
+        );
+    }
+    #[test]
+    fn conditional_operator_and_labels() {
+        let lex = lex_source("a ? b : c");
+        assert_eq!(operator_types(&lex), vec![OperatorType::OpQuestion, OperatorType::OpColon]);
+
+        let lex = lex_source("label: x;");
+        assert_eq!(
+            token_types(&lex),
+            vec![
+                &TokenType::Identifier,
+                &TokenType::Operator(OperatorType::OpColon),
+                &TokenType::Identifier,
+                &TokenType::Punct(PunctType::Semicolon),
+            ]
+        );
+    }
+    #[test]
+    fn leading_dot_floats() {
+        let lex = lex_source("x = .5 + .25f;");
+        let floats: Vec<(&TokenType, &str, usize)> = lex.tokens.iter()
+            .filter(|token| matches!(token.token_type, TokenType::FlotNumber(_)))
+            .map(|token| (&token.token_type, token.source.as_str(), token.loc.column))
+            .collect();
+        assert_eq!(
+            floats,
+            vec![
+                (&TokenType::FlotNumber(FloatSuffix::None), ".5", 5),
+                (&TokenType::FlotNumber(FloatSuffix::F), ".25f", 10),
+            ]
+        );
+
+        let lex = lex_source("a.b");
+        assert_eq!(
+            token_types(&lex),
+            vec![&TokenType::Identifier, &TokenType::Operator(OperatorType::OpDot), &TokenType::Identifier]
+        );
+    }
+    #[test]
+    fn second_decimal_point() {
+        let lex = lex_source("1.2");
+        assert_eq!(token_types(&lex), vec![&TokenType::FlotNumber(FloatSuffix::None)]);
+
+        assert_eq!(lex_errors("1.2.3"), vec![(String::from("Too many decimal points in number"), 1, 4)]);
+        assert_eq!(lex_errors("x = 1..2;"), vec![(String::from("Too many decimal points in number"), 1, 7)]);
+    }
+    #[test]
+    fn punctuators() {
+        let lex = lex_source("int main(void) { return a[0], 0; }");
+        assert_eq!(
+            token_types(&lex),
+            vec![
+                &TokenType::KeyWord(KeyWordType::KInt),
+                &TokenType::Identifier,
+                &TokenType::Punct(PunctType::LParen),
+                &TokenType::KeyWord(KeyWordType::KVoid),
+                &TokenType::Punct(PunctType::RParen),
+                &TokenType::Punct(PunctType::LBrace),
+                &TokenType::KeyWord(KeyWordType::KReturn),
+                &TokenType::Identifier,
+                &TokenType::Punct(PunctType::LBracket),
+                &TokenType::Number(IntSuffix::None),
+                &TokenType::Punct(PunctType::RBracket),
+                &TokenType::Punct(PunctType::Comma),
+                &TokenType::Number(IntSuffix::None),
+                &TokenType::Punct(PunctType::Semicolon),
+                &TokenType::Punct(PunctType::RBrace),
+            ]
+        );
+        assert_eq!(lex.tokens[3].to_string(), "'(' [Punct(LParen)] Loc:(test.c:1:9)");
+    }
+    #[test]
+    fn dots_next_to_numbers() {
+        let lex = lex_source("x.5");
+        let tokens: Vec<(&TokenType, &str)> = lex.tokens.iter().map(|token| (&token.token_type, token.source.as_str())).collect();
+        assert_eq!(tokens, vec![(&TokenType::Identifier, "x"), (&TokenType::FlotNumber(FloatSuffix::None), ".5"), (&TokenType::Eof, "")]);
+
+        let lex = lex_source("a[1].b");
+        assert_eq!(token_types(&lex)[4], &TokenType::Operator(OperatorType::OpDot));
+
+        assert_eq!(lex_errors("1..2").len(), 1);
+    }
+    #[test]
+    fn char_values() {
+        let cases = [
+            ("'A'", 0x41),
+            ("'\\n'", 0x0a),
+            ("'\\t'", 0x09),
+            ("'\\0'", 0),
+            ("'\\\\'", 0x5c),
+            ("'\\''", 0x27),
+            ("'\\x41'", 0x41),
+            ("'\\101'", 0x41),
+            ("'\"'", 0x22),
+        ];
+
+        for (src, value) in cases {
+            let lex = lex_source(src);
+            assert_eq!(token_types(&lex), vec![&TokenType::Char(Encoding::None)], "{}", src);
+            assert_eq!(lex.tokens[0].value, Some(LiteralValue::Char(value)), "{}", src);
+            assert_eq!(lex.tokens[0].source, src);
+        }
+
+        assert_eq!(lex_errors("c = '';"), vec![(String::from("Empty character constant"), 1, 5)]);
+        assert_eq!(lex_errors("c = 'abcde';"), vec![(String::from("Character constant too long for its type"), 1, 5)]);
+        assert_eq!(lex_errors("c = '\\x';"), vec![(String::from("\\x used with no following hex digits"), 1, 6)]);
+        assert_eq!(lex_errors("c = 'a\nb"), vec![(String::from("Missing ''' at the end"), 1, 5)]);
+    }
+    #[test]
+    fn exponent_signs() {
+        for src in ["1e10", "1e+10", "1e-10", "1.5E+3", "6.02e23"] {
+            let lex = lex_source(src);
+            assert_eq!(token_types(&lex), vec![&TokenType::FlotNumber(FloatSuffix::None)], "{}", src);
+        }
+
+        for (src, column) in [("1e", 3), ("1e+", 4), ("1e+x", 4), ("2.5E-", 6)] {
+            assert_eq!(lex_errors(src), vec![(String::from("Exponent has no digits"), 1, column)], "{}", src);
+        }
+    }
+    #[test]
+    fn hexadecimal_floats() {
+        for src in ["0x1.8p3", "0X.4p-2", "0x1.8P+3", "0XAP-10", "0x.8p0L"] {
+            let lex = lex_source(src);
+            assert_eq!(token_types(&lex).len(), 1, "{}", src);
+            assert!(matches!(lex.tokens[0].token_type, TokenType::FlotNumber(_)), "{}", src);
+            assert_eq!(lex.tokens[0].source, src);
+        }
+
+        assert_eq!(
+            lex_errors("x = 0x1.8;"),
+            vec![(String::from("Hexadecimal floating constant requires an exponent"), 1, 5)]
+        );
+        assert_eq!(lex_errors("0x1p"), vec![(String::from("Exponent has no digits"), 1, 5)]);
+    }
+    #[test]
+    fn char_literals_past_start_of_file() {
+        let src = "int main(void)\n{\n    char a = 'x', b = '\\n';\n    return a + b;\n}\n";
+        let lex = lex_source(src);
+        let chars: Vec<(&str, usize, usize)> = lex.tokens.iter()
+            .filter(|token| token.token_type == TokenType::Char(Encoding::None))
+            .map(|token| (token.source.as_str(), token.loc.line, token.loc.column))
+            .collect();
+        assert_eq!(chars, vec![("'x'", 3, 14), ("'\\n'", 3, 23)]);
+    }
+    #[test]
+    fn every_integer_suffix() {
+        let suffixes = [
+            ("", IntSuffix::None),
+            ("u", IntSuffix::U),
+            ("l", IntSuffix::L),
+            ("ul", IntSuffix::UL),
+            ("lu", IntSuffix::UL),
+            ("ll", IntSuffix::LL),
+            ("ull", IntSuffix::ULL),
+            ("llu", IntSuffix::ULL),
+        ];
+
+        for number in ["10", "017", "0x1f", "0b11"] {
+            for (suffix, int_suffix) in suffixes {
+                for suffix in [suffix.to_string(), suffix.to_uppercase()] {
+                    let src = format!("{}{}", number, suffix);
+                    let lex = lex_source(&src);
+                    assert_eq!(token_types(&lex), vec![&TokenType::Number(int_suffix)], "{}", src);
+                }
+            }
+        }
+
+        for (src, column) in [("1lul", 2), ("1uu", 2), ("0x1lL", 4)] {
+            let errors = lex_errors(src);
+            assert_eq!(errors.len(), 1, "{}", src);
+            assert_eq!(errors[0].0, format!("Invalid suffix '{}' on integer constant", &src[column - 1..]));
+            assert_eq!(errors[0].2, column, "{}", src);
+        }
+    }
+    #[test]
+    fn eof_token() {
+        let lex = lex_source("int x;\n");
+        let eof = lex.tokens.last().unwrap();
+        assert_eq!(eof.token_type, TokenType::Eof);
+        assert_eq!((eof.loc.line, eof.loc.column), (2, 1));
+        assert_eq!(eof.span(), Span { start: 7, end: 7 });
+        assert!(!lex.show().contains("Eof"));
+
+        let lex = lex_source("");
+        assert_eq!(token_types(&lex).len(), 0);
+        assert_eq!(lex.tokens.len(), 1);
+        assert_eq!(lex.tokens[0].token_type, TokenType::Eof);
+    }
+    #[test]
+    fn token_stream_peeks() {
+        let lex = lex_source("a=1;");
+        let mut stream = lex.token_stream();
+        assert_eq!(stream.peek().unwrap().source, "a");
+        assert_eq!(stream.peek_nth(2).unwrap().source, "1");
+        assert_eq!(stream.next().unwrap().source, "a");
+        assert_eq!(stream.peek().unwrap().source, "=");
+        assert_eq!(stream.by_ref().count(), 4);
+        assert!(stream.peek().is_none());
+
+        let mut stream = lex_source("x y").into_token_stream();
+        assert_eq!(stream.peek_nth(1).unwrap().token_type, TokenType::Space);
+        let sources: Vec<String> = stream.by_ref().map(|token| token.source).collect();
+        assert_eq!(sources, vec!["x", " ", "y", ""]);
+        assert!(stream.next().is_none());
+    }
+    #[test]
+    fn float_suffixes() {
+        let cases = [
+            ("1.0f", TokenType::FlotNumber(FloatSuffix::F)),
+            ("2.F", TokenType::FlotNumber(FloatSuffix::F)),
+            ("1e3L", TokenType::FlotNumber(FloatSuffix::L)),
+            ("3l", TokenType::Number(IntSuffix::L)),
+            ("0xff", TokenType::Number(IntSuffix::None)),
+            ("0x1fp0f", TokenType::FlotNumber(FloatSuffix::F)),
+        ];
+        for (src, token_type) in cases {
+            let lex = lex_source(src);
+            assert_eq!(token_types(&lex), vec![&token_type], "{}", src);
+        }
+
+        assert_eq!(lex_errors("1.0ff"), vec![(String::from("Invalid suffix 'ff' on floating constant"), 1, 4)]);
+        assert_eq!(lex_errors("1.0fl"), vec![(String::from("Invalid suffix 'fl' on floating constant"), 1, 4)]);
+        assert_eq!(lex_errors("1f"), vec![(String::from("Invalid suffix 'f' on integer constant"), 1, 2)]);
+    }
+    #[test]
+    fn public_token_accessors() {
+        let lex = lex_source("int a = 1;");
+        let types: Vec<&TokenType> = lex.tokens()
+            .map(|token| token.token_type())
+            .filter(|token_type| **token_type != TokenType::Space)
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                &TokenType::KeyWord(KeyWordType::KInt),
+                &TokenType::Identifier,
+                &TokenType::Operator(OperatorType::OpAssign),
+                &TokenType::Number(IntSuffix::None),
+                &TokenType::Punct(PunctType::Semicolon),
+                &TokenType::Eof,
+            ]
+        );
+
+        let one = lex.tokens().find(|token| token.source() == "1").unwrap();
+        assert_eq!((one.loc().file(), one.loc().line(), one.loc().column()), ("test.c", 1, 9));
+    }
+    #[test]
+    fn display() {
+        let lex = lex_source("int x <<= 1;");
+        let types: Vec<String> = token_types(&lex).iter().map(|token_type| token_type.to_string()).collect();
+        assert_eq!(types, vec!["'int'", "identifier", "'<<='", "integer constant", "';'"]);
+        assert_eq!(format!("{}", lex.tokens[0].loc), "test.c:1:1");
+        assert_eq!(format!("{}", lex.tokens[2]), "'x' [Identifier] Loc:(test.c:1:5)");
+        assert_eq!(KeyWordType::KBool.to_string(), "_Bool");
+        assert_eq!(OperatorType::OpEllipsis.to_string(), "...");
+    }
+    #[test]
+    fn digit_separators() {
+        let lex_c23 = |src: &str| {
+            let mut lex = Lex::from_source("test.c", src);
+            lex.set_digit_separators(true);
+            let result = lex.parse();
+            (lex, result)
+        };
+
+        for (src, token_type) in [
+            ("1'000'000", TokenType::Number(IntSuffix::None)),
+            ("0xFF'FF", TokenType::Number(IntSuffix::None)),
+            ("0b1010'0101u", TokenType::Number(IntSuffix::U)),
+            ("1'000.000'1e1'0", TokenType::FlotNumber(FloatSuffix::None)),
+        ] {
+            let (lex, result) = lex_c23(src);
+            assert!(result.is_ok(), "{}", src);
+            assert_eq!(token_types(&lex), vec![&token_type], "{}", src);
+            assert_eq!(lex.tokens[0].source, src);
+        }
+
+        for (src, column) in [("x = 1'000';", 10), ("0x'FF", 3), ("1'.5", 2)] {
+            let errors = lex_c23(src).1.unwrap_err();
+            assert_eq!(errors.len(), 1, "{}", src);
+            assert_eq!(errors[0].message, "Digit separator must be between digits");
+            assert_eq!(errors[0].location.column, column, "{}", src);
+        }
+
+        // Without C23 the quote still starts a character constant.
+        let lex = lex_source("1'0'");
+        assert_eq!(token_types(&lex), vec![&TokenType::Number(IntSuffix::None), &TokenType::Char(Encoding::None)]);
+    }
+    #[test]
+    fn from_source_needs_no_file() {
+        let name = "no/such/dir/buffer.c";
+        assert!(!Path::new(name).exists());
+
+        let mut lex = Lex::from_source(name, "int\nx;");
+        lex.parse().unwrap();
+        let x = lex.tokens().find(|token| token.source() == "x").unwrap();
+        assert_eq!(x.loc().to_string(), "no/such/dir/buffer.c:2:1");
+    }
+    #[test]
+    fn location_spans() {
+        let src = "s = \"héllo\";\nx = 1e+;";
+        let mut lex = Lex::from_source("test.c", src);
+        let errors = lex.parse().unwrap_err();
+
+        let string = lex.tokens().find(|token| *token.token_type() == TokenType::Str(Encoding::None)).unwrap();
+        let span = string.loc().span();
+        assert_eq!(span, Span { start: 4, end: 12 });
+        assert_eq!(&src[span.start..span.end], "\"héllo\"");
+
+        let span = errors[0].location.span();
+        assert_eq!(&src[span.start..span.end], "1e+");
+        assert_eq!(errors[0].location.to_string(), "test.c:2:8");
+    }
+    #[test]
+    fn binary_prefix_needs_digits() {
+        let lex = lex_source("0b1010");
+        assert_eq!(token_types(&lex), vec![&TokenType::Number(IntSuffix::None)]);
+
+        for src in ["x = 0b", "x = 0b;", "f(0B )", "x = 0bu;"] {
+            assert_eq!(lex_errors(src), vec![(String::from("Missing digits after binary prefix"), 1, src.find('0').unwrap() + 1)], "{}", src);
+        }
+        assert_eq!(lex_errors("0b2"), vec![(String::from("Invalid digit '2' in binary constant"), 1, 3)]);
+    }
+    #[test]
+    fn directives_start_lines() {
+        let lex = lex_source("#include \"a.h\"\n  # define X 1\n#\nx = a # b;\n");
+        let directives: Vec<(&str, usize, usize)> = lex.tokens()
+            .filter(|token| matches!(token.token_type(), TokenType::Directive(_)))
+            .map(|token| (token.source(), token.loc().line(), token.loc().column()))
+            .collect();
+        assert_eq!(directives, vec![("#include", 1, 1), ("# define", 2, 3), ("#", 3, 1)]);
+
+        let x = lex.tokens().find(|token| token.source() == "X").unwrap();
+        assert_eq!(x.loc().column(), 12);
+        let hash = lex.tokens().find(|token| token.source() == "#" && token.loc().line() == 4).unwrap();
+        assert_eq!(*hash.token_type(), TokenType::Operator(OperatorType::OpHash));
+    }
+    #[test]
+    fn errors_accessor_collects_every_line() {
+        let mut lex = Lex::from_source("test.c", "int a = 09;\nint b = 1 $ 2;\nint c;");
+        assert!(lex.errors().is_empty());
+        assert!(lex.parse().is_err());
+
+        let errors: Vec<(&str, usize, usize)> = lex.errors().iter()
+            .map(|error| (error.message.as_str(), error.location.line(), error.location.column()))
+            .collect();
+        assert_eq!(errors, vec![("Invalid digit '9' in octal constant", 1, 10), ("Stray '$' in program", 2, 11)]);
+        assert!(lex.tokens().any(|token| token.source() == "c"));
+    }
+    #[test]
+    fn hexadecimal_prefix_needs_digits() {
+        let lex = lex_source("0X1A 0xff");
+        assert_eq!(token_types(&lex), vec![&TokenType::Number(IntSuffix::None), &TokenType::Number(IntSuffix::None)]);
+
+        assert_eq!(lex_errors("x = 0xg;"), vec![(String::from("Invalid digit 'g' in hexadecimal constant"), 1, 7)]);
+        assert_eq!(lex_errors("x = 0x;"), vec![(String::from("Missing digits after hexadecimal prefix"), 1, 5)]);
+
+        let mut lex = Lex::from_source("test.c", "int x;\nx = 0X");
+        let errors = lex.parse().unwrap_err();
+        assert_eq!(errors[0].to_string(), "Error: \"Missing digits after hexadecimal prefix\" at (test.c:2:5)");
+    }
+    #[test]
+    fn directive_kinds() {
+        let src = "#include <a.h>\n#define X\n#undef X\n#ifdef X\n#ifndef X\n#if 1\n#elif 2\n#else\n#endif\n\
+                   #pragma once\n#error e\n#warning w\n#line 10\n#frobnicate\n#\n#includes\n";
+        let kinds: Vec<DirectiveKind> = lex_source(src).tokens()
+            .filter_map(|token| match token.token_type() {
+                TokenType::Directive(kind) => Some(*kind),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(kinds, vec![
+            DirectiveKind::Include, DirectiveKind::Define, DirectiveKind::Undef, DirectiveKind::Ifdef,
+            DirectiveKind::Ifndef, DirectiveKind::If, DirectiveKind::Elif, DirectiveKind::Else,
+            DirectiveKind::Endif, DirectiveKind::Pragma, DirectiveKind::Error, DirectiveKind::Warning,
+            DirectiveKind::Line, DirectiveKind::Unknown, DirectiveKind::Unknown, DirectiveKind::Unknown,
+        ]);
+
+        assert_eq!(TokenType::Directive(DirectiveKind::Define).to_string(), "'#define'");
+    }
+    #[test]
+    fn configurable_tab_stop() {
+        let mut lex = Lex::from_source("test.c", "\tint x;\nab\tc;");
+        lex.set_tab_stop(4);
+        lex.parse().unwrap();
+        let at = |source: &str| -> (usize, usize) {
+            let token = lex.tokens().find(|token| token.source() == source).unwrap();
+            (token.loc().line(), token.loc().column())
+        };
+        assert_eq!(at("int"), (1, 5));
+        assert_eq!(at("x"), (1, 9));
+        assert_eq!(at("c"), (2, 5));
+
+        let mut lex = Lex::from_source("test.c", "\t\tx");
+        lex.set_tab_stop(0);
+        lex.parse().unwrap();
+        assert_eq!(lex.tokens[2].loc().column(), 3);
+    }
+    #[test]
+    fn number_values() {
+        let cases = [
+            ("42", LiteralValue::Int(42)),
+            ("0", LiteralValue::Int(0)),
+            ("0xaBcdef", LiteralValue::Int(0xabcdef)),
+            ("0X1Au", LiteralValue::Int(26)),
+            ("017", LiteralValue::Int(0o17)),
+            ("0b10101", LiteralValue::Int(0b10101)),
+            ("18446744073709551615ull", LiteralValue::Int(u64::MAX)),
+            ("1.5", LiteralValue::Float(1.5)),
+            (".25f", LiteralValue::Float(0.25)),
+            ("1e3", LiteralValue::Float(1000.0)),
+            ("09.5", LiteralValue::Float(9.5)),
+            ("0x1.8p1", LiteralValue::Float(3.0)),
+            ("0x.4p-2L", LiteralValue::Float(0.0625)),
+        ];
+        for (src, value) in cases {
+            let lex = lex_source(src);
+            assert_eq!(lex.tokens[0].value(), Some(value), "{}", src);
+        }
+
+        let mut lex = Lex::from_source("test.c", "1'000'000");
+        lex.set_digit_separators(true);
+        lex.parse().unwrap();
+        assert_eq!(lex.tokens[0].value(), Some(LiteralValue::Int(1_000_000)));
+
+        assert_eq!(format!("{}", lex_source("0x10").tokens[0]), "'0x10' = 16 [Number(None)] Loc:(test.c:1:1)");
+        assert_eq!(lex_errors("x = 18446744073709551616;"), vec![(String::from("Integer constant is too large"), 1, 5)]);
+        assert_eq!(lex_errors("x = 0x1p99999;"), vec![(String::from("Floating constant is out of range"), 1, 5)]);
+        assert_eq!(lex_errors("x = 1e999;"), vec![(String::from("Floating constant is out of range"), 1, 5)]);
+    }
+    #[test]
+    fn encoding_prefixes() {
+        let cases = [
+            ("L\"hi\"", TokenType::Str(Encoding::Wide)),
+            ("u8\"x\"", TokenType::Str(Encoding::Utf8)),
+            ("u\"x\"", TokenType::Str(Encoding::Utf16)),
+            ("U\"x\"", TokenType::Str(Encoding::Utf32)),
+            ("U'A'", TokenType::Char(Encoding::Utf32)),
+            ("L'\\n'", TokenType::Char(Encoding::Wide)),
+            ("u'x'", TokenType::Char(Encoding::Utf16)),
+            ("u8'x'", TokenType::Char(Encoding::Utf8)),
+        ];
+        for (src, token_type) in cases {
+            let lex = lex_source(src);
+            assert_eq!(token_types(&lex), vec![&token_type], "{}", src);
+            assert_eq!(lex.tokens[0].source(), src);
+        }
+        assert_eq!(lex_source("U'A'").tokens[0].value(), Some(LiteralValue::Char(0x41)));
+
+        let lex = lex_source("u8x Lx u8 L + \"s\"");
+        assert_eq!(
+            token_types(&lex),
+            vec![&TokenType::Identifier, &TokenType::Identifier, &TokenType::Identifier, &TokenType::Identifier, &TokenType::Operator(OperatorType::OpAdd), &TokenType::Str(Encoding::None)]
+        );
+
+        let lex = lex_source("x = L\"a\\\"b\" y");
+        let y = lex.tokens().find(|token| token.source() == "y").unwrap();
+        assert_eq!(y.loc().column(), 13);
+        let y = lex_source("\"ab\" y").tokens.into_iter().find(|token| token.source() == "y").unwrap();
+        assert_eq!(y.loc().column(), 6);
+        assert_eq!(lex_errors("c = L'ab';\nd"), vec![(String::from("There can only be one character between \"''\""), 1, 5)]);
+        assert_eq!(lex_errors("c = u'a\nd"), vec![(String::from("Missing ''' at the end"), 1, 5)]);
+    }
+    #[test]
+    fn header_names() {
+        let lex = lex_source("#include <stdio.h>\n# include \"sub/local.h\" // c\n#include <a\\b.h>\n");
+        let names: Vec<(&str, usize, usize)> = lex.tokens()
+            .filter(|token| *token.token_type() == TokenType::HeaderName)
+            .map(|token| (token.source(), token.loc().line(), token.loc().column()))
+            .collect();
+        assert_eq!(names, vec![("<stdio.h>", 1, 10), ("\"sub/local.h\"", 2, 11), ("<a\\b.h>", 3, 10)]);
+        assert_eq!(*lex.tokens[0].token_type(), TokenType::Directive(DirectiveKind::Include));
+
+        // Elsewhere, and without a closing delimiter on the line, '<' is an operator.
+        let lex = lex_source("a <b.h> c;\n#define X <y>\n#include <z\n>");
+        assert!(lex.tokens().all(|token| *token.token_type() != TokenType::HeaderName));
+        assert_eq!(lex_source("#include \"x.h\"").tokens[2].source_slice("#include \"x.h\""), "\"x.h\"");
+    }
+    #[test]
+    fn simple_escapes() {
+        let cases = [
+            ("'\\n'", 0x0a),
+            ("'\\t'", 0x09),
+            ("'\\r'", 0x0d),
+            ("'\\\\'", 0x5c),
+            ("'\\''", 0x27),
+            ("'\\\"'", 0x22),
+            ("'\\?'", 0x3f),
+            ("'\\0'", 0x00),
+            ("'\\a'", 0x07),
+            ("'\\b'", 0x08),
+            ("'\\f'", 0x0c),
+            ("'\\v'", 0x0b),
+        ];
+        for (src, value) in cases {
+            let lex = lex_source(src);
+            assert_eq!(lex.tokens[0].value(), Some(LiteralValue::Char(value)), "{}", src);
+        }
+        assert_eq!(format!("{}", lex_source("'\\n'").tokens[0]), "''\\n'' = 10 [Char(None)] Loc:(test.c:1:1)");
+
+        assert_eq!(lex_errors("c = '\\q';"), vec![(String::from("Unknown escape sequence '\\q'"), 1, 6)]);
+        let mut lex = Lex::from_source("test.c", "c = '\\q'; d");
+        assert!(lex.parse().is_err());
+        assert!(lex.tokens().any(|token| token.source() == "d"));
+    }
+    #[test]
+    fn numeric_escapes() {
+        for (src, value) in [("'\\x41'", 0x41), ("'\\101'", 0x41), ("'\\xff'", 0xff), ("'\\0'", 0), ("L'\\x100'", 0x100), ("U'\\x1F600'", 0x1f600), ("u'\\xffff'", 0xffff)] {
+            let lex = lex_source(src);
+            assert_eq!(lex.tokens[0].value(), Some(LiteralValue::Char(value)), "{}", src);
+        }
+        for src in ["\"\\033[0m\"", "\"\\x41\\101\\\\\"", "L\"\\x100\"", "\"a\\\\\""] {
+            let lex = lex_source(src);
+            assert_eq!(token_types(&lex).len(), 1, "{}", src);
+        }
+
+        assert_eq!(lex_errors("c = '\\777';"), vec![(String::from("Octal escape sequence out of range"), 1, 6)]);
+        assert_eq!(lex_errors("c = '\\x100';"), vec![(String::from("Hex escape sequence out of range"), 1, 6)]);
+        assert_eq!(lex_errors("c = u'\\x10000';"), vec![(String::from("Hex escape sequence out of range"), 1, 7)]);
+        assert_eq!(lex_errors("s = \"ab\\x100\";"), vec![(String::from("Hex escape sequence out of range"), 1, 8)]);
+        assert_eq!(lex_errors("s = \"\\n\\400\\q\";"), vec![
+            (String::from("Octal escape sequence out of range"), 1, 8),
+            (String::from("Unknown escape sequence '\\q'"), 1, 12),
+        ]);
+        let mut lex = Lex::from_source("test.c", "s = \"\\777\"; t");
+        assert!(lex.parse().is_err());
+        assert!(lex.tokens().any(|token| token.source() == "t"));
+    }
+    #[test]
+    fn directive_tokens_only_at_line_start() {
+        let lex = lex_source("#define FOO 1\n #endif\n\t#pragma once\nx = y # z;\n/* c */ #ifdef X\n");
+        let directives: Vec<(&str, &TokenType)> = lex.tokens()
+            .filter(|token| matches!(token.token_type(), TokenType::Directive(_)))
+            .map(|token| (token.source(), token.token_type()))
+            .collect();
+        assert_eq!(directives, vec![
+            ("#define", &TokenType::Directive(DirectiveKind::Define)),
+            ("#endif", &TokenType::Directive(DirectiveKind::Endif)),
+            ("#pragma", &TokenType::Directive(DirectiveKind::Pragma)),
+            ("#ifdef", &TokenType::Directive(DirectiveKind::Ifdef)),
+        ]);
+
+        let endif = lex.tokens().find(|token| token.source() == "#endif").unwrap();
+        assert_eq!((endif.loc().line(), endif.loc().column()), (2, 2));
+        let hash = lex.tokens().find(|token| token.source() == "#").unwrap();
+        assert_eq!(*hash.token_type(), TokenType::Operator(OperatorType::OpHash));
+        assert_eq!(hash.loc().line(), 4);
+    }
+    #[test]
+    fn directives_turned_off() {
+        let mut lex = Lex::from_source("test.c", "## #define");
+        lex.set_directives(false);
+        lex.parse().unwrap();
+        assert_eq!(token_types(&lex), vec![&TokenType::Operator(OperatorType::OpHashHash), &TokenType::Operator(OperatorType::OpHash), &TokenType::Identifier]);
+    }
+    #[test]
+    fn universal_character_names() {
+        for (src, value) in [("'\\u00e9'", 0xe9), ("L'\\U0001F600'", 0x1f600), ("U'\\u0024'", 0x24), ("u'\\uFFFD'", 0xfffd)] {
+            let lex = lex_source(src);
+            assert_eq!(lex.tokens[0].value(), Some(LiteralValue::Char(value)), "{}", src);
+        }
+        for src in ["\"caf\\u00e9\"", "L\"\\U0001F600!\""] {
+            assert_eq!(token_types(&lex_source(src)).len(), 1, "{}", src);
+        }
+
+        let cases = [
+            ("c = L'\\uD800';", "\\uD800 is not a valid universal character", 7),
+            ("c = '\\u0041';", "\\u0041 is not a valid universal character", 6),
+            ("c = U'\\U00110000';", "\\U00110000 is not a valid universal character", 7),
+            ("c = '\\u12';", "\\u needs 4 hexadecimal digits", 6),
+            ("s = \"x\\U1234567\";", "\\U needs 8 hexadecimal digits", 7),
+            ("s = \"\\udfff\";", "\\udfff is not a valid universal character", 6),
+        ];
+        for (src, message, column) in cases {
+            assert_eq!(lex_errors(src), vec![(String::from(message), 1, column)], "{}", src);
+        }
+    }
+    #[test]
+    fn multichar_constants() {
+        let mut lex = Lex::from_source("test.c", "int c =\n  'ab' + 'abcd';");
+        lex.parse().unwrap();
+        let values: Vec<Option<LiteralValue>> =
+            lex.tokens.iter().filter(|token| token.token_type == TokenType::Char(Encoding::None)).map(|token| token.value).collect();
+        assert_eq!(values, vec![Some(LiteralValue::Char(0x6162)), Some(LiteralValue::Char(0x61626364))]);
+
+        let warnings: Vec<(String, usize, usize)> =
+            lex.warnings().iter().map(|warning| (warning.message.clone(), warning.location.line, warning.location.column)).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                (String::from("Multi-character character constant"), 2, 3),
+                (String::from("Multi-character character constant"), 2, 10),
+            ]
+        );
+        assert_eq!(lex.warnings()[0].location.span, Span { start: 10, end: 14 });
+
+        assert!(lex_source("'a'").warnings().is_empty());
+    }
+    #[test]
+    fn wide_char_literals() {
+        let cases = [
+            ("L'\u{e9}'", TokenType::Char(Encoding::Wide), 0xe9),
+            ("u'\u{e9}'", TokenType::Char(Encoding::Utf16), 0xe9),
+            ("U'\u{1F600}'", TokenType::Char(Encoding::Utf32), 0x1f600),
+            ("u8'a'", TokenType::Char(Encoding::Utf8), 0x61),
+            ("'a'", TokenType::Char(Encoding::None), 0x61),
+        ];
+        for (src, token_type, value) in cases {
+            let lex = lex_source(src);
+            assert_eq!(token_types(&lex), vec![&token_type], "{}", src);
+            assert_eq!(lex.tokens[0].value, Some(LiteralValue::Char(value)), "{}", src);
+            assert_eq!(lex.tokens[0].source, src);
+        }
+
+        // Neither a plain nor a u8 character constant can hold a non-ASCII character, nor a u one outside the BMP.
+        assert_eq!(lex_errors("c = '\u{e9}';"), vec![(String::from("[195] is not an ascii character"), 1, 6)]);
+        assert_eq!(lex_errors("c = u8'\u{e9}';"), vec![(String::from("[195] is not an ascii character"), 1, 8)]);
+        assert_eq!(lex_errors("c = u'\u{1F600}';"), vec![(String::from("[240] is not an ascii character"), 1, 7)]);
+    }
+    #[test]
+    fn dump_tokens_rows() {
+        let lex = lex_source("int x;");
+        let dump = dump_tokens(lex.tokens());
+        let rows: Vec<Vec<&str>> = dump.lines().map(|line| line.split('\t').collect()).collect();
+        assert_eq!(rows.len(), 5);
+        assert!(rows.iter().all(|row| row.len() == 4), "{}", dump);
+        assert_eq!(rows[0], vec!["1", "1", "keyword", "int"]);
+        assert_eq!(rows[1], vec!["1", "4", "space", " "]);
+        assert_eq!(rows[4], vec!["1", "7", "eof", ""]);
+
+        let lex = lex_source("a\t/* \\ */\n");
+        let dump = dump_tokens(lex.tokens());
+        let rows: Vec<Vec<&str>> = dump.lines().map(|line| line.split('\t').collect()).collect();
+        let kinds: Vec<&[&str]> = rows.iter().map(|row| &row[2..]).collect();
+        assert_eq!(kinds, vec![&["identifier", "a"][..], &["space", "\\t"], &["comment", "/* \\\\ */"], &["newline", "\\n"], &["eof", ""]]);
+    }
+    #[test]
+    fn string_prefix_must_touch_the_quote() {
+        for src in ["L \"x\"", "u8 \"x\"", "u\t\"x\"", "U/**/\"x\""] {
+            let lex = lex_source(src);
+            assert_eq!(token_types(&lex), vec![&TokenType::Identifier, &TokenType::Str(Encoding::None)], "{}", src);
+            assert_eq!(lex.tokens().last().map(|token| token.token_type()), Some(&TokenType::Eof));
+        }
+        let lex = lex_source("L\"a\" u8\"b\"");
+        assert_eq!(token_types(&lex), vec![&TokenType::Str(Encoding::Wide), &TokenType::Str(Encoding::Utf8)]);
+        let sources: Vec<&str> = lex.tokens().filter(|token| matches!(token.token_type(), TokenType::Str(_))).map(Token::source).collect();
+        assert_eq!(sources, vec!["L\"a\"", "u8\"b\""]);
+    }
+    #[test]
+    fn string_values() {
+        let cases: [(&str, &[u32]); 7] = [
+            ("\"a\\nb\"", &[0x61, 0x0a, 0x62]),
+            ("\"\\\\\"", &[0x5c]),
+            ("\"say \\\"hi\\\"\"", &[0x73, 0x61, 0x79, 0x20, 0x22, 0x68, 0x69, 0x22]),
+            ("\"\\x41\\101\"", &[0x41, 0x41]),
+            ("\"\"", &[]),
+            ("\"\\u00e9\u{e9}\"", &[0xc3, 0xa9, 0xc3, 0xa9]),
+            ("L\"\\u00e9\u{e9}\\x100\"", &[0xe9, 0xe9, 0x100]),
+        ];
+        for (src, value) in cases {
+            let lex = lex_source(src);
+            assert_eq!(lex.tokens[0].string_value().as_deref(), Some(value), "{}", src);
+        }
+        assert_eq!(lex_source("'a'").tokens[0].string_value(), None);
+
+        let mut lex = Lex::from_source("test.c", "s = \"ok\\q\";");
+        assert_eq!(lex.parse().unwrap_err()[0].to_string(), "Error: \"Unknown escape sequence '\\q'\" at (test.c:1:8)");
+        let s = lex.tokens().find(|token| matches!(token.token_type(), TokenType::Str(_))).unwrap();
+        assert_eq!(s.string_value(), None);
+    }
+    #[test]
+    fn json_tokens() {
+        // Reads back an array of flat objects, checking the syntax along the way.
+        fn parse_json(json: &str) -> Vec<Vec<(String, String)>> {
+            let mut chars = json.trim().chars().peekable();
+            let string = |chars: &mut std::iter::Peekable<std::str::Chars>| -> String {
+                assert_eq!(chars.next(), Some('"'));
+                let mut text = String::new();
+                loop {
+                    match chars.next().unwrap() {
+                        '"' => return text,
+                        '\\' => match chars.next().unwrap() {
+                            'n' => text.push('\n'),
+                            't' => text.push('\t'),
+                            'r' => text.push('\r'),
+                            'u' => {
+                                let hex: String = chars.by_ref().take(4).collect();
+                                text.push(char::from_u32(u32::from_str_radix(&hex, 16).unwrap()).unwrap());
+                            },
+                            chr => text.push(chr),
+                        },
+                        chr => text.push(chr),
                     }
-                },
-                'f' | 'F' => {
-                    if !err_token {
-                        if !hex_num {
-                            if index != start && (bytes[index - 1] as char).is_ascii_digit() {
-                                if f_flag {
-                                    err_token = true;
-                                } else {
-                                    f_flag = true;
-                                }
-                            } else {
-                                if bin_num {
-                                    eprintln!("Error: \"The number of binary values exceeds 1\" at ({}:{}:{})",
-                                        self.file, self.line, column);
-                                    std::process::exit(-1);
-                                } else if oct_num {
-                                    eprintln!("Error: \"The number of octal values exceeds 7\" at ({}:{}:{})",
-                                        self.file, self.line, column);
-                                    std::process::exit(-1);
-                                } else if dec_num {
-                                    eprintln!("Error: \"The number of decimal values exceeds 9\" at ({}:{}:{})",
-                                            self.file, self.line, column);
-                                    std::process::exit(-1);
-                                }
-                            }
+                }
+            };
+            let mut objects = Vec::new();
+            assert_eq!(chars.next(), Some('['));
+            while chars.peek() != Some(&']') {
+                assert_eq!(chars.next(), Some('{'));
+                let mut fields = Vec::new();
+                loop {
+                    let key = string(&mut chars);
+                    assert_eq!(chars.next(), Some(':'));
+                    let value = if chars.peek() == Some(&'"') {
+                        string(&mut chars)
+                    } else {
+                        let mut number = String::new();
+                        while chars.peek().is_some_and(|chr| chr.is_ascii_digit()) {
+                            number.push(chars.next().unwrap());
                         }
+                        assert!(!number.is_empty());
+                        number
+                    };
+                    fields.push((key, value));
+                    match chars.next() {
+                        Some(',') => continue,
+                        Some('}') => break,
+                        chr => panic!("unexpected {:?}", chr),
                     }
                 }
-                _ => {
-                    err_token = true;
-                },
+                objects.push(fields);
+                if chars.peek() == Some(&',') {
+                    chars.next();
+                }
+                while chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
             }
+            assert_eq!(chars.next(), Some(']'));
+            assert_eq!(chars.next(), None);
+            objects
+        }
 
-            index += 1;
-            column += 1;
+        let lex = lex_source("char *s = \"a\\\"\\\\\";\t// \x01\n");
+        let objects = parse_json(&tokens_to_json(lex.tokens()));
+        assert_eq!(objects.len(), lex.tokens().count());
+
+        let s = &objects[7];
+        let keys: Vec<&str> = s.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, vec!["kind", "source", "file", "line", "column", "start", "end"]);
+        let values: Vec<&str> = s.iter().map(|(_, value)| value.as_str()).collect();
+        assert_eq!(values, vec!["string", "\"a\\\"\\\\\"", "test.c", "1", "11", "10", "17"]);
+        assert_eq!(objects[10][1].1, "// \x01");
+        assert_eq!(tokens_to_json(Vec::<&Token>::new()), "[]\n");
+    }
+    #[test]
+    fn tabs_inside_string_literals() {
+        let lex = lex_source("s = \"a\tb\";\tx\n\t\"\t\" y");
+        let at = |source: &str| -> (usize, usize) {
+            let token = lex.tokens.iter().find(|token| token.source == source).unwrap();
+            (token.loc.line, token.loc.column)
+        };
+        // The tab after "a" moves to column 9, so the closing quote is at 10.
+        assert_eq!(at(";"), (1, 11));
+        assert_eq!(at("x"), (1, 17));
+        assert_eq!(at("\"\t\""), (2, 9));
+        assert_eq!(at("y"), (2, 19));
+    }
+    #[test]
+    fn crlf_never_leaks_into_token_sources() {
+        let src = "#define N 1\nchar c = 'a';\n  s = \"b\" L\"c\"; // d\n/* e\n */ x\n";
+        let lf = lex_source(src);
+        let crlf = lex_source(&src.replace('\n', "\r\n"));
+
+        assert_eq!(locations(&crlf), locations(&lf));
+        for token in crlf.tokens().filter(|token| token.token_type != TokenType::NewLine) {
+            assert!(!token.source.contains('\r') || token.token_type == TokenType::Note, "{:?}", token.source);
         }
+        assert_eq!(lex_errors("c = 'a\r\nb"), lex_errors("c = 'a\nb"));
+    }
+    #[test]
+    fn line_continuations() {
+        let src = "#define MAX(a, b) \\\n  ((a) > (b) ? (a) : (b))\nint fo\\\no = 12\\\r\n34;\nchar *s = \"ab\\\ncd\";\nx \\\n\ty\n";
+        let lex = lex_source(src);
+        let tokens: Vec<(&str, usize, usize)> = lex.tokens().filter(|token| !matches!(token.token_type(), TokenType::Space | TokenType::NewLine | TokenType::Eof)).map(|token| (token.source(), token.loc().line(), token.loc().column())).collect();
+        assert_eq!(tokens[..3], [("#define", 1, 1), ("MAX", 1, 9), ("(", 1, 12)]);
+        assert_eq!(tokens[7], ("(", 2, 3));
+        let after: Vec<(&str, usize, usize)> = tokens.iter().copied().skip_while(|(source, _, _)| *source != "int").collect();
+        assert_eq!(after, vec![("int", 3, 1), ("foo", 3, 5), ("=", 4, 3), ("1234", 4, 5), (";", 5, 3), ("char", 6, 1), ("*", 6, 6), ("s", 6, 7), ("=", 6, 9), ("\"abcd\"", 6, 11), (";", 7, 4), ("x", 8, 1), ("y", 9, 9)]);
+
+        let number = lex.tokens().find(|token| token.source() == "1234").unwrap();
+        assert_eq!(number.value(), Some(LiteralValue::Int(1234)));
+        assert_eq!(number.source_slice(src), "12\\\r\n34");
+        assert_eq!(lex.tokens().filter(|token| token.token_type() == &TokenType::NewLine).count(), 4);
+
+        // Errors after a splice point at the physical line.
+        assert_eq!(lex_errors("int a \\\n = 0x;"), vec![(String::from("Missing digits after hexadecimal prefix"), 2, 4)]);
+    }
+    #[test]
+    fn trigraphs() {
+        let src = "??=define ARR(x) x??(0??)\na ??'= b??!c ??/\n; ??<??>??-\n";
+        let mut lex = Lex::from_source("test.c", src);
+        lex.set_trigraphs(true);
+        lex.parse().unwrap();
+        let tokens: Vec<(&str, usize, usize)> = lex.tokens().filter(|token| !matches!(token.token_type(), TokenType::Space | TokenType::NewLine | TokenType::Eof)).map(|token| (token.source(), token.loc().line(), token.loc().column())).collect();
+        assert_eq!(tokens, vec![("#define", 1, 1), ("ARR", 1, 11), ("(", 1, 14), ("x", 1, 15), (")", 1, 16), ("x", 1, 18), ("[", 1, 19), ("0", 1, 22), ("]", 1, 23), ("a", 2, 1), ("^=", 2, 3), ("b", 2, 8), ("|", 2, 9), ("c", 2, 12), (";", 3, 1), ("{", 3, 3), ("}", 3, 6), ("~", 3, 9)]);
+        assert_eq!(lex.tokens().next().unwrap().token_type(), &TokenType::Directive(DirectiveKind::Define));
+        let define = lex.tokens().next().unwrap();
+        assert_eq!(define.source_slice(src), "??=define");
+
+        let warnings: Vec<(&str, usize, usize)> = lex.warnings().iter().map(|warning| (warning.message.as_str(), warning.location.line(), warning.location.column())).collect();
+        assert_eq!(warnings[0], ("Trigraph ??= converted to #", 1, 1));
+        assert_eq!(warnings[5], ("Trigraph ??/ converted to \\", 2, 14));
+        assert_eq!(warnings.len(), 9);
+
+        // Without the option they are just question marks.
+        let lex = lex_source("??=define");
+        assert_eq!(token_types(&lex)[..2], [&TokenType::Operator(OperatorType::OpQuestion), &TokenType::Operator(OperatorType::OpQuestion)]);
+        assert!(lex.warnings().is_empty());
+    }
+    #[test]
+    fn digraphs() {
+        assert_eq!(token_types(&lex_source("int a<:3:>;")), token_types(&lex_source("int a[3];")));
+        assert_eq!(token_types(&lex_source("f() <% g(); %>")), token_types(&lex_source("f() { g(); }")));
+
+        let lex = lex_source("%:define CAT(a, b) a %:%: b %:a\nx %:% y <::>");
+        let tokens: Vec<(&TokenType, &str)> = lex.tokens().filter(|token| !matches!(token.token_type(), TokenType::Space | TokenType::NewLine | TokenType::Eof)).map(|token| (token.token_type(), token.source())).collect();
+        assert_eq!(tokens[0], (&TokenType::Directive(DirectiveKind::Define), "%:define"));
+        assert_eq!(tokens[8..], [
+            (&TokenType::Operator(OperatorType::OpHashHash), "%:%:"),
+            (&TokenType::Identifier, "b"),
+            (&TokenType::Operator(OperatorType::OpHash), "%:"),
+            (&TokenType::Identifier, "a"),
+            (&TokenType::Identifier, "x"),
+            (&TokenType::Operator(OperatorType::OpHash), "%:"),
+            (&TokenType::Operator(OperatorType::OpMod), "%"),
+            (&TokenType::Identifier, "y"),
+            (&TokenType::Punct(PunctType::LBracket), "<:"),
+            (&TokenType::Punct(PunctType::RBracket), ":>"),
+        ]);
+    }
+    #[test]
+    fn doc_line_comments() {
+        let lex = lex_source("/// Counts.\nint n; //// banner /* not a block\n/// last\n//");
+        let notes: Vec<(&str, usize, usize)> = lex.tokens().filter(|token| *token.token_type() == TokenType::Note).map(|token| (token.source(), token.loc().line(), token.loc().column())).collect();
+        assert_eq!(notes, vec![("/// Counts.", 1, 1), ("//// banner /* not a block", 2, 8), ("/// last", 3, 1), ("//", 4, 1)]);
+        assert_eq!(token_types(&lex), vec![&TokenType::KeyWord(KeyWordType::KInt), &TokenType::Identifier, &TokenType::Punct(PunctType::Semicolon)]);
+        let n = lex.tokens().find(|token| token.source() == "n").unwrap();
+        assert_eq!((n.loc().line(), n.loc().column()), (2, 5));
+    }
+    #[test]
+    fn block_comment_columns() {
+        let lex = lex_source("int a; /* one\n   two\n   three */ int b;\nc/**/d /*\t*/e");
+        let tokens: Vec<(&str, usize, usize)> = lex.tokens().filter(|token| matches!(token.token_type(), TokenType::Identifier | TokenType::KeyWord(_))).map(|token| (token.source(), token.loc().line(), token.loc().column())).collect();
+        assert_eq!(tokens, vec![("int", 1, 1), ("a", 1, 5), ("int", 3, 13), ("b", 3, 17), ("c", 4, 1), ("d", 4, 6), ("e", 4, 19)]);
     }
 }
@@ -0,0 +1,10 @@
+pub mod ast;
+pub mod cmdline;
+pub mod ir;
+pub mod lex;
+pub mod optimize;
+pub mod parser;
+pub mod preprocess;
+pub mod sema;
+pub mod symbol_table;
+pub mod types;
@@ -1,99 +1,122 @@
-mod cmdline;
-mod lex;
-
+use std::fs;
+use std::io::{self, Write};
 use std::path::Path;
 
-use cmdline as cmd;
-use lex::Lex;
+use ncc::cmdline as cmd;
+use ncc::lex::{self, Lex, LexError, Token};
+use ncc::preprocess::{self, Preprocessor};
 
-fn add_cmd_info(cmdline: &mut cmd::CmdLine) {
+fn add_cmd_info(cmdline: &mut cmd::CmdLine) -> Result<(), cmd::CmdError> {
     cmdline.add(
         "",
         "--help",
         "Show this help info.",
         cmd::CmdValType::NoVal,
         "",
-    );
+    )?;
     cmdline.add(
         "",
         "--version",
         "Show version number.",
         cmd::CmdValType::NoVal,
         "",
-    );
+    )?;
+    cmdline.add(
+        "",
+        "--dump-tokens",
+        "Print each token as tab-separated line, column, kind and source.",
+        cmd::CmdValType::NoVal,
+        "",
+    )?;
+    cmdline.add(
+        "",
+        "--emit=",
+        "Print the tokens in <format>; only json is supported.",
+        cmd::CmdValType::ValNoSpace,
+        "format",
+    )?;
     cmdline.add(
         "-o",
         "",
         "Place the output into <file>.",
         cmd::CmdValType::ValSpace,
         "file",
-    );
+    )?;
     cmdline.add(
         "-D",
         "",
         "Add macro definition.",
         cmd::CmdValType::ValOptSpace,
         "macro[=<value>]",
-    );
+    )?;
     cmdline.add(
         "-I",
         "",
         "Add the header file index path.",
         cmd::CmdValType::ValOptSpace,
         "path",
-    );
+    )?;
     cmdline.add(
         "-v",
         "",
         "Display the programs invoked by the compiler.",
         cmd::CmdValType::NoVal,
         "",
-    );
+    )?;
     cmdline.add(
         "-###",
         "",
         "Like -v but options quoted and commands not executed.",
         cmd::CmdValType::NoVal,
         "",
-    );
+    )?;
     cmdline.add(
         "-E",
         "",
         "Preprocess only; do not compile, assemble or link.",
         cmd::CmdValType::NoVal,
         "",
-    );
+    )?;
     cmdline.add(
         "-S",
         "",
         "Compile only; do not assemble or link.",
         cmd::CmdValType::NoVal,
         "",
-    );
+    )?;
     cmdline.add(
         "-c",
         "",
         "Compile and assemble, but do not link.",
         cmd::CmdValType::NoVal,
         "",
-    );
+    )?;
     cmdline.add(
         "-pie",
         "",
         "Create a dynamically linked position independent executable.",
         cmd::CmdValType::NoVal,
         "",
-    );
+    )?;
+    cmdline.add(
+        "-trigraphs",
+        "",
+        "Replace ISO C trigraphs such as ??= before lexing.",
+        cmd::CmdValType::NoVal,
+        "",
+    )?;
     cmdline.add(
         "-std=",
         "",
         "Set language standards for use.",
         cmd::CmdValType::ValNoSpace,
         "",
-    );
+    )?;
+
+    Ok(())
 }
 
-fn check_input_file(files: &Vec<String>) {
+fn check_input_file(files: &Vec<String>) -> bool {
     let mut err = false;
 
     for file in files {
@@ -105,55 +128,185 @@ fn check_input_file(files: &Vec<String>) {
 
     if err {
         eprintln!("\nError: Input file error\n");
-        std::process::exit(-1);
     }
+    !err
 }
 
-fn main() {
-    let mut cmdline = cmd::CmdLine::new();
-    let args: Vec<String> = std::env::args().collect();
-    let test = 1.2;
-    let test2: f64 = 3.4f64;
-    let test3 = 0b10101;
-    let test4 = 0xaBcdef;
-    let test5 = 01234567;
+fn report_errors(errors: &[LexError]) -> i32 {
+    for error in errors {
+        eprintln!("{}", error);
+    }
+    eprintln!("\n{} error(s) generated.", errors.len());
+    -1
+}
+
+/// Writes `text` to the file `output`, or to stdout when there is none or it
+/// is "-".
+fn write_output(output: Option<&str>, text: &str) -> io::Result<()> {
+    match output {
+        None | Some("-") => io::stdout().write_all(text.as_bytes()),
+        Some(file) => fs::write(file, text),
+    }
+}
 
-    add_cmd_info(&mut cmdline);
-    cmdline.parse(&args[1..].to_vec());
+/// Does what the command line `args`, without the program name, asks for,
+/// and returns the exit status.
+fn run(args: &[String]) -> i32 {
+    let mut cmdline = cmd::CmdLine::new();
+    if let Err(error) = add_cmd_info(&mut cmdline).and_then(|_| cmdline.parse_from(args)) {
+        eprintln!("{}", error);
+        return -1;
+    }
 
     if cmdline.is_include("--help") {
-        println!(
-            "{}\n\nNcc compiler by Nick.Hu -- {}",
-            cmdline.help(),
-            "V0.1.0"
-        );
-        std::process::exit(0);
+        println!("{}\n\nNcc compiler by Nick.Hu -- V0.1.0", cmdline.help());
+        return 0;
     }
 
     if cmdline.is_include("--version") {
-        println!("Ncc compiler by Nick.Hu -- {}", "V0.1.0");
-        std::process::exit(0);
+        println!("Ncc compiler by Nick.Hu -- V0.1.0");
+        return 0;
     }
 
-    if cmdline.others.len() == 0 {
+    if cmdline.others.is_empty() {
         eprintln!("No input file");
-        std::process::exit(-1);
+        return -1;
+    }
+    if !check_input_file(&cmdline.others) {
+        return -1;
     }
-    check_input_file(&cmdline.others);
 
-    /*
-    if let Some(vals) = cmdline.get_value_by_name("-D") {
-        println!("[{}]:{:?}", "-D", vals);
+    let std = cmdline
+        .get_value_by_name("-std=")
+        .and_then(|stds| stds.last());
+    let mut lex = match Lex::new(&cmdline.others[0]) {
+        Ok(lex) => lex,
+        Err(why) => {
+            eprintln!("couldn't read {}: {}", cmdline.others[0], why);
+            return -1;
+        }
+    };
+    lex.set_trigraphs(cmdline.is_include("-trigraphs"));
+    if let Some(std) = std {
+        lex.set_digit_separators(matches!(std.as_str(), "c23" | "c2x" | "gnu23" | "gnu2x"));
+    }
+    let result = lex.parse();
+    for warning in lex.warnings() {
+        eprintln!("Warning: \"{}\" at ({})", warning.message, warning.location);
+    }
+    if let Err(errors) = result {
+        return report_errors(&errors);
+    }
+
+    let include_paths = cmdline.get_value_by_name("-I").cloned().unwrap_or_default();
+    let mut preprocessor = Preprocessor::new(&include_paths);
+    if let Some(std) = std {
+        preprocessor.set_std(std);
+    }
+    for define in cmdline.get_value_by_name("-D").into_iter().flatten() {
+        if let Err(errors) = preprocessor.define_arg(define) {
+            return report_errors(&errors);
+        }
+    }
+    let result = preprocessor.process(lex.into_token_stream());
+    for warning in preprocessor.warnings() {
+        eprintln!("Warning: \"{}\" at ({})", warning.message, warning.location);
+    }
+    let tokens: Vec<Token> = match result {
+        Ok(tokens) => tokens.collect(),
+        Err(errors) => return report_errors(&errors),
+    };
+
+    let text = if cmdline.is_include("-E") {
+        preprocess::render(&cmdline.others[0], tokens)
+    } else if let Some(format) = cmdline
+        .get_value_by_name("--emit=")
+        .and_then(|formats| formats.last())
+    {
+        if format != "json" {
+            eprintln!("Unknown --emit format '{}'", format);
+            return -1;
+        }
+        lex::tokens_to_json(&tokens)
+    } else if cmdline.is_include("--dump-tokens") {
+        lex::dump_tokens(&tokens)
+    } else {
+        format!("{}\n", lex::show_tokens(&tokens))
+    };
+    let output = cmdline
+        .get_value_by_name("-o")
+        .and_then(|files| files.last());
+    if let Err(why) = write_output(output.map(String::as_str), &text) {
+        eprintln!(
+            "couldn't write {}: {}",
+            output.map_or("stdout", String::as_str),
+            why
+        );
+        return -1;
     }
-    if let Some(vals) = cmdline.get_value_by_name("-I") {
-        println!("[{}]:{:?}", "-I", vals);
+    0
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    std::process::exit(run(&args[1..]));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_file() {
+        let dir = std::env::temp_dir().join(format!("ncc-output-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("in.c");
+        fs::write(&src, "int x;\n").unwrap();
+        let out = dir.join("out.txt");
+        let args: Vec<String> = [
+            "--dump-tokens",
+            src.to_str().unwrap(),
+            "-o",
+            out.to_str().unwrap(),
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+
+        let status = run(&args);
+        let text = fs::read_to_string(&out).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(status, 0);
+        assert_eq!(
+            text,
+            "1\t1\tkeyword\tint\n1\t4\tspace\t \n1\t5\tidentifier\tx\n1\t6\tpunct\t;\n1\t7\tnewline\t\\n\n2\t1\teof\t\n"
+        );
     }
 
-    println!("[others]:{:?}", cmdline.others);
-    println!("{:?}", cmdline.args);
-    */
+    #[test]
+    fn command_line_defines() {
+        let dir = std::env::temp_dir().join(format!("ncc-defines-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("in.c");
+        fs::write(&src, "int x = FOO;\n").unwrap();
+        let out = dir.join("out.i");
+        let args: Vec<String> = [
+            "-DFOO=42",
+            "-E",
+            src.to_str().unwrap(),
+            "-o",
+            out.to_str().unwrap(),
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+
+        let status = run(&args);
+        let text = fs::read_to_string(&out).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
 
-    let mut lex = Lex::new(&cmdline.others[0]);
-    lex.parse();
-    println!("{}", lex.show());
+        assert_eq!(status, 0);
+        assert_eq!(text, format!("# 1 \"{}\"\nint x = 42;\n", src.display()));
+    }
 }
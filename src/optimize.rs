@@ -0,0 +1,378 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::ast::{BinOpKind, UnaryOpKind};
+use crate::ir::{BlockId, IrFunction, IrInstr, IrModule, IrVal};
+
+/// The value a temporary is known to hold at a point in a block.
+pub type ConstantVal = i64;
+
+/// An operation that would fail if run, found while folding, such as a
+/// division by zero.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoldError {
+    pub function: String,
+    pub message: String,
+}
+
+impl fmt::Display for FoldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Error: \"{}\" in function '{}'",
+            self.message, self.function
+        )
+    }
+}
+
+/// Evaluates every `BinOp` and `UnaryOp` whose operands are known constants,
+/// replacing it with an `Assign` of the result, and turns conditional
+/// jumps on constants into plain jumps. Constants are propagated through
+/// temporaries within a block. Values are folded as 64-bit words, wrapping
+/// on overflow, which is how the IR's operators work. Faults are
+/// only reported in blocks that can still be reached once jumps are folded.
+pub fn constant_fold(module: &mut IrModule) -> Result<(), Vec<FoldError>> {
+    let mut errors = Vec::new();
+    for function in &mut module.functions {
+        fold_function(function, &mut errors);
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn fold_function(function: &mut IrFunction, errors: &mut Vec<FoldError>) {
+    let mut faults = Vec::new();
+    for block in &mut function.blocks {
+        // Temporaries may be assigned in more than one block, so what is
+        // known does not carry over from one block to the next.
+        let mut constants: HashMap<IrVal, ConstantVal> = HashMap::new();
+        for instr in &mut block.instrs {
+            substitute(instr, &constants);
+            let unsigned = matches!(instr, IrInstr::UnsignedBinOp(..));
+            let folded = match instr {
+                IrInstr::BinOp(dst, op, lhs, rhs) | IrInstr::UnsignedBinOp(dst, op, lhs, rhs) => {
+                    match fold_binop(*op, lhs, rhs, unsigned) {
+                        Ok(value) => {
+                            value.map(|value| IrInstr::Assign(dst.clone(), IrVal::Const(value)))
+                        }
+                        Err(message) => {
+                            faults.push((block.id, message));
+                            None
+                        }
+                    }
+                }
+                IrInstr::UnaryOp(dst, op, IrVal::Const(value)) => fold_unary(*op, *value)
+                    .map(|value| IrInstr::Assign(dst.clone(), IrVal::Const(value))),
+                IrInstr::CondJump(IrVal::Const(cond), then, els) => {
+                    Some(IrInstr::Jump(if *cond != 0 { *then } else { *els }))
+                }
+                _ => None,
+            };
+            if let Some(folded) = folded {
+                *instr = folded;
+            }
+
+            match instr {
+                IrInstr::Assign(dst, IrVal::Const(value)) => {
+                    constants.insert(dst.clone(), *value);
+                }
+                IrInstr::Assign(dst, _)
+                | IrInstr::BinOp(dst, ..)
+                | IrInstr::UnsignedBinOp(dst, ..)
+                | IrInstr::UnaryOp(dst, ..)
+                | IrInstr::Load(dst, _)
+                | IrInstr::Call(dst, ..) => {
+                    constants.remove(dst);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let reachable = reachable(function);
+    for (block, message) in faults {
+        if reachable.contains(&block) {
+            errors.push(FoldError {
+                function: function.name.clone(),
+                message,
+            });
+        }
+    }
+}
+
+/// The blocks control can reach from the entry block.
+fn reachable(function: &IrFunction) -> HashSet<BlockId> {
+    let mut reachable = HashSet::new();
+    let mut pending = vec![BlockId(0)];
+    while let Some(id) = pending.pop() {
+        if !reachable.insert(id) {
+            continue;
+        }
+        match function.blocks[id.0].terminator() {
+            Some(IrInstr::Jump(target)) => pending.push(*target),
+            Some(IrInstr::CondJump(_, then, els)) => pending.extend([*then, *els]),
+            _ => {}
+        }
+    }
+    reachable
+}
+
+/// Replaces the operands `constants` knows the value of; destinations are
+/// left alone.
+fn substitute(instr: &mut IrInstr, constants: &HashMap<IrVal, ConstantVal>) {
+    let known = |val: &mut IrVal| {
+        if let Some(value) = constants.get(val) {
+            *val = IrVal::Const(*value);
+        }
+    };
+    match instr {
+        IrInstr::Assign(_, src) | IrInstr::UnaryOp(_, _, src) => known(src),
+        IrInstr::BinOp(_, _, lhs, rhs) | IrInstr::UnsignedBinOp(_, _, lhs, rhs) => {
+            known(lhs);
+            known(rhs);
+        }
+        IrInstr::Load(_, addr) => known(&mut addr.base),
+        IrInstr::Store(addr, src) => {
+            known(&mut addr.base);
+            known(src);
+        }
+        IrInstr::CondJump(cond, ..) | IrInstr::Return(Some(cond)) => known(cond),
+        IrInstr::Call(_, callee, args) => {
+            known(callee);
+            args.iter_mut().for_each(known);
+        }
+        IrInstr::Jump(_) | IrInstr::Return(None) | IrInstr::Label(_) => {}
+    }
+}
+
+/// The result of `lhs op rhs`, if it can be worked out now. `&&` and `||`
+/// fold when either operand settles them, as neither operand of an IR
+/// instruction has side effects. Dividing by a constant zero is an error
+/// whatever the dividend. `unsigned` is set for an `UnsignedBinOp`.
+fn fold_binop(
+    op: BinOpKind,
+    lhs: &IrVal,
+    rhs: &IrVal,
+    unsigned: bool,
+) -> Result<Option<ConstantVal>, String> {
+    match (op, lhs, rhs) {
+        (BinOpKind::LogAnd, IrVal::Const(0), _) | (BinOpKind::LogAnd, _, IrVal::Const(0)) => {
+            return Ok(Some(0))
+        }
+        (BinOpKind::LogOr, IrVal::Const(value), _) | (BinOpKind::LogOr, _, IrVal::Const(value))
+            if *value != 0 =>
+        {
+            return Ok(Some(1))
+        }
+        (BinOpKind::Div, _, IrVal::Const(0)) => return Err(String::from("Division by zero")),
+        (BinOpKind::Mod, _, IrVal::Const(0)) => return Err(String::from("Remainder by zero")),
+        _ => {}
+    }
+    let (&IrVal::Const(lhs), &IrVal::Const(rhs)) = (lhs, rhs) else {
+        return Ok(None);
+    };
+
+    if unsigned {
+        let (lhs, rhs) = (lhs as u64, rhs as u64);
+        return Ok(match op {
+            BinOpKind::Div => Some((lhs / rhs) as i64),
+            BinOpKind::Mod => Some((lhs % rhs) as i64),
+            BinOpKind::Shr => u32::try_from(rhs)
+                .ok()
+                .and_then(|rhs| lhs.checked_shr(rhs))
+                .map(|value| value as i64),
+            BinOpKind::Lt => Some((lhs < rhs) as i64),
+            BinOpKind::Gt => Some((lhs > rhs) as i64),
+            BinOpKind::Le => Some((lhs <= rhs) as i64),
+            BinOpKind::Ge => Some((lhs >= rhs) as i64),
+            _ => None,
+        });
+    }
+    Ok(match op {
+        BinOpKind::Add => Some(lhs.wrapping_add(rhs)),
+        BinOpKind::Sub => Some(lhs.wrapping_sub(rhs)),
+        BinOpKind::Mul => Some(lhs.wrapping_mul(rhs)),
+        // i64::MIN / -1 overflows; it is left for run time.
+        BinOpKind::Div => lhs.checked_div(rhs),
+        BinOpKind::Mod => lhs.checked_rem(rhs),
+        // Shifting by the width or more is undefined, so is not folded.
+        BinOpKind::Shl => u32::try_from(rhs).ok().and_then(|rhs| lhs.checked_shl(rhs)),
+        BinOpKind::Shr => u32::try_from(rhs).ok().and_then(|rhs| lhs.checked_shr(rhs)),
+        BinOpKind::BitAnd => Some(lhs & rhs),
+        BinOpKind::BitOr => Some(lhs | rhs),
+        BinOpKind::BitXor => Some(lhs ^ rhs),
+        BinOpKind::Lt => Some((lhs < rhs) as i64),
+        BinOpKind::Gt => Some((lhs > rhs) as i64),
+        BinOpKind::Le => Some((lhs <= rhs) as i64),
+        BinOpKind::Ge => Some((lhs >= rhs) as i64),
+        BinOpKind::Eq => Some((lhs == rhs) as i64),
+        BinOpKind::Ne => Some((lhs != rhs) as i64),
+        BinOpKind::LogAnd | BinOpKind::LogOr => Some((rhs != 0) as i64),
+        _ => None,
+    })
+}
+
+fn fold_unary(op: UnaryOpKind, value: ConstantVal) -> Option<ConstantVal> {
+    match op {
+        UnaryOpKind::Plus => Some(value),
+        UnaryOpKind::Neg => Some(value.wrapping_neg()),
+        UnaryOpKind::BitNot => Some(!value),
+        UnaryOpKind::LogNot => Some((value == 0) as i64),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{BasicBlock, IrGen};
+    use crate::lex::Lex;
+    use crate::parser::Parser;
+
+    fn lower(src: &str) -> IrModule {
+        let mut lex = Lex::from_source("test.c", src);
+        lex.parse().unwrap();
+        let unit = Parser::new(lex.token_stream()).parse().unwrap();
+        IrGen::lower_translation_unit(&unit).unwrap()
+    }
+
+    #[test]
+    fn arithmetic() {
+        let mut module = lower("int f(int x) { return (2 + 3) * 4 + (x << (1 - ~0)); }");
+        constant_fold(&mut module).unwrap();
+        assert_eq!(
+            module.to_string(),
+            "function f(1 params, 1 locals):\n\
+             b0:\n  \
+               store4 [local0], arg0\n  \
+               t0 = 5\n  \
+               t1 = 20\n  \
+               t2 = load4 [local0]\n  \
+               t3 = -1\n  \
+               t4 = 2\n  \
+               t5 = t2 << 2\n  \
+               t6 = 20 + t5\n  \
+               ret t6\n"
+        );
+
+        let mut module = lower("int g(void) { if (3 > 4) return 1; return -(7 % 4 == 3); }");
+        constant_fold(&mut module).unwrap();
+        let blocks = &module.functions[0].blocks;
+        assert_eq!(blocks[0].terminator(), Some(&IrInstr::Jump(BlockId(2))));
+        assert_eq!(
+            blocks[2].terminator(),
+            Some(&IrInstr::Return(Some(IrVal::Const(-1))))
+        );
+    }
+
+    #[test]
+    fn short_circuits() {
+        let x = IrVal::Temp(0);
+        let mut module = IrModule {
+            functions: vec![IrFunction {
+                name: String::from("f"),
+                params: 1,
+                temps: 3,
+                blocks: vec![BasicBlock {
+                    id: BlockId(0),
+                    instrs: vec![
+                        IrInstr::Label(BlockId(0)),
+                        IrInstr::Assign(x.clone(), IrVal::Param(0)),
+                        IrInstr::BinOp(
+                            IrVal::Temp(1),
+                            BinOpKind::LogAnd,
+                            x.clone(),
+                            IrVal::Const(0),
+                        ),
+                        IrInstr::BinOp(
+                            IrVal::Temp(2),
+                            BinOpKind::LogOr,
+                            x.clone(),
+                            IrVal::Const(1),
+                        ),
+                        IrInstr::BinOp(IrVal::Temp(2), BinOpKind::LogOr, x, IrVal::Temp(1)),
+                        IrInstr::Return(Some(IrVal::Temp(2))),
+                    ],
+                }],
+                ..IrFunction::default()
+            }],
+            globals: Vec::new(),
+        };
+        constant_fold(&mut module).unwrap();
+        assert_eq!(
+            module.functions[0].blocks[0].instrs[2..],
+            [
+                IrInstr::Assign(IrVal::Temp(1), IrVal::Const(0)),
+                IrInstr::Assign(IrVal::Temp(2), IrVal::Const(1)),
+                IrInstr::BinOp(
+                    IrVal::Temp(2),
+                    BinOpKind::LogOr,
+                    IrVal::Temp(0),
+                    IrVal::Const(0)
+                ),
+                IrInstr::Return(Some(IrVal::Temp(2))),
+            ]
+        );
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let mut module =
+            lower("int f(void) { return 1 / (2 - 2); }\nint g(int x) { return x % 0; }");
+        let errors = constant_fold(&mut module).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                FoldError {
+                    function: String::from("f"),
+                    message: String::from("Division by zero"),
+                },
+                FoldError {
+                    function: String::from("g"),
+                    message: String::from("Remainder by zero"),
+                },
+            ]
+        );
+        assert_eq!(
+            errors[0].to_string(),
+            "Error: \"Division by zero\" in function 'f'"
+        );
+        // The faulting instruction is kept as it was.
+        assert!(matches!(
+            module.functions[0].blocks[0].instrs[2],
+            IrInstr::BinOp(_, BinOpKind::Div, IrVal::Const(1), IrVal::Const(0))
+        ));
+
+        // Code that can no longer run does not fault.
+        let mut module = lower("int f(void) { if (0) return 1 / 0; return 0; }");
+        assert_eq!(constant_fold(&mut module), Ok(()));
+    }
+
+    #[test]
+    fn unsigned_operators() {
+        let mut module = lower(
+            "int f(void) { return (-1u / 2 == 2147483647) + (-1 < 0u) + (-8u >> 28) + (-1ul % 10); }",
+        );
+        constant_fold(&mut module).unwrap();
+        let instrs = &module.functions[0].blocks[0].instrs;
+        assert_eq!(
+            instrs.last(),
+            Some(&IrInstr::Return(Some(IrVal::Const(21))))
+        );
+    }
+
+    #[test]
+    fn conversions() {
+        let mut module = lower(
+            "int f(void) { return ((unsigned)-1 == 4294967295) + (unsigned char)-1 + (char)300; }",
+        );
+        constant_fold(&mut module).unwrap();
+        assert_eq!(
+            module.functions[0].blocks[0].terminator(),
+            Some(&IrInstr::Return(Some(IrVal::Const(1 + 255 + 44))))
+        );
+    }
+}
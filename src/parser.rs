@@ -0,0 +1,2208 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::{
+    BinOpKind, BlockItem, Decl, EnumConst, EnumDef, Expr, ExternalDecl, FieldDecl, ForInit,
+    FunctionDef, Param, SizeofArg, Stmt, StorageClass, StructDef, TranslationUnit, UnaryOpKind,
+};
+use crate::lex::{
+    Encoding, IntSuffix, KeyWordType, LiteralValue, OperatorType, PunctType, Span, Token,
+    TokenStream, TokenType,
+};
+use crate::symbol_table::{Site, SymbolInfo, SymbolTable};
+use crate::types::{ArraySize, CType, EnumId, IntSize, Signedness, StructId, TypeQuals, INT};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+    /// Whether the parser found its footing again after this error and went
+    /// on to parse the rest of the input, rather than stopping here.
+    pub recovered: bool,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Error: \"{}\" at bytes {}..{}",
+            self.message, self.span.start, self.span.end
+        )
+    }
+}
+
+type ParseResult<T> = Result<T, ParseError>;
+
+/// What a struct, union or enum tag has been declared as.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tag {
+    Struct(StructId),
+    Union(StructId),
+    Enum(EnumId),
+}
+
+/// The type and storage class named by a run of declaration specifiers.
+struct DeclSpecs {
+    ty: CType,
+    storage: Option<StorageClass>,
+}
+
+/// The result of parsing a declarator: the declared name, if any, and the
+/// type built around the specifiers' base type.
+struct Declarator {
+    name: Option<String>,
+    ty: CType,
+    /// The parameters, when the declarator directly declares a function.
+    params: Option<Vec<Param>>,
+    span: Span,
+}
+
+/// How many times each basic type keyword appears in a run of specifiers.
+#[derive(Default)]
+struct BaseCounts {
+    void: usize,
+    bool: usize,
+    char: usize,
+    short: usize,
+    int: usize,
+    long: usize,
+    signed: usize,
+    unsigned: usize,
+    float: usize,
+    double: usize,
+}
+
+impl BaseCounts {
+    fn is_empty(&self) -> bool {
+        self.void
+            + self.bool
+            + self.char
+            + self.short
+            + self.int
+            + self.long
+            + self.signed
+            + self.unsigned
+            + self.float
+            + self.double
+            == 0
+    }
+
+    /// The type the keywords name together, or `None` for a combination C
+    /// does not allow, like `short long` or `unsigned double`.
+    fn ctype(&self) -> Option<CType> {
+        if self.signed + self.unsigned > 1 {
+            return None;
+        }
+        let sign = if self.unsigned > 0 {
+            Signedness::Unsigned
+        } else {
+            Signedness::Signed
+        };
+        let sized = self.signed + self.unsigned == 0;
+        let others = |counts: &[usize]| counts.iter().sum::<usize>() == 0;
+
+        if self.void == 1 && sized && others(&[self.bool, self.char, self.short, self.int]) {
+            return others(&[self.long, self.float, self.double]).then_some(CType::Void);
+        }
+        if self.bool == 1 && sized && others(&[self.void, self.char, self.short, self.int]) {
+            return others(&[self.long, self.float, self.double]).then_some(CType::Bool);
+        }
+        if self.float + self.double > 0 {
+            return match (self.float, self.double, self.long) {
+                _ if !sized || !others(&[self.void, self.bool, self.char]) => None,
+                _ if !others(&[self.short, self.int]) => None,
+                (1, 0, 0) => Some(CType::Float),
+                (0, 1, 0) => Some(CType::Double),
+                (0, 1, 1) => Some(CType::LongDouble),
+                _ => None,
+            };
+        }
+        if !others(&[self.void, self.bool]) {
+            return None;
+        }
+        if self.char == 1 {
+            return others(&[self.short, self.int, self.long]).then_some(CType::Char(sign));
+        }
+        let size = match (self.short, self.long, self.int) {
+            (0, 0, 0..=1) => IntSize::Int,
+            (1, 0, 0..=1) => IntSize::Short,
+            (0, 1, 0..=1) => IntSize::Long,
+            (0, 2, 0..=1) => IntSize::LongLong,
+            _ => return None,
+        };
+        (self.char == 0).then_some(CType::Int { size, sign })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Assoc {
+    Left,
+    Right,
+}
+
+/// The precedence level of assignment, the loosest an operand of `,` binds.
+const ASSIGNMENT: u8 = 2;
+/// The precedence level of `?:`, as required of `case` labels and enum values.
+const CONDITIONAL: u8 = 3;
+
+/// How tightly each operator joins the operands on either side of it, and
+/// which way a run of them groups; indexed by `OperatorType as usize`. Higher
+/// binds tighter. The comma, level 1, is a punctuator and is handled by
+/// `Parser::expr`.
+const PRECEDENCE: [Option<(u8, Assoc)>; 40] = [
+    Some((12, Assoc::Left)),           // +
+    Some((12, Assoc::Left)),           // -
+    Some((13, Assoc::Left)),           // *
+    Some((13, Assoc::Left)),           // /
+    Some((13, Assoc::Left)),           // %
+    None,                              // ++
+    None,                              // --
+    Some((9, Assoc::Left)),            // ==
+    Some((9, Assoc::Left)),            // !=
+    Some((10, Assoc::Left)),           // <
+    Some((10, Assoc::Left)),           // >
+    Some((10, Assoc::Left)),           // <=
+    Some((10, Assoc::Left)),           // >=
+    Some((5, Assoc::Left)),            // &&
+    Some((4, Assoc::Left)),            // ||
+    None,                              // !
+    Some((8, Assoc::Left)),            // &
+    Some((6, Assoc::Left)),            // |
+    Some((7, Assoc::Left)),            // ^
+    None,                              // ~
+    Some((11, Assoc::Left)),           // <<
+    Some((11, Assoc::Left)),           // >>
+    Some((ASSIGNMENT, Assoc::Right)),  // =
+    Some((ASSIGNMENT, Assoc::Right)),  // +=
+    Some((ASSIGNMENT, Assoc::Right)),  // -=
+    Some((ASSIGNMENT, Assoc::Right)),  // *=
+    Some((ASSIGNMENT, Assoc::Right)),  // /=
+    Some((ASSIGNMENT, Assoc::Right)),  // %=
+    Some((ASSIGNMENT, Assoc::Right)),  // &=
+    Some((ASSIGNMENT, Assoc::Right)),  // |=
+    Some((ASSIGNMENT, Assoc::Right)),  // ^=
+    Some((ASSIGNMENT, Assoc::Right)),  // <<=
+    Some((ASSIGNMENT, Assoc::Right)),  // >>=
+    None,                              // ->
+    None,                              // .
+    None,                              // ...
+    Some((CONDITIONAL, Assoc::Right)), // ?
+    None,                              // :
+    None,                              // #
+    None,                              // ##
+];
+
+/// Array and function parameters are really pointers.
+fn adjust_param(ty: CType) -> CType {
+    match ty {
+        CType::Array { elem, .. } => CType::Pointer {
+            pointee: elem,
+            quals: TypeQuals::NONE,
+        },
+        CType::Function { .. } => CType::Pointer {
+            pointee: Box::new(ty),
+            quals: TypeQuals::NONE,
+        },
+        _ => ty,
+    }
+}
+
+/// The type of an integer constant: the first of the types C11 6.4.4.1
+/// lists for its suffix and base that can represent `value`.
+fn int_literal_type(value: u64, suffix: IntSuffix, decimal: bool) -> CType {
+    use IntSize::{Int, Long, LongLong};
+    use Signedness::{Signed, Unsigned};
+    let candidates: &[(IntSize, Signedness)] = match (suffix, decimal) {
+        (IntSuffix::None, true) => &[(Int, Signed), (Long, Signed), (LongLong, Signed)],
+        (IntSuffix::None, false) => &[
+            (Int, Signed),
+            (Int, Unsigned),
+            (Long, Signed),
+            (Long, Unsigned),
+            (LongLong, Signed),
+            (LongLong, Unsigned),
+        ],
+        (IntSuffix::U, _) => &[(Int, Unsigned), (Long, Unsigned), (LongLong, Unsigned)],
+        (IntSuffix::L, true) => &[(Long, Signed), (LongLong, Signed)],
+        (IntSuffix::L, false) => &[
+            (Long, Signed),
+            (Long, Unsigned),
+            (LongLong, Signed),
+            (LongLong, Unsigned),
+        ],
+        (IntSuffix::UL, _) => &[(Long, Unsigned), (LongLong, Unsigned)],
+        (IntSuffix::LL, true) => &[(LongLong, Signed)],
+        (IntSuffix::LL, false) => &[(LongLong, Signed), (LongLong, Unsigned)],
+        (IntSuffix::ULL, _) => &[(LongLong, Unsigned)],
+    };
+    let fits = |(size, sign): &&(IntSize, Signedness)| {
+        let bits = size.width() as u32 * 8 - (*sign == Signed) as u32;
+        bits == 64 || value < 1 << bits
+    };
+    // A decimal constant too large for `long long` is taken as unsigned, as
+    // other compilers do.
+    let (size, sign) = candidates
+        .iter()
+        .find(fits)
+        .copied()
+        .unwrap_or((LongLong, Unsigned));
+    CType::Int { size, sign }
+}
+
+/// Builds a `TranslationUnit` from preprocessed tokens by recursive descent.
+pub struct Parser<'a> {
+    /// The significant tokens: whitespace, comments and newlines are dropped.
+    tokens: Vec<&'a Token>,
+    pos: usize,
+    errors: Vec<ParseError>,
+    /// Ordinary identifiers in scope, which tell typedef names apart from
+    /// the objects and functions that may shadow them.
+    symbols: SymbolTable,
+    tags: HashMap<String, Tag>,
+    unit: TranslationUnit,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(tokens: TokenStream<'a>) -> Parser<'a> {
+        let tokens = tokens
+            .filter(|token| {
+                !matches!(
+                    token.token_type(),
+                    TokenType::Note | TokenType::NewLine | TokenType::Space | TokenType::Eof
+                )
+            })
+            .collect();
+        Parser {
+            tokens,
+            pos: 0,
+            errors: Vec::new(),
+            symbols: SymbolTable::new(),
+            tags: HashMap::new(),
+            unit: TranslationUnit::default(),
+        }
+    }
+
+    pub fn parse(&mut self) -> Result<TranslationUnit, Vec<ParseError>> {
+        while self.peek().is_some() {
+            if let Err(error) = self.external_decl() {
+                self.synchronize(false);
+                self.recover(error);
+            }
+        }
+
+        if self.errors.is_empty() {
+            Ok(std::mem::take(&mut self.unit))
+        } else {
+            Err(self.errors.clone())
+        }
+    }
+
+    /// Parses the tokens as a single conditional expression, the kind an
+    /// `#if` line holds, with nothing after it.
+    pub fn parse_constant_expr(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.parse_expr(CONDITIONAL)?;
+        match self.peek() {
+            Some(token) => self.error(format!("Unexpected '{}' in expression", token.source())),
+            None => Ok(expr),
+        }
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<&'a Token> {
+        self.tokens.get(self.pos + n).copied()
+    }
+
+    fn peek_type(&self) -> Option<&'a TokenType> {
+        self.peek().map(Token::token_type)
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.peek();
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn is_punct(&self, punct: PunctType) -> bool {
+        self.peek_type() == Some(&TokenType::Punct(punct))
+    }
+
+    fn is_op(&self, op: OperatorType) -> bool {
+        self.peek_type() == Some(&TokenType::Operator(op))
+    }
+
+    fn is_keyword(&self, keyword: KeyWordType) -> bool {
+        self.peek_type() == Some(&TokenType::KeyWord(keyword))
+    }
+
+    fn eat_punct(&mut self, punct: PunctType) -> bool {
+        let found = self.is_punct(punct);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_op(&mut self, op: OperatorType) -> bool {
+        let found = self.is_op(op);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_keyword(&mut self, keyword: KeyWordType) -> bool {
+        let found = self.is_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    /// The span of the next token, or an empty span just past the last one.
+    fn here(&self) -> Span {
+        match self.peek() {
+            Some(token) => token.span(),
+            None => {
+                let end = self.prev_end();
+                Span { start: end, end }
+            }
+        }
+    }
+
+    fn prev_end(&self) -> usize {
+        match self.pos.checked_sub(1).and_then(|pos| self.tokens.get(pos)) {
+            Some(token) => token.span().end,
+            None => 0,
+        }
+    }
+
+    /// From `start` to the end of the last token consumed.
+    fn span_from(&self, start: usize) -> Span {
+        Span {
+            start,
+            end: self.prev_end().max(start),
+        }
+    }
+
+    /// How the next token reads in a message.
+    fn found(&self) -> String {
+        match self.peek() {
+            Some(token) => format!("'{}'", token.source()),
+            None => String::from("end of input"),
+        }
+    }
+
+    fn error<T>(&self, message: String) -> ParseResult<T> {
+        Err(ParseError {
+            message,
+            span: self.here(),
+            recovered: false,
+        })
+    }
+
+    /// Skips past the rest of a construct that failed to parse: to just
+    /// after a `;`, or up to a `{`, `}` or keyword that starts a declaration
+    /// (or, inside a block, a statement) outside any parentheses. At the top
+    /// level a `{` starts a function body, which is skipped whole. Apart from
+    /// a `}` closing the block, at least one token is consumed, so the parser
+    /// cannot stall on the one at fault.
+    fn synchronize(&mut self, in_block: bool) {
+        let start = self.pos;
+        let mut parens = 0usize;
+        while let Some(token) = self.peek() {
+            let moved = self.pos > start;
+            match token.token_type() {
+                TokenType::Punct(PunctType::Semicolon) => {
+                    self.pos += 1;
+                    return;
+                }
+                TokenType::Punct(PunctType::LBrace) if !in_block => {
+                    self.pos += 1;
+                    let mut depth = 1;
+                    while depth > 0 {
+                        match self.next().map(Token::token_type) {
+                            Some(TokenType::Punct(PunctType::LBrace)) => depth += 1,
+                            Some(TokenType::Punct(PunctType::RBrace)) => depth -= 1,
+                            Some(_) => {}
+                            None => return,
+                        }
+                    }
+                    return;
+                }
+                TokenType::Punct(PunctType::RBrace) if !in_block => {
+                    self.pos += 1;
+                    return;
+                }
+                TokenType::Punct(PunctType::RBrace) => return,
+                TokenType::Punct(PunctType::LBrace) if moved => return,
+                TokenType::Punct(PunctType::LParen) => parens += 1,
+                TokenType::Punct(PunctType::RParen) => parens = parens.saturating_sub(1),
+                TokenType::KeyWord(keyword) if moved && parens == 0 => {
+                    let starts_stmt = matches!(
+                        keyword,
+                        KeyWordType::KIf
+                            | KeyWordType::KWhile
+                            | KeyWordType::KDo
+                            | KeyWordType::KFor
+                            | KeyWordType::KSwitch
+                            | KeyWordType::KCase
+                            | KeyWordType::KDefault
+                            | KeyWordType::KReturn
+                            | KeyWordType::KBreak
+                            | KeyWordType::KContinue
+                            | KeyWordType::KGoto
+                    );
+                    if self.starts_type(Some(token)) || in_block && starts_stmt {
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// Records an error the parser has synchronized after.
+    fn recover(&mut self, mut error: ParseError) {
+        error.recovered = self.peek().is_some();
+        self.errors.push(error);
+    }
+
+    fn expect_punct(&mut self, punct: PunctType) -> ParseResult<()> {
+        if self.eat_punct(punct) {
+            Ok(())
+        } else {
+            self.error(format!("Expected '{}' before {}", punct, self.found()))
+        }
+    }
+
+    fn expect_op(&mut self, op: OperatorType) -> ParseResult<()> {
+        if self.eat_op(op) {
+            Ok(())
+        } else {
+            self.error(format!("Expected '{}' before {}", op, self.found()))
+        }
+    }
+
+    fn expect_identifier(&mut self) -> ParseResult<String> {
+        match self.peek() {
+            Some(token) if token.token_type() == &TokenType::Identifier => {
+                self.pos += 1;
+                Ok(String::from(token.source()))
+            }
+            _ => self.error(format!("Expected an identifier before {}", self.found())),
+        }
+    }
+
+    /// Whether `token` can begin declaration specifiers.
+    fn starts_type(&self, token: Option<&Token>) -> bool {
+        match token.map(Token::token_type) {
+            Some(TokenType::KeyWord(keyword)) => matches!(
+                keyword,
+                KeyWordType::KVoid
+                    | KeyWordType::KBool
+                    | KeyWordType::KChar
+                    | KeyWordType::KShort
+                    | KeyWordType::KInt
+                    | KeyWordType::KLong
+                    | KeyWordType::KSigned
+                    | KeyWordType::KUnsigned
+                    | KeyWordType::KFloat
+                    | KeyWordType::KDouble
+                    | KeyWordType::KComplex
+                    | KeyWordType::KImaginary
+                    | KeyWordType::KStruct
+                    | KeyWordType::KUnion
+                    | KeyWordType::KEnum
+                    | KeyWordType::KConst
+                    | KeyWordType::KVolatile
+                    | KeyWordType::KRestrict
+                    | KeyWordType::KTypedef
+                    | KeyWordType::KExtern
+                    | KeyWordType::KStatic
+                    | KeyWordType::KAuto
+                    | KeyWordType::KRegister
+                    | KeyWordType::KInline
+            ),
+            Some(TokenType::Identifier) => self.symbols.typedef(token.unwrap().source()).is_some(),
+            _ => false,
+        }
+    }
+
+    fn external_decl(&mut self) -> ParseResult<()> {
+        let start = self.here().start;
+        let specs = self.decl_specs()?;
+        if self.eat_punct(PunctType::Semicolon) {
+            return Ok(());
+        }
+
+        let declarator = self.declarator(specs.ty.clone())?;
+        if let (Some(params), true) = (&declarator.params, self.is_punct(PunctType::LBrace)) {
+            let Some(name) = declarator.name.clone() else {
+                return self.error(String::from("Function definition has no name"));
+            };
+            let params = params.clone();
+            let site = Site::DefinedAt(declarator.span);
+            self.declare(&name, declarator.ty.clone(), specs.storage, site);
+
+            self.symbols.push_scope();
+            for param in &params {
+                if let Some(name) = &param.name {
+                    let site = Site::DefinedAt(param.span);
+                    self.declare(name, param.ty.clone(), None, site);
+                }
+            }
+            // The parameters and the outermost block of the body share a
+            // scope.
+            let body = self.block();
+            self.symbols.pop_scope();
+            let body = body?;
+            let function = FunctionDef {
+                name,
+                ty: declarator.ty,
+                storage: specs.storage,
+                params,
+                body,
+                span: self.span_from(start),
+            };
+            self.unit.decls.push(ExternalDecl::Function(function));
+            return Ok(());
+        }
+
+        for decl in self.init_declarators(&specs, declarator)? {
+            self.unit.decls.push(ExternalDecl::Decl(decl));
+        }
+        Ok(())
+    }
+
+    /// A declaration after its specifiers and first declarator, up to and
+    /// including the `;`.
+    fn init_declarators(&mut self, specs: &DeclSpecs, first: Declarator) -> ParseResult<Vec<Decl>> {
+        let mut decls = Vec::new();
+        let mut declarator = first;
+        loop {
+            let Some(name) = declarator.name else {
+                return Err(ParseError {
+                    message: String::from("Declaration does not declare a name"),
+                    span: declarator.span,
+                    recovered: false,
+                });
+            };
+            let init = if self.eat_op(OperatorType::OpAssign) {
+                Some(self.initializer()?)
+            } else {
+                None
+            };
+            // Prototypes, `extern` declarations and tentative definitions
+            // at file scope leave the definition to somewhere else.
+            let declared_only = specs.storage != Some(StorageClass::Typedef)
+                && init.is_none()
+                && (matches!(declarator.ty, CType::Function { .. })
+                    || specs.storage == Some(StorageClass::Extern)
+                    || self.symbols.is_file_scope());
+            let site = if declared_only {
+                Site::DeclaredAt(declarator.span)
+            } else {
+                Site::DefinedAt(declarator.span)
+            };
+            self.declare(&name, declarator.ty.clone(), specs.storage, site);
+            decls.push(Decl {
+                name,
+                ty: declarator.ty,
+                storage: specs.storage,
+                init,
+                span: self.span_from(declarator.span.start),
+            });
+
+            if !self.eat_punct(PunctType::Comma) {
+                break;
+            }
+            declarator = self.declarator(specs.ty.clone())?;
+        }
+        self.expect_punct(PunctType::Semicolon)?;
+        Ok(decls)
+    }
+
+    /// Adds `name` to the innermost scope, recording an error but carrying on
+    /// if it is already declared there incompatibly.
+    fn declare(&mut self, name: &str, ty: CType, storage: Option<StorageClass>, site: Site) {
+        let info = SymbolInfo {
+            ty,
+            storage,
+            site,
+            value: None,
+        };
+        self.declare_symbol(name, info);
+    }
+
+    fn declare_symbol(&mut self, name: &str, info: SymbolInfo) {
+        if let Err(error) = self.symbols.insert(name, info) {
+            self.errors.push(ParseError {
+                message: format!("Redefinition of '{}'", error.name),
+                span: error.span,
+                recovered: true,
+            });
+        }
+    }
+
+    /// A whole declaration inside a function, `;` included.
+    fn declaration(&mut self) -> ParseResult<Vec<Decl>> {
+        let specs = self.decl_specs()?;
+        if self.eat_punct(PunctType::Semicolon) {
+            return Ok(Vec::new());
+        }
+        let declarator = self.declarator(specs.ty.clone())?;
+        self.init_declarators(&specs, declarator)
+    }
+
+    fn initializer(&mut self) -> ParseResult<Expr> {
+        if !self.is_punct(PunctType::LBrace) {
+            return self.parse_expr(ASSIGNMENT);
+        }
+        let start = self.here().start;
+        self.pos += 1;
+        let mut items = Vec::new();
+        while !self.eat_punct(PunctType::RBrace) {
+            items.push(self.initializer()?);
+            if !self.eat_punct(PunctType::Comma) {
+                self.expect_punct(PunctType::RBrace)?;
+                break;
+            }
+        }
+        Ok(Expr::InitList(items, self.span_from(start)))
+    }
+
+    fn decl_specs(&mut self) -> ParseResult<DeclSpecs> {
+        let start = self.here();
+        let mut counts = BaseCounts::default();
+        let mut named: Option<CType> = None;
+        let mut quals = TypeQuals::NONE;
+        let mut storage = None;
+
+        while let Some(token) = self.peek() {
+            let keyword = match token.token_type() {
+                TokenType::KeyWord(keyword) => *keyword,
+                TokenType::Identifier if named.is_none() && counts.is_empty() => {
+                    match self.symbols.typedef(token.source()) {
+                        Some(ty) => {
+                            let name = String::from(token.source());
+                            named = Some(CType::Typedef(name, Box::new(ty.clone())));
+                            self.pos += 1;
+                            continue;
+                        }
+                        None => break,
+                    }
+                }
+                _ => break,
+            };
+
+            let class = match keyword {
+                KeyWordType::KTypedef => Some(StorageClass::Typedef),
+                KeyWordType::KExtern => Some(StorageClass::Extern),
+                KeyWordType::KStatic => Some(StorageClass::Static),
+                KeyWordType::KAuto => Some(StorageClass::Auto),
+                KeyWordType::KRegister => Some(StorageClass::Register),
+                _ => None,
+            };
+            if class.is_some() {
+                if storage.is_some() {
+                    return self.error(String::from("Multiple storage classes in declaration"));
+                }
+                storage = class;
+                self.pos += 1;
+                continue;
+            }
+
+            match keyword {
+                KeyWordType::KConst => quals |= TypeQuals::CONST,
+                KeyWordType::KVolatile => quals |= TypeQuals::VOLATILE,
+                KeyWordType::KRestrict => quals |= TypeQuals::RESTRICT,
+                KeyWordType::KInline => {}
+                KeyWordType::KVoid => counts.void += 1,
+                KeyWordType::KBool => counts.bool += 1,
+                KeyWordType::KChar => counts.char += 1,
+                KeyWordType::KShort => counts.short += 1,
+                KeyWordType::KInt => counts.int += 1,
+                KeyWordType::KLong => counts.long += 1,
+                KeyWordType::KSigned => counts.signed += 1,
+                KeyWordType::KUnsigned => counts.unsigned += 1,
+                KeyWordType::KFloat => counts.float += 1,
+                KeyWordType::KDouble => counts.double += 1,
+                KeyWordType::KStruct | KeyWordType::KUnion | KeyWordType::KEnum => {
+                    if named.is_some() {
+                        return self.error(String::from(
+                            "Two or more data types in declaration specifiers",
+                        ));
+                    }
+                    named = Some(match keyword {
+                        KeyWordType::KStruct => self.parse_struct_decl()?,
+                        KeyWordType::KUnion => self.parse_union_decl()?,
+                        _ => self.parse_enum_decl()?,
+                    });
+                    continue;
+                }
+                KeyWordType::KComplex | KeyWordType::KImaginary => {
+                    return self.error(format!("'{}' is not supported", keyword));
+                }
+                _ => break,
+            }
+            self.pos += 1;
+        }
+
+        let ty = match (named, counts.is_empty()) {
+            (Some(ty), true) => ty,
+            (None, false) => match counts.ctype() {
+                Some(ty) => ty,
+                None => {
+                    return Err(ParseError {
+                        message: String::from("Invalid combination of type specifiers"),
+                        span: self.span_from(start.start),
+                        recovered: false,
+                    })
+                }
+            },
+            (Some(_), false) => {
+                return Err(ParseError {
+                    message: String::from("Two or more data types in declaration specifiers"),
+                    span: self.span_from(start.start),
+                    recovered: false,
+                })
+            }
+            (None, true) => {
+                return Err(ParseError {
+                    message: format!("Expected a type before {}", self.found()),
+                    span: start,
+                    recovered: false,
+                })
+            }
+        };
+        let ty = if quals.is_empty() {
+            ty
+        } else {
+            CType::Qualified {
+                inner: Box::new(ty),
+                quals,
+            }
+        };
+        Ok(DeclSpecs { ty, storage })
+    }
+
+    fn type_quals(&mut self) -> TypeQuals {
+        let mut quals = TypeQuals::NONE;
+        loop {
+            if self.eat_keyword(KeyWordType::KConst) {
+                quals |= TypeQuals::CONST;
+            } else if self.eat_keyword(KeyWordType::KVolatile) {
+                quals |= TypeQuals::VOLATILE;
+            } else if self.eat_keyword(KeyWordType::KRestrict) {
+                quals |= TypeQuals::RESTRICT;
+            } else {
+                return quals;
+            }
+        }
+    }
+
+    /// The optional tag after `struct`, `union` or `enum`, which can only be
+    /// left out when a body follows.
+    fn tag(&mut self) -> ParseResult<(Option<String>, Span)> {
+        let span = self.here();
+        let tag = match self.peek_type() {
+            Some(TokenType::Identifier) => Some(self.expect_identifier()?),
+            _ => None,
+        };
+        if tag.is_none() && !self.is_punct(PunctType::LBrace) {
+            return self.error(format!(
+                "Expected a tag name or '{{' before {}",
+                self.found()
+            ));
+        }
+        Ok((tag, span))
+    }
+
+    fn parse_struct_decl(&mut self) -> ParseResult<CType> {
+        self.parse_record_decl(false)
+    }
+
+    fn parse_union_decl(&mut self) -> ParseResult<CType> {
+        self.parse_record_decl(true)
+    }
+
+    /// `struct` or `union`, an optional tag and an optional member list. A
+    /// tag seen before refers to the same `StructId`; a new or missing tag
+    /// gets a new one, which stays a forward declaration until a member list
+    /// defines it.
+    fn parse_record_decl(&mut self, is_union: bool) -> ParseResult<CType> {
+        let start = self.here().start;
+        self.pos += 1;
+        let (tag, tag_span) = self.tag()?;
+        let keyword = if is_union { "union" } else { "struct" };
+
+        let id = match tag.as_ref().and_then(|tag| self.tags.get(tag)) {
+            Some(Tag::Struct(id)) if !is_union => *id,
+            Some(Tag::Union(id)) if is_union => *id,
+            Some(_) => {
+                return Err(ParseError {
+                    message: format!("'{}' defined as wrong kind of tag", tag.unwrap_or_default()),
+                    span: tag_span,
+                    recovered: false,
+                })
+            }
+            None => {
+                let id = StructId(self.unit.structs.len());
+                self.unit.structs.push(StructDef {
+                    tag: tag.clone(),
+                    is_union,
+                    fields: None,
+                    span: self.span_from(start),
+                });
+                if let Some(tag) = tag.clone() {
+                    let kind = if is_union {
+                        Tag::Union(id)
+                    } else {
+                        Tag::Struct(id)
+                    };
+                    self.tags.insert(tag, kind);
+                }
+                id
+            }
+        };
+
+        if self.is_punct(PunctType::LBrace) {
+            if self.unit.structs[id.0].fields.is_some() {
+                return Err(ParseError {
+                    message: format!("Redefinition of '{} {}'", keyword, tag.unwrap_or_default()),
+                    span: tag_span,
+                    recovered: false,
+                });
+            }
+            self.pos += 1;
+            let mut fields = Vec::new();
+            while !self.eat_punct(PunctType::RBrace) {
+                fields.extend(self.field_decls()?);
+            }
+            let span = self.span_from(start);
+            let def = &mut self.unit.structs[id.0];
+            def.fields = Some(fields);
+            def.span = span;
+        }
+        Ok(if is_union {
+            CType::Union(id)
+        } else {
+            CType::Struct(id)
+        })
+    }
+
+    /// One member declaration, which may declare several members, up to and
+    /// including its `;`.
+    fn field_decls(&mut self) -> ParseResult<Vec<FieldDecl>> {
+        let start = self.here().start;
+        let specs = self.decl_specs()?;
+        if specs.storage.is_some() {
+            return self.error(String::from("Storage class specified for a member"));
+        }
+
+        if self.eat_punct(PunctType::Semicolon) {
+            // A C11 anonymous struct or union, whose members belong to the
+            // enclosing one.
+            let anonymous = match specs.ty {
+                CType::Struct(id) | CType::Union(id) => self.unit.structs[id.0].tag.is_none(),
+                _ => false,
+            };
+            if !anonymous {
+                return Err(ParseError {
+                    message: String::from("Declaration does not declare anything"),
+                    span: self.span_from(start),
+                    recovered: false,
+                });
+            }
+            return Ok(vec![FieldDecl {
+                name: None,
+                ty: specs.ty,
+                bit_width: None,
+                span: self.span_from(start),
+            }]);
+        }
+
+        let mut fields = Vec::new();
+        loop {
+            let start = self.here().start;
+            // A bit-field's name may be left out, as in `int : 4;`.
+            let (name, ty) = if self.is_op(OperatorType::OpColon) {
+                (None, specs.ty.clone())
+            } else {
+                let declarator = self.declarator(specs.ty.clone())?;
+                (declarator.name, declarator.ty)
+            };
+            let bit_width = if self.eat_op(OperatorType::OpColon) {
+                Some(self.parse_expr(CONDITIONAL)?)
+            } else {
+                None
+            };
+            if name.is_none() && bit_width.is_none() {
+                return Err(ParseError {
+                    message: String::from("Member has no name"),
+                    span: self.span_from(start),
+                    recovered: false,
+                });
+            }
+            fields.push(FieldDecl {
+                name,
+                ty,
+                bit_width,
+                span: self.span_from(start),
+            });
+            if !self.eat_punct(PunctType::Comma) {
+                break;
+            }
+        }
+        self.expect_punct(PunctType::Semicolon)?;
+        Ok(fields)
+    }
+
+    /// `enum`, an optional tag and an optional list of constants, each with
+    /// an optional value.
+    fn parse_enum_decl(&mut self) -> ParseResult<CType> {
+        let start = self.here().start;
+        self.pos += 1;
+        let (tag, tag_span) = self.tag()?;
+
+        let id = match tag.as_ref().and_then(|tag| self.tags.get(tag)) {
+            Some(Tag::Enum(id)) => *id,
+            Some(_) => {
+                return Err(ParseError {
+                    message: format!("'{}' defined as wrong kind of tag", tag.unwrap_or_default()),
+                    span: tag_span,
+                    recovered: false,
+                })
+            }
+            None => {
+                let id = EnumId(self.unit.enums.len());
+                self.unit.enums.push(EnumDef {
+                    tag: tag.clone(),
+                    constants: None,
+                    span: self.span_from(start),
+                });
+                if let Some(tag) = tag.clone() {
+                    self.tags.insert(tag, Tag::Enum(id));
+                }
+                id
+            }
+        };
+
+        if self.is_punct(PunctType::LBrace) {
+            if self.unit.enums[id.0].constants.is_some() {
+                return Err(ParseError {
+                    message: format!("Redefinition of 'enum {}'", tag.unwrap_or_default()),
+                    span: tag_span,
+                    recovered: false,
+                });
+            }
+            self.pos += 1;
+            let mut constants = Vec::new();
+            // Each constant is one more than the last unless given a value.
+            let mut next = 0;
+            while !self.eat_punct(PunctType::RBrace) {
+                let start = self.here().start;
+                let name = self.expect_identifier()?;
+                let init = if self.eat_op(OperatorType::OpAssign) {
+                    let init = self.parse_expr(CONDITIONAL)?;
+                    match self.unit.const_eval(&init) {
+                        Some(value) => next = value,
+                        None => self.errors.push(ParseError {
+                            message: String::from("Enumerator value is not an integer constant"),
+                            span: init.span(),
+                            recovered: true,
+                        }),
+                    }
+                    Some(init)
+                } else {
+                    None
+                };
+                let span = self.span_from(start);
+                let info = SymbolInfo {
+                    ty: CType::Enum(id),
+                    storage: None,
+                    site: Site::DefinedAt(span),
+                    value: Some(next),
+                };
+                self.declare_symbol(&name, info);
+                constants.push(EnumConst {
+                    name,
+                    init,
+                    value: next,
+                    span,
+                });
+                next = next.wrapping_add(1);
+                if !self.eat_punct(PunctType::Comma) {
+                    self.expect_punct(PunctType::RBrace)?;
+                    break;
+                }
+            }
+            let span = self.span_from(start);
+            let def = &mut self.unit.enums[id.0];
+            def.constants = Some(constants);
+            def.span = span;
+        }
+        Ok(CType::Enum(id))
+    }
+
+    /// Parses a declarator, or an abstract one with no name, around `ty`.
+    fn declarator(&mut self, mut ty: CType) -> ParseResult<Declarator> {
+        let start = self.here().start;
+        while self.eat_op(OperatorType::OpMul) {
+            let quals = self.type_quals();
+            ty = CType::Pointer {
+                pointee: Box::new(ty),
+                quals,
+            };
+        }
+
+        if self.is_punct(PunctType::LParen) && self.nested_declarator() {
+            // In `int (*p)[3]` the suffix after the parentheses applies
+            // first, so skip ahead to it, then come back for the inside.
+            self.pos += 1;
+            let inner = self.pos;
+            self.skip_parens()?;
+            let (ty, _) = self.type_suffix(ty)?;
+            let end = self.pos;
+            self.pos = inner;
+            let declarator = self.declarator(ty)?;
+            self.expect_punct(PunctType::RParen)?;
+            self.pos = end;
+            return Ok(Declarator {
+                span: self.span_from(start),
+                ..declarator
+            });
+        }
+
+        let name = match self.peek_type() {
+            Some(TokenType::Identifier) => Some(self.expect_identifier()?),
+            _ => None,
+        };
+        let (ty, params) = self.type_suffix(ty)?;
+        Ok(Declarator {
+            name,
+            ty,
+            params,
+            span: self.span_from(start),
+        })
+    }
+
+    /// Whether the `(` ahead opens a parenthesized declarator rather than a
+    /// parameter list.
+    fn nested_declarator(&self) -> bool {
+        match self.peek_nth(1).map(Token::token_type) {
+            Some(TokenType::Operator(OperatorType::OpMul)) => true,
+            Some(TokenType::Punct(PunctType::LParen)) => true,
+            Some(TokenType::Identifier) => !self.starts_type(self.peek_nth(1)),
+            _ => false,
+        }
+    }
+
+    /// Skips to just past the `)` closing the one before the current token.
+    fn skip_parens(&mut self) -> ParseResult<()> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next().map(Token::token_type) {
+                Some(TokenType::Punct(PunctType::LParen)) => depth += 1,
+                Some(TokenType::Punct(PunctType::RParen)) => depth -= 1,
+                Some(_) => {}
+                None => return self.error(String::from("Expected ')' before end of input")),
+            }
+        }
+        Ok(())
+    }
+
+    /// Array and function suffixes, e.g. the `[3]` in `int a[3]`.
+    fn type_suffix(&mut self, ty: CType) -> ParseResult<(CType, Option<Vec<Param>>)> {
+        if self.eat_punct(PunctType::LParen) {
+            let (params, variadic) = self.params()?;
+            let ty = CType::Function {
+                ret: Box::new(ty),
+                params: params.iter().map(|param| param.ty.clone()).collect(),
+                variadic,
+            };
+            return Ok((ty, Some(params)));
+        }
+
+        if self.eat_punct(PunctType::LBracket) {
+            let size = if self.eat_punct(PunctType::RBracket) {
+                ArraySize::Incomplete
+            } else {
+                let expr = self.parse_expr(ASSIGNMENT)?;
+                let size = match self.unit.const_value(&expr) {
+                    Some((size, ty))
+                        if size < 0 && ty.int_repr().is_some_and(|(_, signed)| signed) =>
+                    {
+                        self.errors.push(ParseError {
+                            message: String::from("Array has a negative size"),
+                            span: expr.span(),
+                            recovered: true,
+                        });
+                        ArraySize::Incomplete
+                    }
+                    Some((size, _)) => ArraySize::Fixed(size as u64),
+                    None => ArraySize::Variable,
+                };
+                self.expect_punct(PunctType::RBracket)?;
+                size
+            };
+            let (elem, _) = self.type_suffix(ty)?;
+            let ty = CType::Array {
+                elem: Box::new(elem),
+                size,
+            };
+            return Ok((ty, None));
+        }
+
+        Ok((ty, None))
+    }
+
+    /// A parameter list after its `(`, up to and including the `)`.
+    fn params(&mut self) -> ParseResult<(Vec<Param>, bool)> {
+        let mut params = Vec::new();
+        if self.eat_punct(PunctType::RParen) {
+            return Ok((params, false));
+        }
+        if self.is_keyword(KeyWordType::KVoid)
+            && self.peek_nth(1).map(Token::token_type) == Some(&TokenType::Punct(PunctType::RParen))
+        {
+            self.pos += 2;
+            return Ok((params, false));
+        }
+
+        loop {
+            if self.eat_op(OperatorType::OpEllipsis) {
+                self.expect_punct(PunctType::RParen)?;
+                return Ok((params, true));
+            }
+            let specs = self.decl_specs()?;
+            let declarator = self.declarator(specs.ty)?;
+            params.push(Param {
+                name: declarator.name,
+                ty: adjust_param(declarator.ty),
+                span: declarator.span,
+            });
+            if !self.eat_punct(PunctType::Comma) {
+                break;
+            }
+        }
+        self.expect_punct(PunctType::RParen)?;
+        Ok((params, false))
+    }
+
+    /// A type with no name, as in a cast or `sizeof`.
+    fn type_name(&mut self) -> ParseResult<CType> {
+        let specs = self.decl_specs()?;
+        if specs.storage.is_some() {
+            return self.error(String::from("Storage class specified for a type name"));
+        }
+        let declarator = self.declarator(specs.ty)?;
+        if declarator.name.is_some() {
+            return Err(ParseError {
+                message: String::from("Unexpected name in type name"),
+                span: declarator.span,
+                recovered: false,
+            });
+        }
+        Ok(declarator.ty)
+    }
+
+    fn compound_stmt(&mut self) -> ParseResult<Stmt> {
+        self.symbols.push_scope();
+        let stmt = self.block();
+        self.symbols.pop_scope();
+        stmt
+    }
+
+    fn block(&mut self) -> ParseResult<Stmt> {
+        let start = self.here().start;
+        self.expect_punct(PunctType::LBrace)?;
+        let mut items = Vec::new();
+        while !self.eat_punct(PunctType::RBrace) {
+            if self.peek().is_none() {
+                return self.error(String::from("Expected '}' before end of input"));
+            }
+            let label = self.peek_nth(1).map(Token::token_type)
+                == Some(&TokenType::Operator(OperatorType::OpColon));
+            let item = if self.starts_type(self.peek()) && !label {
+                self.declaration()
+                    .map(|decls| decls.into_iter().map(BlockItem::Decl).collect())
+            } else {
+                self.stmt().map(|stmt| vec![BlockItem::Stmt(stmt)])
+            };
+            match item {
+                Ok(item) => items.extend(item),
+                Err(error) => {
+                    self.synchronize(true);
+                    self.recover(error);
+                }
+            }
+        }
+        Ok(Stmt::Block(items, self.span_from(start)))
+    }
+
+    fn paren_expr(&mut self) -> ParseResult<Expr> {
+        self.expect_punct(PunctType::LParen)?;
+        let expr = self.expr()?;
+        self.expect_punct(PunctType::RParen)?;
+        Ok(expr)
+    }
+
+    fn stmt(&mut self) -> ParseResult<Stmt> {
+        let start = self.here().start;
+        let Some(token) = self.peek() else {
+            return self.error(String::from("Expected a statement before end of input"));
+        };
+
+        let keyword = match token.token_type() {
+            TokenType::Punct(PunctType::LBrace) => return self.compound_stmt(),
+            TokenType::Punct(PunctType::Semicolon) => {
+                self.pos += 1;
+                return Ok(Stmt::Empty(self.span_from(start)));
+            }
+            TokenType::Identifier
+                if self.peek_nth(1).map(Token::token_type)
+                    == Some(&TokenType::Operator(OperatorType::OpColon)) =>
+            {
+                self.pos += 2;
+                let stmt = self.stmt()?;
+                let label = String::from(token.source());
+                return Ok(Stmt::Label(label, Box::new(stmt), self.span_from(start)));
+            }
+            TokenType::KeyWord(keyword) => *keyword,
+            _ => {
+                let expr = self.expr()?;
+                self.expect_punct(PunctType::Semicolon)?;
+                return Ok(Stmt::Expr(expr, self.span_from(start)));
+            }
+        };
+
+        self.pos += 1;
+        let stmt = match keyword {
+            KeyWordType::KIf => {
+                let cond = self.paren_expr()?;
+                let then = Box::new(self.stmt()?);
+                let else_ = if self.eat_keyword(KeyWordType::KElse) {
+                    Some(Box::new(self.stmt()?))
+                } else {
+                    None
+                };
+                Stmt::If {
+                    cond,
+                    then,
+                    else_,
+                    span: self.span_from(start),
+                }
+            }
+            KeyWordType::KWhile => {
+                let cond = self.paren_expr()?;
+                let body = Box::new(self.stmt()?);
+                Stmt::While {
+                    cond,
+                    body,
+                    span: self.span_from(start),
+                }
+            }
+            KeyWordType::KDo => {
+                let body = Box::new(self.stmt()?);
+                if !self.eat_keyword(KeyWordType::KWhile) {
+                    return self.error(format!("Expected 'while' before {}", self.found()));
+                }
+                let cond = self.paren_expr()?;
+                self.expect_punct(PunctType::Semicolon)?;
+                Stmt::DoWhile {
+                    body,
+                    cond,
+                    span: self.span_from(start),
+                }
+            }
+            KeyWordType::KFor => {
+                // A declaration in the first clause is scoped to the loop.
+                self.symbols.push_scope();
+                let stmt = self.for_stmt(start);
+                self.symbols.pop_scope();
+                stmt?
+            }
+            KeyWordType::KSwitch => {
+                let expr = self.paren_expr()?;
+                let body = Box::new(self.stmt()?);
+                Stmt::Switch {
+                    expr,
+                    body,
+                    span: self.span_from(start),
+                }
+            }
+            KeyWordType::KCase => {
+                let value = self.parse_expr(CONDITIONAL)?;
+                self.expect_op(OperatorType::OpColon)?;
+                let stmt = Box::new(self.stmt()?);
+                Stmt::Case(value, stmt, self.span_from(start))
+            }
+            KeyWordType::KDefault => {
+                self.expect_op(OperatorType::OpColon)?;
+                let stmt = Box::new(self.stmt()?);
+                Stmt::Default(stmt, self.span_from(start))
+            }
+            KeyWordType::KReturn => {
+                let value = if self.is_punct(PunctType::Semicolon) {
+                    None
+                } else {
+                    Some(self.expr()?)
+                };
+                self.expect_punct(PunctType::Semicolon)?;
+                Stmt::Return(value, self.span_from(start))
+            }
+            KeyWordType::KBreak => {
+                self.expect_punct(PunctType::Semicolon)?;
+                Stmt::Break(self.span_from(start))
+            }
+            KeyWordType::KContinue => {
+                self.expect_punct(PunctType::Semicolon)?;
+                Stmt::Continue(self.span_from(start))
+            }
+            KeyWordType::KGoto => {
+                let label = self.expect_identifier()?;
+                self.expect_punct(PunctType::Semicolon)?;
+                Stmt::Goto(label, self.span_from(start))
+            }
+            _ => {
+                // `sizeof x;` and the like.
+                self.pos -= 1;
+                let expr = self.expr()?;
+                self.expect_punct(PunctType::Semicolon)?;
+                Stmt::Expr(expr, self.span_from(start))
+            }
+        };
+        Ok(stmt)
+    }
+
+    /// The rest of a `for` statement after its keyword.
+    fn for_stmt(&mut self, start: usize) -> ParseResult<Stmt> {
+        self.expect_punct(PunctType::LParen)?;
+        let init = if self.eat_punct(PunctType::Semicolon) {
+            None
+        } else if self.starts_type(self.peek()) {
+            Some(ForInit::Decl(self.declaration()?))
+        } else {
+            let expr = self.expr()?;
+            self.expect_punct(PunctType::Semicolon)?;
+            Some(ForInit::Expr(expr))
+        };
+        let cond = if self.is_punct(PunctType::Semicolon) {
+            None
+        } else {
+            Some(self.expr()?)
+        };
+        self.expect_punct(PunctType::Semicolon)?;
+        let step = if self.is_punct(PunctType::RParen) {
+            None
+        } else {
+            Some(self.expr()?)
+        };
+        self.expect_punct(PunctType::RParen)?;
+        let body = Box::new(self.stmt()?);
+        Ok(Stmt::For {
+            init,
+            cond,
+            step,
+            body,
+            span: self.span_from(start),
+        })
+    }
+
+    fn binop(op: BinOpKind, lhs: Expr, rhs: Expr) -> Expr {
+        let span = Span {
+            start: lhs.span().start,
+            end: rhs.span().end,
+        };
+        Expr::BinOp {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+            span,
+        }
+    }
+
+    /// A full expression, commas included.
+    fn expr(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.parse_expr(ASSIGNMENT)?;
+        while self.eat_punct(PunctType::Comma) {
+            let rhs = self.parse_expr(ASSIGNMENT)?;
+            expr = Self::binop(BinOpKind::Comma, expr, rhs);
+        }
+        Ok(expr)
+    }
+
+    /// Parses operands joined by operators binding at least as tightly as
+    /// `min_prec`, by precedence climbing: after each operand, an operator
+    /// from `PRECEDENCE` at or above `min_prec` takes everything so far as
+    /// its left operand, and its right operand is whatever binds tighter
+    /// than it, or as tightly for the right-associative ones.
+    fn parse_expr(&mut self, min_prec: u8) -> ParseResult<Expr> {
+        let mut lhs = self.cast()?;
+        while let Some(TokenType::Operator(op)) = self.peek_type() {
+            let op = *op;
+            let (prec, assoc) = match PRECEDENCE[op as usize] {
+                Some((prec, assoc)) if prec >= min_prec => (prec, assoc),
+                _ => break,
+            };
+            self.pos += 1;
+            let next = match assoc {
+                Assoc::Left => prec + 1,
+                Assoc::Right => prec,
+            };
+
+            if op == OperatorType::OpQuestion {
+                let then = self.expr()?;
+                self.expect_op(OperatorType::OpColon)?;
+                let els = self.parse_expr(next)?;
+                let span = Span {
+                    start: lhs.span().start,
+                    end: els.span().end,
+                };
+                lhs = Expr::Ternary {
+                    cond: Box::new(lhs),
+                    then: Box::new(then),
+                    els: Box::new(els),
+                    span,
+                };
+            } else {
+                let rhs = self.parse_expr(next)?;
+                lhs = Self::binop(BinOpKind::from_operator(op).unwrap(), lhs, rhs);
+            }
+        }
+        Ok(lhs)
+    }
+
+    fn cast(&mut self) -> ParseResult<Expr> {
+        if !self.is_punct(PunctType::LParen) || !self.starts_type(self.peek_nth(1)) {
+            return self.unary();
+        }
+        let start = self.here().start;
+        self.pos += 1;
+        let ty = self.type_name()?;
+        self.expect_punct(PunctType::RParen)?;
+        if self.is_punct(PunctType::LBrace) {
+            return self.error(String::from("Compound literals are not supported"));
+        }
+        let expr = Box::new(self.cast()?);
+        Ok(Expr::Cast {
+            ty,
+            expr,
+            span: self.span_from(start),
+        })
+    }
+
+    fn unary(&mut self) -> ParseResult<Expr> {
+        let start = self.here().start;
+        if self.eat_keyword(KeyWordType::KSizeof) {
+            let arg = if self.is_punct(PunctType::LParen) && self.starts_type(self.peek_nth(1)) {
+                self.pos += 1;
+                let ty = self.type_name()?;
+                self.expect_punct(PunctType::RParen)?;
+                SizeofArg::Type(ty)
+            } else {
+                SizeofArg::Expr(Box::new(self.unary()?))
+            };
+            return Ok(Expr::Sizeof(arg, self.span_from(start)));
+        }
+
+        let op = match self.peek_type() {
+            Some(TokenType::Operator(op)) => match op {
+                OperatorType::OpAdd => UnaryOpKind::Plus,
+                OperatorType::OpSub => UnaryOpKind::Neg,
+                OperatorType::OpLogNot => UnaryOpKind::LogNot,
+                OperatorType::OpBitNot => UnaryOpKind::BitNot,
+                OperatorType::OpMul => UnaryOpKind::Deref,
+                OperatorType::OpBitAnd => UnaryOpKind::AddrOf,
+                OperatorType::OpInc => UnaryOpKind::PreInc,
+                OperatorType::OpDec => UnaryOpKind::PreDec,
+                _ => return self.postfix(),
+            },
+            _ => return self.postfix(),
+        };
+        self.pos += 1;
+        let operand = match op {
+            UnaryOpKind::PreInc | UnaryOpKind::PreDec => self.unary()?,
+            _ => self.cast()?,
+        };
+        Ok(Expr::UnaryOp {
+            op,
+            expr: Box::new(operand),
+            span: self.span_from(start),
+        })
+    }
+
+    fn postfix(&mut self) -> ParseResult<Expr> {
+        let start = self.here().start;
+        let mut expr = self.primary()?;
+        loop {
+            if self.eat_punct(PunctType::LBracket) {
+                let idx = self.expr()?;
+                self.expect_punct(PunctType::RBracket)?;
+                expr = Expr::Index {
+                    base: Box::new(expr),
+                    idx: Box::new(idx),
+                    span: self.span_from(start),
+                };
+            } else if self.eat_punct(PunctType::LParen) {
+                let mut args = Vec::new();
+                if !self.eat_punct(PunctType::RParen) {
+                    loop {
+                        args.push(self.parse_expr(ASSIGNMENT)?);
+                        if !self.eat_punct(PunctType::Comma) {
+                            break;
+                        }
+                    }
+                    self.expect_punct(PunctType::RParen)?;
+                }
+                expr = Expr::Call {
+                    callee: Box::new(expr),
+                    args,
+                    span: self.span_from(start),
+                };
+            } else if self.is_op(OperatorType::OpDot) || self.is_op(OperatorType::OpArrow) {
+                let arrow = self.is_op(OperatorType::OpArrow);
+                self.pos += 1;
+                let field = self.expect_identifier()?;
+                expr = Expr::Member {
+                    obj: Box::new(expr),
+                    field,
+                    arrow,
+                    span: self.span_from(start),
+                };
+            } else if self.is_op(OperatorType::OpInc) || self.is_op(OperatorType::OpDec) {
+                let op = if self.is_op(OperatorType::OpInc) {
+                    UnaryOpKind::PostInc
+                } else {
+                    UnaryOpKind::PostDec
+                };
+                self.pos += 1;
+                expr = Expr::UnaryOp {
+                    op,
+                    expr: Box::new(expr),
+                    span: self.span_from(start),
+                };
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> ParseResult<Expr> {
+        let Some(token) = self.peek() else {
+            return self.error(String::from("Expected an expression before end of input"));
+        };
+        let span = token.span();
+
+        let expr = match token.token_type() {
+            TokenType::Number(suffix) => {
+                let value = match token.value() {
+                    Some(LiteralValue::Int(value)) => value,
+                    _ => 0,
+                };
+                let decimal = !token.source().starts_with('0') || token.source() == "0";
+                Expr::IntLit(value, int_literal_type(value, *suffix, decimal), span)
+            }
+            TokenType::Char(_) => {
+                let value = match token.value() {
+                    Some(LiteralValue::Char(value)) => value as u64,
+                    _ => 0,
+                };
+                Expr::IntLit(value, INT, span)
+            }
+            TokenType::FlotNumber(_) => {
+                let value = match token.value() {
+                    Some(LiteralValue::Float(value)) => value,
+                    _ => 0.0,
+                };
+                Expr::FloatLit(value, span)
+            }
+            TokenType::Str(_) => {
+                // Adjacent string literals are one string, in the encoding
+                // of the first one with a prefix.
+                let start = self.pos;
+                let mut encoding = Encoding::None;
+                while let Some(TokenType::Str(next)) = self.peek().map(|token| token.token_type()) {
+                    if encoding == Encoding::None {
+                        encoding = *next;
+                    }
+                    self.pos += 1;
+                }
+                let narrow = |encoding| matches!(encoding, Encoding::None | Encoding::Utf8);
+                let mut values = Vec::new();
+                for token in &self.tokens[start..self.pos] {
+                    let mut token_values = token.string_value().unwrap_or_default();
+                    let TokenType::Str(token_encoding) = token.token_type() else {
+                        unreachable!()
+                    };
+                    // The UTF-8 of a plain string becomes characters in a
+                    // wide one.
+                    if narrow(*token_encoding) && !narrow(encoding) {
+                        let bytes: Vec<u8> =
+                            token_values.iter().map(|value| *value as u8).collect();
+                        token_values = String::from_utf8_lossy(&bytes)
+                            .chars()
+                            .map(u32::from)
+                            .collect();
+                    }
+                    for value in token_values {
+                        match char::from_u32(value) {
+                            Some(c) if encoding == Encoding::Utf16 && value > 0xffff => values
+                                .extend(
+                                    c.encode_utf16(&mut [0; 2]).iter().map(|unit| *unit as u32),
+                                ),
+                            _ => values.push(value),
+                        }
+                    }
+                }
+                return Ok(Expr::StrLit(values, encoding, self.span_from(span.start)));
+            }
+            TokenType::Identifier => {
+                let name = String::from(token.source());
+                match self.symbols.lookup(&name).and_then(|info| info.value) {
+                    Some(value) => Expr::Enumerator(name, value, span),
+                    None => Expr::Ident(name, span),
+                }
+            }
+            TokenType::Punct(PunctType::LParen) => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                self.expect_punct(PunctType::RParen)?;
+                return Ok(expr);
+            }
+            _ => return self.error(format!("Expected an expression before {}", self.found())),
+        };
+        self.pos += 1;
+        Ok(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex::Lex;
+
+    fn parse_source(src: &str) -> Result<TranslationUnit, Vec<ParseError>> {
+        let mut lex = Lex::from_source("test.c", src);
+        lex.parse().unwrap();
+        Parser::new(lex.token_stream()).parse()
+    }
+
+    /// A compact prefix rendering of an expression, to check its shape.
+    fn sexpr(expr: &Expr) -> String {
+        match expr {
+            Expr::IntLit(value, _, _) => value.to_string(),
+            Expr::FloatLit(value, _) => format!("{:?}", value),
+            Expr::StrLit(values, ..) => {
+                let text: String = values
+                    .iter()
+                    .filter_map(|value| char::from_u32(*value))
+                    .collect();
+                format!("{:?}", text)
+            }
+            Expr::Ident(name, _) | Expr::Enumerator(name, ..) => name.clone(),
+            Expr::BinOp { op, lhs, rhs, .. } => {
+                format!("({:?} {} {})", op, sexpr(lhs), sexpr(rhs))
+            }
+            Expr::UnaryOp { op, expr, .. } => format!("({:?} {})", op, sexpr(expr)),
+            Expr::Call { callee, args, .. } => {
+                let args: Vec<String> = args.iter().map(sexpr).collect();
+                format!("(call {} {})", sexpr(callee), args.join(" "))
+            }
+            Expr::Index { base, idx, .. } => format!("(index {} {})", sexpr(base), sexpr(idx)),
+            Expr::Member {
+                obj, field, arrow, ..
+            } => format!(
+                "({} {} {})",
+                if *arrow { "->" } else { "." },
+                sexpr(obj),
+                field
+            ),
+            Expr::Cast { ty, expr, .. } => format!("(cast {:?} {})", ty, sexpr(expr)),
+            Expr::Ternary {
+                cond, then, els, ..
+            } => format!("(? {} {} {})", sexpr(cond), sexpr(then), sexpr(els)),
+            Expr::Sizeof(SizeofArg::Type(ty), _) => format!("(sizeof {:?})", ty),
+            Expr::Sizeof(SizeofArg::Expr(expr), _) => format!("(sizeof {})", sexpr(expr)),
+            Expr::InitList(items, _) => {
+                let items: Vec<String> = items.iter().map(sexpr).collect();
+                format!("{{{}}}", items.join(" "))
+            }
+        }
+    }
+
+    fn decl(unit: &TranslationUnit, index: usize) -> &Decl {
+        match &unit.decls[index] {
+            ExternalDecl::Decl(decl) => decl,
+            ExternalDecl::Function(function) => panic!("{} is a function", function.name),
+        }
+    }
+
+    fn init(src: &str) -> String {
+        let unit = parse_source(src).unwrap();
+        sexpr(decl(&unit, unit.decls.len() - 1).init.as_ref().unwrap())
+    }
+
+    fn pointer(ty: CType) -> CType {
+        CType::Pointer {
+            pointee: Box::new(ty),
+            quals: TypeQuals::NONE,
+        }
+    }
+
+    #[test]
+    fn expressions() {
+        let cases = [
+            ("int x = a + b * c;", "(Add a (Mul b c))"),
+            ("int x = a - b - c;", "(Sub (Sub a b) c)"),
+            ("int x = a || b && c | d;", "(LogOr a (LogAnd b (BitOr c d)))"),
+            ("int x = a << 1 < b == c;", "(Eq (Lt (Shl a 1) b) c)"),
+            ("int x = a = b += c;", "(Assign a (AddAssign b c))"),
+            ("int x = a ? b : c ? d : e;", "(? a b (? c d e))"),
+            ("int x = -*p++;", "(Neg (Deref (PostInc p)))"),
+            ("int x = f(a, b)[1].y->z;", "(-> (. (index (call f a b) 1) y) z)"),
+            ("int x = (long)'a' + sizeof(int) + sizeof x;", "(Add (Add (cast Int { size: Long, sign: Signed } 97) (sizeof Int { size: Int, sign: Signed })) (sizeof x))"),
+            ("char *s = \"ab\" \"c\";", "\"abc\""),
+            ("char *s = \"a\\tb\" L\"\\x41\";", "\"a\\tbA\""),
+            ("int a[] = {1, {2, 3},};", "{1 {2 3}}"),
+            ("double d = (1.5);", "1.5"),
+        ];
+        for (src, expected) in cases {
+            assert_eq!(init(src), expected, "{}", src);
+        }
+
+        let unit = parse_source("void f(void) { a, b = c; }").unwrap();
+        let ExternalDecl::Function(function) = &unit.decls[0] else {
+            panic!()
+        };
+        let Stmt::Block(items, _) = &function.body else {
+            panic!()
+        };
+        let BlockItem::Stmt(Stmt::Expr(expr, _)) = &items[0] else {
+            panic!()
+        };
+        assert_eq!(sexpr(expr), "(Comma a (Assign b c))");
+    }
+
+    #[test]
+    fn declarators() {
+        let unit = parse_source(
+            "unsigned long long a, *b[2][3];\nint (*fp)(int, char *);\nconst char *const s;\nstatic int f(int n, int a[], ...);\ntypedef int T; T t;",
+        )
+        .unwrap();
+
+        let ull = CType::Int {
+            size: IntSize::LongLong,
+            sign: Signedness::Unsigned,
+        };
+        assert_eq!(decl(&unit, 0).ty, ull);
+        let array = |elem, size| CType::Array {
+            elem: Box::new(elem),
+            size: ArraySize::Fixed(size),
+        };
+        assert_eq!(decl(&unit, 1).ty, array(array(pointer(ull), 3), 2));
+
+        let function = CType::Function {
+            ret: Box::new(INT),
+            params: vec![INT, pointer(CType::Char(Signedness::Signed))],
+            variadic: false,
+        };
+        assert_eq!(decl(&unit, 2).name, "fp");
+        assert_eq!(decl(&unit, 2).ty, pointer(function));
+
+        let char_const = CType::Qualified {
+            inner: Box::new(CType::Char(Signedness::Signed)),
+            quals: TypeQuals::CONST,
+        };
+        let s = CType::Pointer {
+            pointee: Box::new(char_const),
+            quals: TypeQuals::CONST,
+        };
+        assert_eq!(decl(&unit, 3).ty, s);
+
+        assert_eq!(decl(&unit, 4).storage, Some(StorageClass::Static));
+        assert_eq!(
+            decl(&unit, 4).ty,
+            CType::Function {
+                ret: Box::new(INT),
+                params: vec![INT, pointer(INT)],
+                variadic: true,
+            }
+        );
+
+        assert_eq!(decl(&unit, 5).storage, Some(StorageClass::Typedef));
+        assert_eq!(
+            decl(&unit, 6).ty,
+            CType::Typedef(String::from("T"), Box::new(INT))
+        );
+    }
+
+    #[test]
+    fn array_sizes() {
+        let unit = parse_source(
+            "int a[2 * 4];\nenum { N = 3, M };\nint b[N], c[sizeof(int)], d[M + (char)257];\n\
+             int f(int n) { int N = n; int v[N]; return v[0]; }",
+        )
+        .unwrap();
+        let sizes: Vec<ArraySize> = unit
+            .decls
+            .iter()
+            .filter_map(|decl| match decl {
+                ExternalDecl::Decl(Decl {
+                    ty: CType::Array { size, .. },
+                    ..
+                }) => Some(*size),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            sizes,
+            [
+                ArraySize::Fixed(8),
+                ArraySize::Fixed(3),
+                ArraySize::Fixed(4),
+                ArraySize::Fixed(5)
+            ]
+        );
+
+        // The local N hides the enumeration constant.
+        let ExternalDecl::Function(f) = unit.decls.last().unwrap() else {
+            panic!("f is not a function");
+        };
+        let Stmt::Block(items, _) = &f.body else {
+            panic!("f has no body");
+        };
+        let BlockItem::Decl(v) = &items[1] else {
+            panic!("v is not a declaration");
+        };
+        assert!(matches!(
+            v.ty,
+            CType::Array {
+                size: ArraySize::Variable,
+                ..
+            }
+        ));
+
+        let errors = parse_source("int a[1 - 2];").unwrap_err();
+        assert_eq!(errors[0].message, "Array has a negative size");
+        let errors = parse_source("int a[(-1 < 0u) ? 1 : -1];").unwrap_err();
+        assert_eq!(errors[0].message, "Array has a negative size");
+        let errors = parse_source("int a[1 ? -1 : 1 / 0];").unwrap_err();
+        assert_eq!(errors[0].message, "Array has a negative size");
+    }
+
+    #[test]
+    fn function_definitions() {
+        let src = "int main(int argc, char **argv) {\n\
+                   int i, n = 0;\n\
+                   for (i = 0; i < argc; i++) { if (i) continue; else n += i; }\n\
+                   while (n) n--;\n\
+                   do { n++; } while (n < 3);\n\
+                   switch (n) { case 1: break; default: ; }\n\
+                   out: goto out;\n\
+                   return n;\n\
+                   }";
+        let unit = parse_source(src).unwrap();
+        let ExternalDecl::Function(main) = &unit.decls[0] else {
+            panic!()
+        };
+        assert_eq!(main.name, "main");
+        let names: Vec<Option<&str>> = main.params.iter().map(|p| p.name.as_deref()).collect();
+        assert_eq!(names, vec![Some("argc"), Some("argv")]);
+        assert_eq!(
+            main.params[1].ty,
+            pointer(pointer(CType::Char(Signedness::Signed)))
+        );
+        assert_eq!(
+            main.span,
+            Span {
+                start: 0,
+                end: src.len()
+            }
+        );
+
+        let Stmt::Block(items, _) = &main.body else {
+            panic!()
+        };
+        let kinds: Vec<&str> = items
+            .iter()
+            .map(|item| match item {
+                BlockItem::Decl(_) => "decl",
+                BlockItem::Stmt(Stmt::For { .. }) => "for",
+                BlockItem::Stmt(Stmt::While { .. }) => "while",
+                BlockItem::Stmt(Stmt::DoWhile { .. }) => "do",
+                BlockItem::Stmt(Stmt::Switch { .. }) => "switch",
+                BlockItem::Stmt(Stmt::Label(..)) => "label",
+                BlockItem::Stmt(Stmt::Return(..)) => "return",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            vec!["decl", "decl", "for", "while", "do", "switch", "label", "return"]
+        );
+    }
+
+    #[test]
+    fn tagged_types() {
+        let src = "struct point { int x, y; } p;\nunion u { int i; float f; };\nenum color { RED, GREEN = 2, BLUE } c;\nstruct point *q;";
+        let unit = parse_source(src).unwrap();
+
+        assert_eq!(decl(&unit, 0).ty, CType::Struct(StructId(0)));
+        assert_eq!(decl(&unit, 2).ty, pointer(CType::Struct(StructId(0))));
+        let point = unit.struct_def(StructId(0));
+        assert_eq!(point.tag.as_deref(), Some("point"));
+        let fields: Vec<&str> = point
+            .fields
+            .iter()
+            .flatten()
+            .map(|f| f.name.as_deref().unwrap())
+            .collect();
+        assert_eq!(fields, vec!["x", "y"]);
+        assert!(unit.struct_def(StructId(1)).is_union);
+
+        assert_eq!(decl(&unit, 1).ty, CType::Enum(EnumId(0)));
+        let color = unit.enum_def(EnumId(0));
+        let constants: Vec<(&str, Option<String>, i64)> = color
+            .constants
+            .iter()
+            .flatten()
+            .map(|c| (c.name.as_str(), c.init.as_ref().map(sexpr), c.value))
+            .collect();
+        assert_eq!(
+            constants,
+            vec![
+                ("RED", None, 0),
+                ("GREEN", Some(String::from("2")), 2),
+                ("BLUE", None, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            ("int x = ;", "Expected an expression before ';'", 8),
+            ("int x", "Expected ';' before end of input", 5),
+            ("int f() { return 1 }", "Expected ';' before '}'", 19),
+            ("x;", "Expected a type before 'x'", 0),
+            ("long short x;", "Invalid combination of type specifiers", 0),
+            (
+                "struct s; union s u;",
+                "'s' defined as wrong kind of tag",
+                16,
+            ),
+        ];
+        for (src, message, start) in cases {
+            let errors = parse_source(src).unwrap_err();
+            assert_eq!(errors.len(), 1, "{}", src);
+            assert_eq!(errors[0].message, message, "{}", src);
+            assert_eq!(errors[0].span.start, start, "{}", src);
+        }
+    }
+
+    #[test]
+    fn precedence_table() {
+        for (op, entry) in [
+            (OperatorType::OpAdd, 12),
+            (OperatorType::OpLogOr, 4),
+            (OperatorType::OpShl, 11),
+            (OperatorType::OpShrAssign, ASSIGNMENT),
+            (OperatorType::OpQuestion, CONDITIONAL),
+        ] {
+            assert_eq!(PRECEDENCE[op as usize].map(|(prec, _)| prec), Some(entry));
+        }
+        assert_eq!(PRECEDENCE[OperatorType::OpHashHash as usize], None);
+
+        let cases = [
+            ("int x = a = b = c;", "(Assign a (Assign b c))"),
+            ("int x = a ? b : c ? d : e;", "(? a b (? c d e))"),
+            ("int x = a ? b , c : d;", "(? a (Comma b c) d)"),
+            ("int x = a || b ? c : d;", "(? (LogOr a b) c d)"),
+            ("int x = a = b ? c : d;", "(Assign a (? b c d))"),
+            ("int x = a ? b : c = d;", "(Assign (? a b c) d)"),
+            ("int x = a * b + c * d;", "(Add (Mul a b) (Mul c d))"),
+            ("int x = a / b % c;", "(Mod (Div a b) c)"),
+            (
+                "int x = a & b ^ c | d;",
+                "(BitOr (BitXor (BitAnd a b) c) d)",
+            ),
+            ("int x = a *= b + c;", "(MulAssign a (Add b c))"),
+        ];
+        for (src, expected) in cases {
+            assert_eq!(init(src), expected, "{}", src);
+        }
+    }
+
+    #[test]
+    fn error_recovery() {
+        let src = "int a = ;\n\
+                   int f(void) {\n\
+                   int b = 1 +;\n\
+                   if (b) { b = (int) ; } else b--;\n\
+                   return b;\n\
+                   }\n\
+                   int c = 3 4;\n\
+                   int ok;\n";
+        let errors = parse_source(src).unwrap_err();
+        let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Expected an expression before ';'",
+                "Expected an expression before ';'",
+                "Expected an expression before ';'",
+                "Expected ';' before '4'",
+            ]
+        );
+        assert!(errors.iter().all(|error| error.recovered));
+
+        // Nothing is left to resume with after an error at the very end.
+        let errors = parse_source("int x = 1;\nint y").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(!errors[0].recovered);
+
+        // A stray brace or a broken function body is skipped whole.
+        let errors = parse_source("} int f() { ) } int g;").unwrap_err();
+        let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Expected a type before '}'",
+                "Expected an expression before ')'"
+            ]
+        );
+    }
+
+    #[test]
+    fn struct_members() {
+        let src = "struct list;\n\
+                   struct list { struct list *next; unsigned flags : 3, : 0, big : 5; };\n\
+                   struct outer { union { int i; float f; }; struct { int a; } named; } o;\n\
+                   enum e; enum e { A };";
+        let unit = parse_source(src).unwrap();
+
+        let list = unit.struct_def(StructId(0));
+        let fields = list.fields.as_ref().unwrap();
+        let shape: Vec<(Option<&str>, Option<String>)> = fields
+            .iter()
+            .map(|f| (f.name.as_deref(), f.bit_width.as_ref().map(sexpr)))
+            .collect();
+        assert_eq!(
+            shape,
+            vec![
+                (Some("next"), None),
+                (Some("flags"), Some(String::from("3"))),
+                (None, Some(String::from("0"))),
+                (Some("big"), Some(String::from("5"))),
+            ]
+        );
+        assert_eq!(fields[0].ty, pointer(CType::Struct(StructId(0))));
+
+        let outer = unit.struct_def(StructId(1));
+        let fields = outer.fields.as_ref().unwrap();
+        assert_eq!(fields[0].name, None);
+        assert_eq!(fields[0].ty, CType::Union(StructId(2)));
+        assert_eq!(fields[1].name.as_deref(), Some("named"));
+        assert!(unit.struct_def(StructId(2)).is_union);
+        assert!(unit.struct_def(StructId(2)).tag.is_none());
+
+        assert_eq!(unit.enums.len(), 1);
+        assert_eq!(
+            unit.enum_def(EnumId(0)).constants.as_ref().unwrap().len(),
+            1
+        );
+
+        // Forward declarations stay distinguishable from definitions.
+        let unit = parse_source("struct a; union b *p; struct c {};").unwrap();
+        let defined: Vec<bool> = unit.structs.iter().map(|s| s.fields.is_some()).collect();
+        assert_eq!(defined, vec![false, false, true]);
+
+        let cases = [
+            (
+                "struct s { int a; }; struct s { int b; };",
+                "Redefinition of 'struct s'",
+            ),
+            ("enum e { A }; enum e { B };", "Redefinition of 'enum e'"),
+            (
+                "struct s { int; };",
+                "Declaration does not declare anything",
+            ),
+            ("struct s { int *; };", "Member has no name"),
+        ];
+        for (src, message) in cases {
+            let errors = parse_source(src).unwrap_err();
+            assert_eq!(errors[0].message, message, "{}", src);
+        }
+    }
+
+    #[test]
+    fn scoped_names() {
+        // An inner declaration hides the typedef, so `T * n` multiplies,
+        // until the end of its scope.
+        let src = "typedef int T;\n\
+                   int f(int n) { int T = 2; T * n; { T * n; } return T; }\n\
+                   T g(T x) { for (T T = 0;;) { T * x; } T * y; return x; }";
+        let unit = parse_source(src).unwrap();
+        let ExternalDecl::Function(f) = &unit.decls[1] else {
+            panic!("f is not a function");
+        };
+        let Stmt::Block(items, _) = &f.body else {
+            panic!("f has no body");
+        };
+        let BlockItem::Stmt(Stmt::Expr(expr, _)) = &items[1] else {
+            panic!("T * n is not an expression");
+        };
+        assert_eq!(sexpr(expr), "(Mul T n)");
+        let BlockItem::Stmt(Stmt::Block(inner, _)) = &items[2] else {
+            panic!("no inner block");
+        };
+        assert!(matches!(inner[0], BlockItem::Stmt(Stmt::Expr(..))));
+
+        let ExternalDecl::Function(g) = &unit.decls[2] else {
+            panic!("g is not a function");
+        };
+        let Stmt::Block(items, _) = &g.body else {
+            panic!("g has no body");
+        };
+        let BlockItem::Decl(y) = &items[1] else {
+            panic!("T * y is not a declaration");
+        };
+        assert_eq!(
+            y.ty,
+            pointer(CType::Typedef(String::from("T"), Box::new(INT)))
+        );
+
+        let ok = [
+            "int x; int x = 1; extern int x;",
+            "int f(void); int f(void) { return 0; }",
+            "void f(void) { int x; { int x; } }",
+            "typedef int T; typedef int T; T x;",
+        ];
+        for src in ok {
+            assert!(parse_source(src).is_ok(), "{}", src);
+        }
+
+        let cases = [
+            "int x = 1; int x = 2;",
+            "void f(void) { int x; int x; }",
+            "typedef int T; int T;",
+            "typedef int T; typedef long T;",
+            "int f(int n) { int n; return n; }",
+            "int f(void) { return 0; } int f(void) { return 1; }",
+            "enum { A }; int A = 1;",
+        ];
+        for src in cases {
+            let errors = parse_source(src).unwrap_err();
+            assert!(errors[0].message.starts_with("Redefinition of"), "{}", src);
+            assert!(errors[0].recovered, "{}", src);
+        }
+    }
+}
@@ -0,0 +1,1543 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::ast::TranslationUnit;
+use crate::lex::{
+    DirectiveKind, IntoTokenStream, Lex, LexError, Location, OperatorType, Token, TokenStream,
+    TokenType,
+};
+use crate::parser::Parser;
+
+// The value of __STDC_VERSION__ when no -std= is given, as for GCC's gnu17.
+const DEFAULT_STDC_VERSION: &str = "201710L";
+
+// Includes nested deeper than this are taken to be recursive.
+const MAX_INCLUDE_DEPTH: usize = 200;
+
+// One level of #if/#ifdef/#ifndef nesting.
+struct Conditional {
+    // Whether tokens in the current branch are kept.
+    active: bool,
+    // Whether the region around the conditional is kept; if not, no branch is.
+    parent_active: bool,
+    // Whether an earlier branch, or the current one, was kept.
+    taken: bool,
+    in_else: bool,
+    location: Location,
+}
+
+// A `#define`d macro. `params` is `None` for object-like macros; a variadic
+// macro takes its extra arguments as `__VA_ARGS__`.
+#[derive(Clone)]
+struct MacroDef {
+    params: Option<Vec<String>>,
+    variadic: bool,
+    body: Vec<Token>,
+}
+
+impl MacroDef {
+    // Which argument `token` stands for in the body, if any.
+    fn param(&self, token: &Token) -> Option<usize> {
+        let params = self.params.as_ref()?;
+        if !is_name(token) {
+            return None;
+        }
+        match params.iter().position(|param| param == token.source()) {
+            Some(index) => Some(index),
+            None if self.variadic && token.source() == "__VA_ARGS__" => Some(params.len()),
+            None => None,
+        }
+    }
+}
+
+fn is_space(token: &Token) -> bool {
+    matches!(
+        token.token_type(),
+        TokenType::Space | TokenType::Note | TokenType::NewLine
+    )
+}
+
+fn is_name(token: &Token) -> bool {
+    matches!(
+        token.token_type(),
+        TokenType::Identifier | TokenType::KeyWord(_)
+    )
+}
+
+// Parses the parameters after the '(' of a function-like macro. Returns the
+// names, whether the last one is "...", and how many tokens up to and
+// including the ')' were used.
+fn parameters(tokens: &[Token]) -> Option<(Vec<String>, bool, usize)> {
+    let mut names = Vec::new();
+    let mut variadic = false;
+    let mut expect_name = true;
+
+    for (index, token) in tokens.iter().enumerate() {
+        if is_space(token) {
+            continue;
+        }
+        match token.source() {
+            ")" if !expect_name || (names.is_empty() && !variadic) => {
+                return Some((names, variadic, index + 1))
+            }
+            "," if !expect_name && !variadic => expect_name = true,
+            "..." if expect_name => {
+                variadic = true;
+                expect_name = false;
+            }
+            name if expect_name && is_name(token) && !names.iter().any(|n| n == name) => {
+                names.push(name.to_string());
+                expect_name = false;
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+// Lexes `text` as a single token placed where `at` is, or `None` if it is
+// not exactly one valid token. A leading '#' does not start a directive.
+fn relex(at: &Token, text: &str) -> Option<Token> {
+    let mut lex = Lex::from_source(at.loc().file(), text);
+    lex.set_directives(false);
+    if lex.parse().is_err() {
+        return None;
+    }
+    let mut tokens = lex
+        .into_token_stream()
+        .filter(|token| *token.token_type() != TokenType::Eof);
+    match (tokens.next(), tokens.next()) {
+        (Some(token), None) => Some(token.with_loc(at.loc().clone())),
+        _ => None,
+    }
+}
+
+// Up to this many blank lines are written out as newlines in `render`; a
+// longer gap gets a line marker instead.
+const MAX_BLANK_LINES: usize = 8;
+
+/// Writes the preprocessed tokens of `file` back out as source text, in the
+/// format of `cc -E`: comments become a space, and line markers such as
+/// `# 12 "main.c" 2` keep track of the file and line when an `#include` or
+/// a long run of removed lines moves them. The flag 1 marks entering an
+/// included file and 2 returning to the includer.
+pub fn render(file: &str, tokens: impl IntoIterator<Item = Token>) -> String {
+    let mut text = format!("# 1 \"{}\"\n", file);
+    // Files being included, innermost last.
+    let mut files = vec![file.to_string()];
+    // The line being written, and newlines not written yet.
+    let mut line = 1;
+    let mut pending = 0;
+
+    for token in tokens {
+        match token.token_type() {
+            TokenType::Eof => continue,
+            TokenType::NewLine => {
+                line += 1;
+                pending += 1;
+                continue;
+            }
+            _ => (),
+        }
+
+        let loc = token.loc();
+        if files.last().map(|file| file.as_str()) != Some(loc.file()) {
+            let flag = if files.len() >= 2 && files[files.len() - 2] == loc.file() {
+                files.pop();
+                2
+            } else {
+                files.push(loc.file().to_string());
+                1
+            };
+            if !text.ends_with('\n') {
+                text.push('\n');
+            }
+            text += &format!("# {} \"{}\" {}\n", loc.line(), loc.file(), flag);
+            line = loc.line();
+            pending = 0;
+        } else if pending > 0 {
+            // A comment spanning lines may have put the token further down.
+            let target = line.max(loc.line());
+            let newlines = pending + target - line;
+            if newlines > MAX_BLANK_LINES {
+                if !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                text += &format!("# {} \"{}\"\n", target, loc.file());
+            } else {
+                text += &"\n".repeat(newlines);
+            }
+            line = target;
+            pending = 0;
+        }
+
+        if *token.token_type() == TokenType::Note {
+            text.push(' ');
+        } else {
+            text += token.source();
+        }
+    }
+    if pending > 0 {
+        text.push('\n');
+    }
+    text
+}
+
+// __DATE__ and __TIME__ for `secs` seconds after the epoch, in UTC, e.g.
+// "Oct  7 2026" and "13:05:09".
+fn date_time(secs: u64) -> (String, String) {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    // Days to a civil date, after Howard Hinnant's days_from_civil inverse.
+    let days = secs / 86400 + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    let time = secs % 86400;
+    (
+        format!("{} {:2} {}", MONTHS[month as usize - 1], day, year),
+        format!("{:02}:{:02}:{:02}", time / 3600, time / 60 % 60, time % 60),
+    )
+}
+
+/// Applies preprocessing directives to a lexed token stream: `#include`,
+/// `#define` and `#undef`, and `#if`/`#ifdef`/`#ifndef`/`#elif`/`#else`/`#endif`.
+pub struct Preprocessor {
+    include_paths: Vec<PathBuf>,
+    macros: HashMap<String, MacroDef>,
+    errors: Vec<LexError>,
+    warnings: Vec<LexError>,
+    depth: usize,
+}
+
+impl Preprocessor {
+    /// `include_paths` are searched in order for `#include <...>`, and for
+    /// `#include "..."` after the directory of the including file.
+    ///
+    /// `__STDC__`, `__STDC_VERSION__`, `__DATE__` and `__TIME__` are
+    /// predefined; `__FILE__` and `__LINE__` are expanded where they are used.
+    pub fn new(include_paths: &[String]) -> Self {
+        let mut preprocessor = Preprocessor {
+            include_paths: include_paths.iter().map(PathBuf::from).collect(),
+            macros: HashMap::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            depth: 0,
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let (date, time) = date_time(now);
+        for (name, value) in [
+            ("__STDC__", "1"),
+            ("__STDC_VERSION__", DEFAULT_STDC_VERSION),
+            ("__DATE__", &format!("\"{}\"", date)),
+            ("__TIME__", &format!("\"{}\"", time)),
+        ] {
+            // These are all well formed, so this cannot fail.
+            let _ = preprocessor.define(name, value);
+        }
+        preprocessor
+    }
+
+    /// Defines `name` as an object-like macro expanding to `value`, as
+    /// `#define name value` would, replacing any earlier definition.
+    pub fn define(&mut self, name: &str, value: &str) -> Result<(), Vec<LexError>> {
+        let mut lex = Lex::from_source("<command line>", &format!("#define {} {}\n", name, value));
+        lex.parse()?;
+        self.process_tokens(lex.into_token_stream(), &mut Vec::new());
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    /// Applies the value of a `-D` option: `NAME=VALUE` defines `NAME` as
+    /// `VALUE`, and a bare `NAME` defines it as 1.
+    pub fn define_arg(&mut self, arg: &str) -> Result<(), Vec<LexError>> {
+        let (name, value) = arg.split_once('=').unwrap_or((arg, "1"));
+        self.define(name, value)
+    }
+
+    /// Sets `__STDC_VERSION__` for a `-std=` value such as "c11" or
+    /// "gnu99". C90 has no `__STDC_VERSION__`; unknown names are ignored.
+    pub fn set_std(&mut self, std: &str) {
+        let version = match std {
+            "c89" | "c90" | "gnu89" | "gnu90" | "ansi" | "iso9899:1990" => {
+                self.macros.remove("__STDC_VERSION__");
+                return;
+            }
+            "c99" | "c9x" | "gnu99" | "gnu9x" | "iso9899:1999" => "199901L",
+            "c11" | "c1x" | "gnu11" | "gnu1x" | "iso9899:2011" => "201112L",
+            "c17" | "c18" | "gnu17" | "gnu18" | "iso9899:2017" | "iso9899:2018" => "201710L",
+            "c23" | "c2x" | "gnu23" | "gnu2x" => "202311L",
+            _ => return,
+        };
+        let _ = self.define("__STDC_VERSION__", version);
+    }
+
+    /// Returns the tokens with directives carried out and macros expanded.
+    /// Directive lines are dropped but their newlines are kept, so the output
+    /// has as many lines as the input outside of included files.
+    pub fn process(&mut self, tokens: IntoTokenStream) -> Result<IntoTokenStream, Vec<LexError>> {
+        let mut output = Vec::new();
+        self.process_tokens(tokens, &mut output);
+
+        if self.errors.is_empty() {
+            Ok(IntoTokenStream::from(output))
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    /// The `#warning` messages met so far.
+    pub fn warnings(&self) -> &[LexError] {
+        &self.warnings
+    }
+
+    fn error(&mut self, token: &Token, message: String) {
+        self.errors.push(LexError {
+            message,
+            location: token.loc().clone(),
+        });
+    }
+
+    fn process_tokens(&mut self, mut tokens: IntoTokenStream, output: &mut Vec<Token>) {
+        let mut conditionals = Vec::<Conditional>::new();
+
+        while let Some(token) = tokens.next() {
+            let active = conditionals
+                .last()
+                .is_none_or(|conditional| conditional.active);
+            match token.token_type() {
+                TokenType::Directive(_) => {
+                    let mut line = Vec::new();
+                    while let Some(next) = tokens.peek() {
+                        if matches!(next.token_type(), TokenType::NewLine | TokenType::Eof) {
+                            break;
+                        }
+                        line.extend(tokens.next());
+                    }
+                    self.directive(&token, &line, &mut conditionals, output);
+                }
+                TokenType::NewLine | TokenType::Eof => output.push(token),
+                _ if !active => (),
+                TokenType::Identifier | TokenType::KeyWord(_) => {
+                    let mut invocation = vec![token];
+                    if self.function_like(invocation[0].source()) {
+                        Self::take_arguments(&mut tokens, &mut invocation, 0);
+                    }
+                    let errors = self.errors.len();
+                    let (mut expanded, mut waiting) = self.rescan(&invocation, &HashSet::new());
+                    // With "#define G F", "G(1)" leaves F to take its
+                    // arguments from the text after G, and with "#define H
+                    // F(~", "H 5)" leaves it to finish them there; expand
+                    // again with that text.
+                    while let Some(depth) = waiting {
+                        if !Self::take_arguments(&mut tokens, &mut invocation, depth) {
+                            break;
+                        }
+                        self.errors.truncate(errors);
+                        (expanded, waiting) = self.rescan(&invocation, &HashSet::new());
+                    }
+                    // Keep the line count right when the arguments span lines.
+                    let newlines: Vec<Token> = invocation
+                        .iter()
+                        .filter(|token| *token.token_type() == TokenType::NewLine)
+                        .cloned()
+                        .collect();
+                    output.extend(expanded);
+                    output.extend(newlines);
+                }
+                _ => output.push(token),
+            }
+        }
+
+        for conditional in conditionals {
+            self.errors.push(LexError {
+                message: String::from("Unterminated conditional directive"),
+                location: conditional.location,
+            });
+        }
+    }
+
+    fn directive(
+        &mut self,
+        directive: &Token,
+        line: &[Token],
+        conditionals: &mut Vec<Conditional>,
+        output: &mut Vec<Token>,
+    ) {
+        let kind = match directive.token_type() {
+            TokenType::Directive(kind) => *kind,
+            _ => return,
+        };
+        let source = directive.source();
+        let name = source
+            .strip_prefix('#')
+            .or_else(|| source.strip_prefix("%:"))
+            .unwrap_or(source)
+            .trim_start();
+        let active = conditionals
+            .last()
+            .is_none_or(|conditional| conditional.active);
+        let args: Vec<&Token> = line
+            .iter()
+            .filter(|token| !matches!(token.token_type(), TokenType::Space | TokenType::Note))
+            .collect();
+
+        match kind {
+            DirectiveKind::Ifdef | DirectiveKind::Ifndef => {
+                let defined = match args.first() {
+                    Some(arg) => self.macros.contains_key(arg.source()),
+                    None => {
+                        if active {
+                            self.error(directive, format!("#{} with no macro name", name));
+                        }
+                        false
+                    }
+                };
+                let taken = active && defined == (kind == DirectiveKind::Ifdef);
+                conditionals.push(Conditional {
+                    active: taken,
+                    parent_active: active,
+                    taken,
+                    in_else: false,
+                    location: directive.loc().clone(),
+                });
+            }
+            DirectiveKind::If => {
+                // The expression is only evaluated where it could matter.
+                let taken = active && self.condition(directive, name, line);
+                conditionals.push(Conditional {
+                    active: taken,
+                    parent_active: active,
+                    taken,
+                    in_else: false,
+                    location: directive.loc().clone(),
+                });
+            }
+            DirectiveKind::Elif => match conditionals.last() {
+                Some(conditional) if !conditional.in_else => {
+                    let taken = conditional.parent_active
+                        && !conditional.taken
+                        && self.condition(directive, name, line);
+                    let conditional = conditionals.last_mut().unwrap();
+                    conditional.active = taken;
+                    conditional.taken |= taken;
+                }
+                Some(_) => self.error(directive, String::from("#elif after #else")),
+                None => self.error(directive, String::from("#elif without #if")),
+            },
+            DirectiveKind::Else => match conditionals.last_mut() {
+                Some(conditional) if !conditional.in_else => {
+                    conditional.in_else = true;
+                    conditional.active = conditional.parent_active && !conditional.taken;
+                    conditional.taken = true;
+                }
+                Some(_) => self.error(directive, String::from("#else after #else")),
+                None => self.error(directive, String::from("#else without #if")),
+            },
+            DirectiveKind::Endif => {
+                if conditionals.pop().is_none() {
+                    self.error(directive, String::from("#endif without #if"));
+                }
+            }
+            _ if !active => (),
+            DirectiveKind::Unknown if name.is_empty() => (),
+            DirectiveKind::Pragma => (),
+            DirectiveKind::Define => self.define_macro(directive, line),
+            DirectiveKind::Undef => match args.first() {
+                Some(arg) => {
+                    self.macros.remove(arg.source());
+                }
+                None => self.error(directive, String::from("#undef with no macro name")),
+            },
+            DirectiveKind::Include => self.include(directive, &args, output),
+            DirectiveKind::Error | DirectiveKind::Warning => {
+                let text: String = line.iter().map(|token| token.source()).collect();
+                let message = format!("#{} {}", name, text.trim());
+                if kind == DirectiveKind::Error {
+                    self.error(directive, message);
+                } else {
+                    self.warnings.push(LexError {
+                        message,
+                        location: directive.loc().clone(),
+                    });
+                }
+            }
+            // Left for the compiler proper, as `cc -E` does.
+            DirectiveKind::Line => {
+                output.push(directive.clone());
+                output.extend(line.iter().cloned());
+            }
+            _ => self.error(
+                directive,
+                format!("Unsupported preprocessing directive '#{}'", name),
+            ),
+        }
+    }
+
+    // Evaluates the expression of an #if or #elif: `defined` is applied,
+    // macros are expanded, and any name left after that counts as 0.
+    fn condition(&mut self, directive: &Token, name: &str, line: &[Token]) -> bool {
+        let significant: Vec<&Token> = line.iter().filter(|token| !is_space(token)).collect();
+        let mut tokens = Vec::new();
+        let mut index = 0;
+        while index < significant.len() {
+            let token = significant[index];
+            index += 1;
+            if !is_name(token) || token.source() != "defined" {
+                tokens.push(token.clone());
+                continue;
+            }
+            let parens = significant.get(index).map(|token| token.source()) == Some("(");
+            let operand = significant.get(index + parens as usize);
+            let close = significant.get(index + 1 + parens as usize);
+            let defined = match operand {
+                Some(operand)
+                    if is_name(operand) && (!parens || close.map(|t| t.source()) == Some(")")) =>
+                {
+                    self.macros.contains_key(operand.source())
+                }
+                _ => {
+                    self.error(
+                        token,
+                        String::from("Operator 'defined' requires an identifier"),
+                    );
+                    return false;
+                }
+            };
+            index += 1 + 2 * parens as usize;
+            tokens.extend(relex(token, if defined { "1" } else { "0" }));
+        }
+
+        let tokens: Vec<Token> = self
+            .expand_list(&tokens, &HashSet::new())
+            .into_iter()
+            .filter(|token| !is_space(token))
+            .map(|token| match is_name(&token) {
+                true => relex(&token, "0").unwrap_or(token),
+                false => token,
+            })
+            .collect();
+        if tokens.is_empty() {
+            self.error(directive, format!("#{} with no expression", name));
+            return false;
+        }
+        let value = Parser::new(TokenStream::from(&tokens[..]))
+            .parse_constant_expr()
+            .ok()
+            .and_then(|expr| TranslationUnit::default().const_eval(&expr));
+        match value {
+            Some(value) => value != 0,
+            None => {
+                self.error(
+                    directive,
+                    format!("#{} expects an integer constant expression", name),
+                );
+                false
+            }
+        }
+    }
+
+    fn define_macro(&mut self, directive: &Token, line: &[Token]) {
+        let mut index = line
+            .iter()
+            .position(|token| !is_space(token))
+            .unwrap_or(line.len());
+        let name = match line.get(index) {
+            Some(name) if is_name(name) => name,
+            _ => {
+                self.error(directive, String::from("Macro name must be an identifier"));
+                return;
+            }
+        };
+        if matches!(name.source(), "__FILE__" | "__LINE__") {
+            self.error(name, format!("'{}' cannot be redefined", name.source()));
+            return;
+        }
+        index += 1;
+
+        // Only a '(' right after the name, with no space, makes a function-like macro.
+        let mut params = None;
+        let mut variadic = false;
+        if line.get(index).map(|token| token.source()) == Some("(") {
+            match parameters(&line[index + 1..]) {
+                Some((names, is_variadic, used)) => {
+                    params = Some(names);
+                    variadic = is_variadic;
+                    index += 1 + used;
+                }
+                None => {
+                    self.error(
+                        name,
+                        format!("Invalid parameter list for macro '{}'", name.source()),
+                    );
+                    return;
+                }
+            }
+        }
+
+        let mut body: Vec<Token> = line[index..]
+            .iter()
+            .filter(|token| *token.token_type() != TokenType::Note)
+            .cloned()
+            .collect();
+        while body.first().is_some_and(is_space) {
+            body.remove(0);
+        }
+        while body.last().is_some_and(is_space) {
+            body.pop();
+        }
+
+        let paste = TokenType::Operator(OperatorType::OpHashHash);
+        if body.first().map(|token| token.token_type()) == Some(&paste)
+            || body.last().map(|token| token.token_type()) == Some(&paste)
+        {
+            self.error(
+                name,
+                String::from("'##' cannot appear at either end of a macro expansion"),
+            );
+            return;
+        }
+        let def = MacroDef {
+            params,
+            variadic,
+            body,
+        };
+        if def.params.is_some() {
+            for (index, token) in def.body.iter().enumerate() {
+                if *token.token_type() != TokenType::Operator(OperatorType::OpHash) {
+                    continue;
+                }
+                let next = def.body[index + 1..].iter().find(|token| !is_space(token));
+                if next.and_then(|next| def.param(next)).is_none() {
+                    self.error(
+                        token,
+                        String::from("'#' is not followed by a macro parameter"),
+                    );
+                    return;
+                }
+            }
+        }
+
+        self.macros.insert(name.source().to_string(), def);
+    }
+
+    fn function_like(&self, name: &str) -> bool {
+        self.macros
+            .get(name)
+            .is_some_and(|def| def.params.is_some())
+    }
+
+    // Moves a parenthesized argument list, if the next token other than
+    // whitespace is '(', from the stream to the end of `invocation`, and
+    // says whether there was one. With a `depth` above 0, `invocation` ends
+    // inside that many parentheses, and the rest of the list is moved.
+    fn take_arguments(
+        tokens: &mut IntoTokenStream,
+        invocation: &mut Vec<Token>,
+        mut depth: usize,
+    ) -> bool {
+        if depth == 0 {
+            let mut n = 0;
+            while tokens.peek_nth(n).is_some_and(is_space) {
+                n += 1;
+            }
+            if tokens.peek_nth(n).map(|token| token.source()) != Some("(") {
+                return false;
+            }
+        }
+
+        let mut taken = false;
+        while let Some(next) = tokens.peek() {
+            if matches!(next.token_type(), TokenType::Eof | TokenType::Directive(_)) {
+                break;
+            }
+            let next = tokens.next().unwrap();
+            match next.source() {
+                "(" => depth += 1,
+                ")" => depth -= 1,
+                _ => (),
+            }
+            let closed = depth == 0 && next.source() == ")";
+            invocation.push(next);
+            taken = true;
+            if closed {
+                break;
+            }
+        }
+        taken
+    }
+
+    // Expands every macro in `tokens`. Names in `expanding` are not expanded
+    // again, which is what stops "#define A B" and "#define B A" looping forever.
+    fn expand_list(&mut self, tokens: &[Token], expanding: &HashSet<String>) -> Vec<Token> {
+        self.rescan(tokens, expanding).0
+    }
+
+    // Does the work of expand_list. An expansion is put back in front of the
+    // tokens after it and scanned again together with them, so a function-like
+    // macro name at its end can take its arguments from what follows. Each
+    // token carries the names it must not expand: `expanding` and the macros
+    // it came out of. Also says whether `tokens` end in a function-like macro
+    // invocation still waiting for its arguments, and how many parentheses of
+    // its argument list are open if so.
+    fn rescan(
+        &mut self,
+        tokens: &[Token],
+        expanding: &HashSet<String>,
+    ) -> (Vec<Token>, Option<usize>) {
+        let expanding = Rc::new(expanding.clone());
+        // The tokens still to scan, next one last.
+        let mut pending: Vec<(Token, Rc<HashSet<String>>)> = tokens
+            .iter()
+            .rev()
+            .map(|token| (token.clone(), Rc::clone(&expanding)))
+            .collect();
+        let mut output = Vec::new();
+        let mut waiting = None;
+
+        while let Some((token, hidden)) = pending.pop() {
+            if is_name(&token) && matches!(token.source(), "__FILE__" | "__LINE__") {
+                let text = if token.source() == "__FILE__" {
+                    let file = token
+                        .loc()
+                        .file()
+                        .replace('\\', "\\\\")
+                        .replace('"', "\\\"");
+                    format!("\"{}\"", file)
+                } else {
+                    token.loc().line().to_string()
+                };
+                output.extend(relex(&token, &text));
+                continue;
+            }
+            let def = match self.macros.get(token.source()) {
+                Some(def) if is_name(&token) && !hidden.contains(token.source()) => def.clone(),
+                _ => {
+                    output.push(token);
+                    continue;
+                }
+            };
+
+            let mut args = Vec::new();
+            if def.params.is_some() {
+                let rest: Vec<Token> = pending
+                    .iter()
+                    .rev()
+                    .map(|(token, _)| token.clone())
+                    .collect();
+                let open = rest.iter().take_while(|token| is_space(token)).count();
+                if rest.get(open).map(|token| token.source()) != Some("(") {
+                    if open == rest.len() {
+                        waiting = Some(0);
+                    }
+                    output.push(token);
+                    continue;
+                }
+                let mut depth = 0;
+                for next in &rest[open..] {
+                    match next.source() {
+                        "(" => depth += 1,
+                        ")" => depth -= 1,
+                        _ => (),
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                if depth > 0 {
+                    waiting = Some(depth);
+                }
+                match self.arguments(&token, &def, &rest[open..]) {
+                    Some((list, used)) => {
+                        args = list;
+                        pending.truncate(rest.len() - open - used);
+                    }
+                    None => {
+                        output.push(token);
+                        output.extend(rest);
+                        break;
+                    }
+                }
+            }
+
+            // The expansion is placed where the macro was used.
+            let mut inner = (*hidden).clone();
+            inner.insert(token.source().to_string());
+            let inner = Rc::new(inner);
+            let body = self.substitute(&def, &args, &hidden);
+            pending.extend(
+                body.into_iter().rev().map(|body_token| {
+                    (body_token.with_loc(token.loc().clone()), Rc::clone(&inner))
+                }),
+            );
+        }
+        (output, waiting)
+    }
+
+    // Splits the argument list starting at the '(' in `tokens[0]`. Returns
+    // the arguments, with one more for `__VA_ARGS__` if the macro is
+    // variadic, and how many tokens the list took up.
+    fn arguments(
+        &mut self,
+        name: &Token,
+        def: &MacroDef,
+        tokens: &[Token],
+    ) -> Option<(Vec<Vec<Token>>, usize)> {
+        let params = def.params.as_deref().unwrap_or(&[]);
+        let split_limit = if def.variadic {
+            params.len() + 1
+        } else {
+            usize::MAX
+        };
+        let mut args = vec![Vec::new()];
+        let mut depth = 0;
+
+        for (index, token) in tokens.iter().enumerate() {
+            match token.source() {
+                "(" => {
+                    depth += 1;
+                    if depth == 1 {
+                        continue;
+                    }
+                }
+                ")" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return self.check_arguments(name, def, args, index + 1);
+                    }
+                }
+                "," if depth == 1 && args.len() < split_limit => {
+                    args.push(Vec::new());
+                    continue;
+                }
+                _ => (),
+            }
+
+            let arg = args.last_mut().unwrap();
+            if *token.token_type() == TokenType::NewLine {
+                arg.push(Token::new(token.loc().clone(), TokenType::Space, " "));
+            } else {
+                arg.push(token.clone());
+            }
+        }
+
+        self.error(
+            name,
+            format!(
+                "Unterminated argument list invoking macro '{}'",
+                name.source()
+            ),
+        );
+        None
+    }
+
+    fn check_arguments(
+        &mut self,
+        name: &Token,
+        def: &MacroDef,
+        mut args: Vec<Vec<Token>>,
+        used: usize,
+    ) -> Option<(Vec<Vec<Token>>, usize)> {
+        for arg in &mut args {
+            while arg.first().is_some_and(is_space) {
+                arg.remove(0);
+            }
+            while arg.last().is_some_and(is_space) {
+                arg.pop();
+            }
+        }
+
+        let params = def.params.as_deref().unwrap_or(&[]);
+        if params.is_empty() && args.len() == 1 && args[0].is_empty() {
+            args.clear();
+        }
+        if args.len() < params.len() || (!def.variadic && args.len() > params.len()) {
+            self.error(
+                name,
+                format!(
+                    "Macro '{}' takes {} argument(s), but {} given",
+                    name.source(),
+                    params.len(),
+                    args.len()
+                ),
+            );
+            return None;
+        }
+        if def.variadic && args.len() == params.len() {
+            args.push(Vec::new());
+        }
+        Some((args, used))
+    }
+
+    // Replaces the parameters in the body of `def` and applies '#' and '##'.
+    // Arguments are macro-expanded first, except next to '#' or '##'.
+    fn substitute(
+        &mut self,
+        def: &MacroDef,
+        args: &[Vec<Token>],
+        expanding: &HashSet<String>,
+    ) -> Vec<Token> {
+        let body = &def.body;
+        let next_significant = |index: usize| {
+            body[index..]
+                .iter()
+                .position(|token| !is_space(token))
+                .map(|offset| index + offset)
+        };
+        let mut result: Vec<Token> = Vec::new();
+        // Set when the last thing substituted was an empty argument, which
+        // '##' must then paste as nothing rather than the token before it.
+        let mut placemarker = false;
+        let mut index = 0;
+
+        while index < body.len() {
+            let token = &body[index];
+            index += 1;
+
+            match token.token_type() {
+                TokenType::Operator(OperatorType::OpHash) if def.params.is_some() => {
+                    // define() made sure a parameter follows.
+                    let param_index = next_significant(index).unwrap();
+                    let arg = &args[def.param(&body[param_index]).unwrap()];
+                    result.extend(self.stringify(token, arg));
+                    index = param_index + 1;
+                    placemarker = false;
+                }
+                TokenType::Operator(OperatorType::OpHashHash) => {
+                    while result.last().is_some_and(is_space) {
+                        result.pop();
+                    }
+                    let left = if placemarker { None } else { result.pop() };
+                    // define() made sure something follows.
+                    let right_index = next_significant(index).unwrap();
+                    let mut right = match def.param(&body[right_index]) {
+                        Some(param) => args[param].clone(),
+                        None => vec![body[right_index].clone()],
+                    };
+                    index = right_index + 1;
+
+                    placemarker = left.is_none() && right.is_empty();
+                    match left {
+                        Some(left) if right.is_empty() => result.push(left),
+                        Some(left) => {
+                            let first = right.remove(0);
+                            match self.paste(&left, &first) {
+                                Some(pasted) => result.push(pasted),
+                                None => result.extend([left, first]),
+                            }
+                            result.extend(right);
+                        }
+                        None => result.extend(right),
+                    }
+                }
+                _ => match def.param(token) {
+                    Some(param) => {
+                        let pasted = next_significant(index).is_some_and(|next| {
+                            *body[next].token_type()
+                                == TokenType::Operator(OperatorType::OpHashHash)
+                        });
+                        if pasted {
+                            result.extend(args[param].iter().cloned());
+                        } else {
+                            let expanded = self.expand_list(&args[param], expanding);
+                            result.extend(expanded);
+                        }
+                        placemarker = args[param].is_empty();
+                    }
+                    None => {
+                        if !is_space(token) {
+                            placemarker = false;
+                        }
+                        result.push(token.clone());
+                    }
+                },
+            }
+        }
+        result
+    }
+
+    // The string literal '#' makes of `arg`: whitespace between tokens
+    // becomes one space, and '"' and '\' in literals are escaped.
+    fn stringify(&mut self, hash: &Token, arg: &[Token]) -> Option<Token> {
+        let mut text = String::from("\"");
+        let mut space = false;
+        for token in arg {
+            if is_space(token) {
+                space = true;
+                continue;
+            }
+            if space && text.len() > 1 {
+                text.push(' ');
+            }
+            space = false;
+            match token.token_type() {
+                TokenType::Str(_) | TokenType::Char(_) => {
+                    for chr in token.source().chars() {
+                        if chr == '"' || chr == '\\' {
+                            text.push('\\');
+                        }
+                        text.push(chr);
+                    }
+                }
+                _ => text += token.source(),
+            }
+        }
+        text.push('"');
+
+        let token = relex(hash, &text);
+        if token.is_none() {
+            self.error(
+                hash,
+                format!("'#' does not give a valid string literal: {}", text),
+            );
+        }
+        token
+    }
+
+    fn paste(&mut self, left: &Token, right: &Token) -> Option<Token> {
+        let token = relex(left, &format!("{}{}", left.source(), right.source()));
+        if token.is_none() {
+            self.error(
+                left,
+                format!(
+                    "Pasting \"{}\" and \"{}\" does not give a valid preprocessing token",
+                    left.source(),
+                    right.source()
+                ),
+            );
+        }
+        token
+    }
+
+    fn include(&mut self, directive: &Token, args: &[&Token], output: &mut Vec<Token>) {
+        let header = match args.first() {
+            Some(arg) if *arg.token_type() == TokenType::HeaderName => arg.source(),
+            _ => {
+                self.error(
+                    directive,
+                    String::from("#include expects \"FILENAME\" or <FILENAME>"),
+                );
+                return;
+            }
+        };
+        let quoted = header.starts_with('"');
+        let name = header[1..header.len() - 1].to_string();
+        if self.depth >= MAX_INCLUDE_DEPTH {
+            self.error(directive, String::from("#include nested too deeply"));
+            return;
+        }
+
+        let mut candidates = Vec::new();
+        if quoted {
+            let dir = Path::new(directive.loc().file())
+                .parent()
+                .unwrap_or(Path::new(""));
+            candidates.push(dir.join(&name));
+        }
+        candidates.extend(self.include_paths.iter().map(|dir| dir.join(&name)));
+        let path = match candidates.into_iter().find(|path| path.is_file()) {
+            Some(path) => path,
+            None => {
+                self.error(directive, format!("'{}' file not found", name));
+                return;
+            }
+        };
+        let src = match fs::read_to_string(&path) {
+            Ok(src) => src,
+            Err(why) => {
+                self.error(
+                    directive,
+                    format!("couldn't read {}: {}", path.display(), why),
+                );
+                return;
+            }
+        };
+
+        let mut lex = Lex::from_source(&path.to_string_lossy(), &src);
+        if let Err(errors) = lex.parse() {
+            self.errors.extend(errors);
+            return;
+        }
+
+        let mut included = Vec::new();
+        self.depth += 1;
+        self.process_tokens(lex.into_token_stream(), &mut included);
+        self.depth -= 1;
+        output.extend(
+            included
+                .into_iter()
+                .filter(|token| *token.token_type() != TokenType::Eof),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preprocess_file(
+        file: &str,
+        src: &str,
+        include_paths: &[String],
+    ) -> Result<String, Vec<String>> {
+        let mut lex = Lex::from_source(file, src);
+        lex.parse().unwrap();
+        let tokens = Preprocessor::new(include_paths)
+            .process(lex.into_token_stream())
+            .map_err(|errors| {
+                errors
+                    .iter()
+                    .map(|error| error.message.clone())
+                    .collect::<Vec<String>>()
+            })?;
+
+        Ok(tokens
+            .filter(|token| {
+                !matches!(
+                    token.token_type(),
+                    TokenType::Space | TokenType::NewLine | TokenType::Note | TokenType::Eof
+                )
+            })
+            .map(|token| token.source().to_string())
+            .collect::<Vec<String>>()
+            .join(" "))
+    }
+
+    fn preprocess(src: &str) -> Result<String, Vec<String>> {
+        preprocess_file("test.c", src, &[])
+    }
+
+    #[test]
+    fn object_like_macros() {
+        let src = "#define N 10\n#define M N + 1\nint a[M];\n#undef N\nN";
+        assert_eq!(preprocess(src).unwrap(), "int a [ 10 + 1 ] ; N");
+
+        let src = "#define A B\n#define B A\nA B";
+        assert_eq!(preprocess(src).unwrap(), "A B");
+
+        let src = "#define EMPTY\nx EMPTY y";
+        assert_eq!(preprocess(src).unwrap(), "x y");
+    }
+
+    #[test]
+    fn conditionals() {
+        let src = "#define X\n\
+                   #ifdef X\na\n#else\nb\n#endif\n\
+                   #ifndef X\nc\n#else\nd\n#endif\n\
+                   #ifdef Y\n#ifdef X\ne\n#endif\n#else\nf\n#endif";
+        assert_eq!(preprocess(src).unwrap(), "a d f");
+
+        let src = "#ifdef Y\n#define Z 1\n#endif\nZ";
+        assert_eq!(preprocess(src).unwrap(), "Z");
+
+        // #elif is looked at inside an #ifdef group too.
+        let src = "#ifdef A\nx\n#elif 1\ny\n#endif";
+        assert_eq!(preprocess(src).unwrap(), "y");
+        let src = "#define A\n#ifdef A\nx\n#elif 1\ny\n#endif";
+        assert_eq!(preprocess(src).unwrap(), "x");
+
+        let src = "#define N 3\n#define F(x) x * 2\n\
+                   #if N > 4\na\n#elif defined N && F(N) == 6 && !defined(M) && UNDEFINED == 0\nb\n#else\nc\n#endif\n\
+                   #if (N - 3) ? 0 : 1\nd\n#elif 1 / 0\ne\n#else\nf\n#endif";
+        assert_eq!(preprocess(src).unwrap(), "b d");
+        let src = "#if -1 < 0u
+a
+#elif -1 / 2u == 0x7fffffff
+b
+#endif";
+        assert_eq!(preprocess(src).unwrap(), "b");
+    }
+
+    #[test]
+    fn newlines_are_kept() {
+        let mut lex = Lex::from_source("test.c", "#define X 1\n#ifdef X\nX\n#endif\n");
+        lex.parse().unwrap();
+        let tokens = Preprocessor::new(&[])
+            .process(lex.into_token_stream())
+            .unwrap();
+        let one = tokens.filter(|token| token.source() == "1").count();
+        assert_eq!(one, 1);
+
+        let mut lex = Lex::from_source("test.c", "#ifdef X\nx\n#endif\ny");
+        lex.parse().unwrap();
+        let tokens: Vec<Token> = Preprocessor::new(&[])
+            .process(lex.into_token_stream())
+            .unwrap()
+            .collect();
+        let newlines = tokens
+            .iter()
+            .filter(|token| *token.token_type() == TokenType::NewLine)
+            .count();
+        assert_eq!(newlines, 3);
+        assert_eq!(tokens[tokens.len() - 2].loc().line(), 4);
+    }
+
+    #[test]
+    fn includes() {
+        let dir = std::env::temp_dir().join(format!("ncc-include-{}", std::process::id()));
+        let sys = dir.join("sys");
+        fs::create_dir_all(&sys).unwrap();
+        fs::write(dir.join("local.h"), "#define LOCAL 1\nlocal").unwrap();
+        fs::write(sys.join("types.h"), "#include \"inner.h\"\nsystem").unwrap();
+        fs::write(sys.join("inner.h"), "inner").unwrap();
+        fs::write(dir.join("self.h"), "#include \"self.h\"").unwrap();
+
+        let main = dir.join("main.c");
+        let main = main.to_str().unwrap();
+        let include_paths = vec![sys.to_str().unwrap().to_string()];
+        let src = "#include \"local.h\"\n#include <types.h>\nLOCAL";
+        let result = preprocess_file(main, src, &include_paths);
+        let missing = preprocess_file(main, "#include <local.h>", &include_paths);
+        let recursive = preprocess_file(main, "#include \"self.h\"", &[]);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result.unwrap(), "local inner system 1");
+        assert_eq!(missing.unwrap_err(), vec!["'local.h' file not found"]);
+        assert_eq!(recursive.unwrap_err(), vec!["#include nested too deeply"]);
+    }
+
+    #[test]
+    fn directive_errors() {
+        let cases = [
+            ("#endif", "#endif without #if"),
+            ("#else", "#else without #if"),
+            ("#ifdef X\n#else\n#else\n#endif", "#else after #else"),
+            ("#ifdef X\nx", "Unterminated conditional directive"),
+            ("#define 1 2", "Macro name must be an identifier"),
+            ("#error stop  here", "#error stop  here"),
+            ("#include", "#include expects \"FILENAME\" or <FILENAME>"),
+            (
+                "#frobnicate",
+                "Unsupported preprocessing directive '#frobnicate'",
+            ),
+            ("#elif X", "#elif without #if"),
+            ("#if 1\n#else\n#elif 1\n#endif", "#elif after #else"),
+            ("#if\n#endif", "#if with no expression"),
+            (
+                "#if 1 +\n#endif",
+                "#if expects an integer constant expression",
+            ),
+            (
+                "#if 1 / 0\n#endif",
+                "#if expects an integer constant expression",
+            ),
+            (
+                "#if defined(X\n#endif",
+                "Operator 'defined' requires an identifier",
+            ),
+        ];
+
+        for (src, message) in cases {
+            assert_eq!(preprocess(src).unwrap_err(), vec![message], "{}", src);
+        }
+
+        assert_eq!(
+            preprocess("#ifdef X\n#frobnicate\n#error no\n#endif").unwrap(),
+            ""
+        );
+        assert_eq!(preprocess("#\n#pragma once\nx").unwrap(), "x");
+    }
+
+    #[test]
+    fn function_like_macros() {
+        let src = "#define MAX(a,b) ((a)>(b)?(a):(b))\nMAX(1, f(2, 3))";
+        assert_eq!(
+            preprocess(src).unwrap(),
+            "( ( 1 ) > ( f ( 2 , 3 ) ) ? ( 1 ) : ( f ( 2 , 3 ) ) )"
+        );
+
+        let src = "#define Z() 0\n#define G(x) x\n#define H (x)\nZ() Z G + 1 H";
+        assert_eq!(preprocess(src).unwrap(), "0 Z G + 1 ( x )");
+
+        // Arguments are expanded first, and a macro is not expanded inside itself.
+        let src = "#define N 4\n#define ID(x) x\n#define f(x) x + f(x)\nID(N) ID(ID(N)) f(1)";
+        assert_eq!(preprocess(src).unwrap(), "4 4 1 + f ( 1 )");
+
+        // An expansion is rescanned together with the text after it.
+        let src = "#define F(x) x\n#define G F\n#define H G(2) + G\nG(1) H(3) G";
+        assert_eq!(preprocess(src).unwrap(), "1 2 + 3 F");
+        let src = "#define f(x) x f\nf(1)(2)";
+        assert_eq!(preprocess(src).unwrap(), "1 f ( 2 )");
+        // C11 6.10.3.5: the argument list can also be finished there.
+        let src =
+            "#define x 2\n#define f(a) f(x * (a))\n#define g f\n#define h g(~\nh 5) h(1) + 2)";
+        assert_eq!(
+            preprocess(src).unwrap(),
+            "f ( 2 * ( ~ 5 ) ) f ( 2 * ( ~ ( 1 ) + 2 ) )"
+        );
+    }
+
+    #[test]
+    fn invocations_across_lines() {
+        let mut lex = Lex::from_source("test.c", "#define ADD(a, b) a + b\nADD(1,\n2)\nx");
+        lex.parse().unwrap();
+        let tokens: Vec<Token> = Preprocessor::new(&[])
+            .process(lex.into_token_stream())
+            .unwrap()
+            .collect();
+        let sources: Vec<&str> = tokens
+            .iter()
+            .filter(|token| !matches!(token.token_type(), TokenType::Space))
+            .map(|token| token.source())
+            .collect();
+        assert_eq!(sources, vec!["\n", "1", "+", "2", "\n", "\n", "x", ""]);
+    }
+
+    #[test]
+    fn variadic_macros() {
+        let src = "#define P(fmt, ...) printf(fmt, __VA_ARGS__)\nP(\"%d %d\", 1, (2, 3))";
+        assert_eq!(
+            preprocess(src).unwrap(),
+            "printf ( \"%d %d\" , 1 , ( 2 , 3 ) )"
+        );
+
+        let src = "#define V(...) [__VA_ARGS__]\n#define W(a, ...) a __VA_ARGS__\nV() V(a, b) W(1)";
+        assert_eq!(preprocess(src).unwrap(), "[ ] [ a , b ] 1");
+    }
+
+    #[test]
+    fn stringify_and_paste() {
+        let src = "#define S(x) #x\nS( a  +\n \"q\\n\" ) S() S(N)";
+        assert_eq!(preprocess(src).unwrap(), r#""a + \"q\\n\"" "" "N""#);
+
+        let src =
+            "#define CAT(a, b) a ## b\n#define N 4\nCAT(x, 1) CAT(, y) CAT(-, >) CAT(N, N) CAT(,)";
+        assert_eq!(preprocess(src).unwrap(), "x1 y -> NN");
+
+        let src = "#define VAR(n) var_ ## n ## _t\nVAR(int)";
+        assert_eq!(preprocess(src).unwrap(), "var_int_t");
+
+        // C11 6.10.3.3: a pasted '#' is not a directive or an operator.
+        let src = "#define hash_hash # ## #\n#define mkstr(a) # a\n#define in_between(a) mkstr(a)\n#define join(c, d) in_between(c hash_hash d)\nchar p[] = join(x, y);";
+        assert_eq!(preprocess(src).unwrap(), r#"char p [ ] = "x ## y" ;"#);
+    }
+
+    #[test]
+    fn macro_errors() {
+        let cases = [
+            (
+                "#define F(x) x\nF(1, 2)",
+                "Macro 'F' takes 1 argument(s), but 2 given",
+            ),
+            (
+                "#define F(x, y) x\nF(1)",
+                "Macro 'F' takes 2 argument(s), but 1 given",
+            ),
+            (
+                "#define F(x) x\nF(1",
+                "Unterminated argument list invoking macro 'F'",
+            ),
+            ("#define F(x,) x", "Invalid parameter list for macro 'F'"),
+            ("#define F(x, x) x", "Invalid parameter list for macro 'F'"),
+            (
+                "#define F(x) #y",
+                "'#' is not followed by a macro parameter",
+            ),
+            (
+                "#define F(x) ## x",
+                "'##' cannot appear at either end of a macro expansion",
+            ),
+            (
+                "#define CAT(a, b) a ## b\nCAT(+, /)",
+                "Pasting \"+\" and \"/\" does not give a valid preprocessing token",
+            ),
+        ];
+
+        for (src, message) in cases {
+            assert_eq!(preprocess(src).unwrap_err(), vec![message], "{}", src);
+        }
+    }
+
+    #[test]
+    fn predefined_macros() {
+        let src = "__FILE__ __LINE__\n__LINE__ __STDC__ __STDC_VERSION__";
+        assert_eq!(preprocess(src).unwrap(), "\"test.c\" 1 2 1 201710L");
+        assert_eq!(
+            preprocess_file("dir\\a\"b.c", "__FILE__", &[]).unwrap(),
+            r#""dir\\a\"b.c""#
+        );
+
+        let expand = |preprocessor: &mut Preprocessor, src: &str| -> String {
+            let mut lex = Lex::from_source("test.c", src);
+            lex.parse().unwrap();
+            let tokens = preprocessor.process(lex.into_token_stream()).unwrap();
+            tokens
+                .filter(|token| !is_space(token) && *token.token_type() != TokenType::Eof)
+                .map(|token| token.source().to_string())
+                .collect::<Vec<String>>()
+                .join(" ")
+        };
+        let mut preprocessor = Preprocessor::new(&[]);
+        let date = expand(&mut preprocessor, "__DATE__");
+        let time = expand(&mut preprocessor, "__TIME__");
+        assert_eq!((date.len(), time.len()), (13, 10));
+
+        preprocessor.set_std("c11");
+        assert_eq!(expand(&mut preprocessor, "__STDC_VERSION__"), "201112L");
+        preprocessor.set_std("c89");
+        assert_eq!(
+            expand(&mut preprocessor, "__STDC_VERSION__"),
+            "__STDC_VERSION__"
+        );
+
+        preprocessor.define("__STDC__", "0").unwrap();
+        preprocessor.define("__DATE__", "\"today\"").unwrap();
+        assert_eq!(
+            expand(&mut preprocessor, "__STDC__ __DATE__"),
+            "0 \"today\""
+        );
+
+        let errors = preprocessor.define("__LINE__", "3").unwrap_err();
+        assert_eq!(errors[0].message, "'__LINE__' cannot be redefined");
+        assert_eq!(
+            preprocess("#define __FILE__ x").unwrap_err(),
+            vec!["'__FILE__' cannot be redefined"]
+        );
+    }
+
+    #[test]
+    fn build_date_and_time() {
+        assert_eq!(
+            date_time(0),
+            (String::from("Jan  1 1970"), String::from("00:00:00"))
+        );
+        assert_eq!(
+            date_time(1791378309),
+            (String::from("Oct  7 2026"), String::from("13:05:09"))
+        );
+        assert_eq!(
+            date_time(951868799),
+            (String::from("Feb 29 2000"), String::from("23:59:59"))
+        );
+    }
+
+    #[test]
+    fn rendering() {
+        let render_src = |file: &str, src: &str| -> String {
+            let mut lex = Lex::from_source(file, src);
+            lex.parse().unwrap();
+            render(
+                file,
+                Preprocessor::new(&[])
+                    .process(lex.into_token_stream())
+                    .unwrap(),
+            )
+        };
+
+        let src = "#define N 2\nint a = N; /* one\ntwo */ int b;\nint  c;\n";
+        assert_eq!(
+            render_src("test.c", src),
+            "# 1 \"test.c\"\n\nint a = 2;   int b;\n\nint  c;\n"
+        );
+
+        let src = "#ifdef X\n\n\n\n\n\n\n\n\n\n#endif\nx\n";
+        assert_eq!(
+            render_src("test.c", src),
+            "# 1 \"test.c\"\n# 12 \"test.c\"\nx\n"
+        );
+
+        let dir = std::env::temp_dir().join(format!("ncc-render-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.h"), "int a;\n#include \"b.h\"\nint c;\n").unwrap();
+        fs::write(dir.join("b.h"), "int b;\n").unwrap();
+        let main = dir.join("main.c");
+        let main = main.to_str().unwrap();
+        let text = render_src(main, "#include \"a.h\"\nint main;\n");
+        let a = dir.join("a.h");
+        let b = dir.join("b.h");
+        fs::remove_dir_all(&dir).unwrap();
+
+        let expected = format!(
+            "# 1 \"{main}\"\n# 1 \"{a}\" 1\nint a;\n# 1 \"{b}\" 1\nint b;\n# 3 \"{a}\" 2\nint c;\n# 2 \"{main}\" 2\nint main;\n",
+            a = a.display(),
+            b = b.display(),
+            main = main
+        );
+        assert_eq!(text, expected);
+    }
+
+    #[test]
+    fn warnings_and_line_markers() {
+        let mut lex = Lex::from_source("test.c", "#warning check  this\n#line 10 \"a.c\"\nx\n");
+        lex.parse().unwrap();
+        let mut preprocessor = Preprocessor::new(&[]);
+        let tokens: Vec<Token> = preprocessor
+            .process(lex.into_token_stream())
+            .unwrap()
+            .collect();
+        assert_eq!(preprocessor.warnings()[0].message, "#warning check  this");
+        assert_eq!(preprocessor.warnings()[0].location.line(), 1);
+        let text: String = tokens.iter().map(|token| token.source()).collect();
+        assert_eq!(text, "\n#line 10 \"a.c\"\nx\n");
+    }
+
+    #[test]
+    fn command_line_defines() {
+        let mut preprocessor = Preprocessor::new(&[]);
+        preprocessor.define_arg("FOO=42").unwrap();
+        preprocessor.define_arg("DEBUG").unwrap();
+        preprocessor.define_arg("EMPTY=").unwrap();
+        preprocessor.define_arg("SQ(x)=((x)*(x))").unwrap();
+
+        let mut lex = Lex::from_source("test.c", "int a = FOO + DEBUG EMPTY;\nSQ(FOO)");
+        lex.parse().unwrap();
+        let tokens = preprocessor.process(lex.into_token_stream()).unwrap();
+        let sources: Vec<String> = tokens
+            .filter(|token| !is_space(token) && *token.token_type() != TokenType::Eof)
+            .map(|token| token.source().to_string())
+            .collect();
+        assert_eq!(sources.join(" "), "int a = 42 + 1 ; ( ( 42 ) * ( 42 ) )");
+
+        let errors = preprocessor.define_arg("2=3").unwrap_err();
+        assert_eq!(errors[0].message, "Macro name must be an identifier");
+    }
+
+    #[test]
+    fn comments_are_stripped() {
+        let dir = std::env::temp_dir().join(format!("ncc-strip-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("main.c");
+        fs::write(
+            &file,
+            "/* header\n * comment */\nint x; /* trailing */\nint  y;\n",
+        )
+        .unwrap();
+        let file = file.to_str().unwrap();
+
+        // The same steps main takes for -E.
+        let mut lex = Lex::new(file).unwrap();
+        lex.parse().unwrap();
+        let tokens = Preprocessor::new(&[])
+            .process(lex.into_token_stream())
+            .unwrap();
+        let text = render(file, tokens);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(text, format!("# 1 \"{}\"\n \n\nint x;  \nint  y;\n", file));
+    }
+
+    #[test]
+    fn digraph_directives() {
+        let src = "%:define N 3\n%:define STR(x) %:x\nint a<:N:>; STR(b)";
+        assert_eq!(preprocess(src).unwrap(), "int a <: 3 :> ; \"b\"");
+    }
+}
@@ -0,0 +1,944 @@
+use std::fmt;
+
+use crate::ast::{
+    BinOpKind, BlockItem, Decl, Expr, ExternalDecl, ForInit, SizeofArg, Stmt, StorageClass,
+    TranslationUnit, UnaryOpKind,
+};
+use crate::lex::Span;
+use crate::symbol_table::{Site, SymbolInfo, SymbolTable};
+use crate::types::{
+    integer_promotions, string_elem, usual_arithmetic_conversions, ArraySize, CType, IntSize,
+    Signedness, StructId, TypeQuals, INT, LONG, UNSIGNED_LONG,
+};
+
+/// A type error found after parsing. Warnings use the same type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SemanticError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl fmt::Display for SemanticError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Error: \"{}\" at bytes {}..{}",
+            self.message, self.span.start, self.span.end
+        )
+    }
+}
+
+/// Whether `ty` is const-qualified at the top level.
+fn is_const(mut ty: &CType) -> bool {
+    loop {
+        match ty {
+            CType::Typedef(_, inner) => ty = inner,
+            CType::Qualified { inner, quals } => {
+                if quals.contains(TypeQuals::CONST) {
+                    return true;
+                }
+                ty = inner;
+            }
+            _ => return false,
+        }
+    }
+}
+
+/// `ty` with every typedef name and qualifier removed, at any depth, so that
+/// two types can be compared with `==`.
+fn unqualified(ty: &CType) -> CType {
+    match ty.strip() {
+        CType::Pointer { pointee, .. } => CType::Pointer {
+            pointee: Box::new(unqualified(pointee)),
+            quals: TypeQuals::NONE,
+        },
+        CType::Array { elem, size } => CType::Array {
+            elem: Box::new(unqualified(elem)),
+            size: *size,
+        },
+        CType::Function {
+            ret,
+            params,
+            variadic,
+        } => CType::Function {
+            ret: Box::new(unqualified(ret)),
+            params: params.iter().map(unqualified).collect(),
+            variadic: *variadic,
+        },
+        ty => ty.clone(),
+    }
+}
+
+/// The type of an expression's value once used: qualifiers are dropped,
+/// arrays become pointers to their first element and functions pointers to
+/// themselves.
+fn decay(ty: &CType) -> CType {
+    match ty.strip() {
+        CType::Array { elem, .. } => CType::Pointer {
+            pointee: elem.clone(),
+            quals: TypeQuals::NONE,
+        },
+        function @ CType::Function { .. } => CType::Pointer {
+            pointee: Box::new(function.clone()),
+            quals: TypeQuals::NONE,
+        },
+        ty => ty.clone(),
+    }
+}
+
+fn is_scalar(ty: &CType) -> bool {
+    ty.is_arithmetic() || ty.pointee().is_some()
+}
+
+fn is_void(ty: &CType) -> bool {
+    *ty.strip() == CType::Void
+}
+
+/// Whether two pointers may be compared or assigned to each other: they
+/// point to the same type, or one of them is a `void *`.
+fn compatible_pointers(a: &CType, b: &CType) -> bool {
+    match (a.pointee(), b.pointee()) {
+        (Some(a), Some(b)) => is_void(a) || is_void(b) || unqualified(a) == unqualified(b),
+        _ => false,
+    }
+}
+
+fn is_null_constant(expr: &Expr) -> bool {
+    matches!(expr, Expr::IntLit(0, ..))
+}
+
+/// Whether `expr` designates an object, going by its shape alone.
+fn is_lvalue(expr: &Expr) -> bool {
+    match expr {
+        Expr::Ident(..) | Expr::StrLit(..) | Expr::Index { .. } => true,
+        Expr::UnaryOp { op, .. } => *op == UnaryOpKind::Deref,
+        Expr::Member { obj, arrow, .. } => *arrow || is_lvalue(obj),
+        _ => false,
+    }
+}
+
+fn is_unsigned(ty: &CType) -> bool {
+    matches!(
+        ty,
+        CType::Int {
+            sign: Signedness::Unsigned,
+            ..
+        }
+    )
+}
+
+/// Checks the types in a parsed translation unit. Every expression gets a
+/// type, or `None` once an error has been reported for it, so that one
+/// mistake is not reported again by every expression around it.
+pub struct Sema<'a> {
+    unit: &'a TranslationUnit,
+    symbols: SymbolTable,
+    /// The return type of the function being checked.
+    ret: Option<CType>,
+    errors: Vec<SemanticError>,
+    warnings: Vec<SemanticError>,
+}
+
+impl<'a> Sema<'a> {
+    pub fn new(unit: &'a TranslationUnit) -> Sema<'a> {
+        Sema {
+            unit,
+            symbols: SymbolTable::new(),
+            ret: None,
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn check(&mut self) -> Result<(), Vec<SemanticError>> {
+        for decl in &self.unit.decls {
+            match decl {
+                ExternalDecl::Decl(decl) => self.decl(decl),
+                ExternalDecl::Function(function) => {
+                    let site = Site::DefinedAt(function.span);
+                    self.declare(&function.name, function.ty.clone(), function.storage, site);
+                    if let CType::Function { ret, .. } = function.ty.strip() {
+                        self.ret = Some(*ret.clone());
+                    }
+                    self.symbols.push_scope();
+                    for param in &function.params {
+                        if let Some(name) = &param.name {
+                            let site = Site::DefinedAt(param.span);
+                            self.declare(name, param.ty.clone(), None, site);
+                        }
+                    }
+                    self.stmt(&function.body);
+                    self.symbols.pop_scope();
+                    self.ret = None;
+                }
+            }
+        }
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    pub fn warnings(&self) -> &[SemanticError] {
+        &self.warnings
+    }
+
+    fn error(&mut self, span: Span, message: String) {
+        self.errors.push(SemanticError { span, message });
+    }
+
+    fn warning(&mut self, span: Span, message: String) {
+        self.warnings.push(SemanticError { span, message });
+    }
+
+    fn declare(&mut self, name: &str, ty: CType, storage: Option<StorageClass>, site: Site) {
+        // The parser has already reported any redefinition.
+        let _ = self.symbols.insert(
+            name,
+            SymbolInfo {
+                ty,
+                storage,
+                site,
+                value: None,
+            },
+        );
+    }
+
+    /// How `ty` is written in C, for diagnostics.
+    fn describe(&self, ty: &CType) -> String {
+        match ty {
+            CType::Void => String::from("void"),
+            CType::Bool => String::from("_Bool"),
+            CType::Char(Signedness::Signed) => String::from("char"),
+            CType::Char(Signedness::Unsigned) => String::from("unsigned char"),
+            CType::Int { size, sign } => {
+                let name = match size {
+                    IntSize::Short => "short",
+                    IntSize::Int => "int",
+                    IntSize::Long => "long",
+                    IntSize::LongLong => "long long",
+                };
+                match sign {
+                    Signedness::Signed => String::from(name),
+                    Signedness::Unsigned => format!("unsigned {}", name),
+                }
+            }
+            CType::Float => String::from("float"),
+            CType::Double => String::from("double"),
+            CType::LongDouble => String::from("long double"),
+            CType::Pointer { pointee, .. } => format!("{} *", self.describe(pointee)),
+            CType::Array { elem, .. } => format!("{} []", self.describe(elem)),
+            CType::Function { ret, .. } => format!("{} ()", self.describe(ret)),
+            CType::Struct(id) | CType::Union(id) => {
+                let def = self.unit.struct_def(*id);
+                let keyword = if def.is_union { "union" } else { "struct" };
+                format!(
+                    "{} {}",
+                    keyword,
+                    def.tag.as_deref().unwrap_or("<anonymous>")
+                )
+            }
+            CType::Enum(id) => format!(
+                "enum {}",
+                self.unit
+                    .enum_def(*id)
+                    .tag
+                    .as_deref()
+                    .unwrap_or("<anonymous>")
+            ),
+            CType::Typedef(name, _) => name.clone(),
+            CType::Qualified { inner, quals } => {
+                let mut text = String::new();
+                for (qual, name) in [
+                    (TypeQuals::CONST, "const "),
+                    (TypeQuals::VOLATILE, "volatile "),
+                    (TypeQuals::RESTRICT, "restrict "),
+                ] {
+                    if quals.contains(qual) {
+                        text += name;
+                    }
+                }
+                text + &self.describe(inner)
+            }
+        }
+    }
+
+    fn decl(&mut self, decl: &Decl) {
+        let site = if decl.init.is_some() {
+            Site::DefinedAt(decl.span)
+        } else {
+            Site::DeclaredAt(decl.span)
+        };
+        self.declare(&decl.name, decl.ty.clone(), decl.storage, site);
+
+        match &decl.init {
+            None => {}
+            Some(Expr::InitList(items, _)) => {
+                for item in items {
+                    self.type_of(item);
+                }
+            }
+            Some(init) => {
+                let value = self.value_of(init);
+                if let CType::Array { .. } = decl.ty.strip() {
+                    if !matches!(init, Expr::StrLit(..)) {
+                        self.error(
+                            init.span(),
+                            String::from(
+                                "Array initializer must be an initializer list or string literal",
+                            ),
+                        );
+                    }
+                } else if let Some(value) = value {
+                    self.check_assignable(&decl.ty, &value, init);
+                }
+            }
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(expr, _) => {
+                self.type_of(expr);
+            }
+            Stmt::Return(value, span) => {
+                let Some(value) = value else {
+                    return;
+                };
+                let ty = self.value_of(value);
+                match (self.ret.clone(), ty) {
+                    (Some(ret), Some(_)) if is_void(&ret) => self.error(
+                        *span,
+                        String::from("Void function should not return a value"),
+                    ),
+                    (Some(ret), Some(ty)) => self.check_assignable(&ret, &ty, value),
+                    _ => {}
+                }
+            }
+            Stmt::If {
+                cond, then, else_, ..
+            } => {
+                self.condition(cond);
+                self.stmt(then);
+                if let Some(else_) = else_ {
+                    self.stmt(else_);
+                }
+            }
+            Stmt::While { cond, body, .. } | Stmt::DoWhile { body, cond, .. } => {
+                self.condition(cond);
+                self.stmt(body);
+            }
+            Stmt::For {
+                init,
+                cond,
+                step,
+                body,
+                ..
+            } => {
+                self.symbols.push_scope();
+                match init {
+                    Some(ForInit::Expr(expr)) => {
+                        self.type_of(expr);
+                    }
+                    Some(ForInit::Decl(decls)) => {
+                        for decl in decls {
+                            self.decl(decl);
+                        }
+                    }
+                    None => {}
+                }
+                if let Some(cond) = cond {
+                    self.condition(cond);
+                }
+                if let Some(step) = step {
+                    self.type_of(step);
+                }
+                self.stmt(body);
+                self.symbols.pop_scope();
+            }
+            Stmt::Switch { expr, body, .. } => {
+                if let Some(ty) = self.value_of(expr) {
+                    if !ty.is_integer() {
+                        let message = format!(
+                            "Statement requires expression of integer type ('{}' invalid)",
+                            self.describe(&ty)
+                        );
+                        self.error(expr.span(), message);
+                    }
+                }
+                self.stmt(body);
+            }
+            Stmt::Case(value, stmt, _) => {
+                self.type_of(value);
+                self.stmt(stmt);
+            }
+            Stmt::Label(_, stmt, _) | Stmt::Default(stmt, _) => self.stmt(stmt),
+            Stmt::Block(items, _) => {
+                self.symbols.push_scope();
+                for item in items {
+                    match item {
+                        BlockItem::Decl(decl) => self.decl(decl),
+                        BlockItem::Stmt(stmt) => self.stmt(stmt),
+                    }
+                }
+                self.symbols.pop_scope();
+            }
+            Stmt::Decl(decl) => self.decl(decl),
+            Stmt::Break(_) | Stmt::Continue(_) | Stmt::Goto(..) | Stmt::Empty(_) => {}
+        }
+    }
+
+    /// The controlling expression of an `if`, a loop or a `?:`.
+    fn condition(&mut self, cond: &Expr) {
+        if let Some(ty) = self.value_of(cond) {
+            if !is_scalar(&ty) {
+                let message = format!(
+                    "Used type '{}' where arithmetic or pointer type is required",
+                    self.describe(&ty)
+                );
+                self.error(cond.span(), message);
+            }
+        }
+    }
+
+    /// Reports an error unless a `value` may be assigned to an object of type
+    /// `target`, as by `=`, an initializer, an argument or a `return`.
+    fn check_assignable(&mut self, target: &CType, value: &CType, expr: &Expr) {
+        let ok = match (target.strip(), value.strip()) {
+            (a, b) if a.is_arithmetic() && b.is_arithmetic() => true,
+            (CType::Bool, CType::Pointer { .. }) => true,
+            (CType::Pointer { .. }, CType::Pointer { .. }) => compatible_pointers(target, value),
+            (CType::Pointer { .. }, _) => is_null_constant(expr),
+            (CType::Struct(a), CType::Struct(b)) | (CType::Union(a), CType::Union(b)) => a == b,
+            _ => false,
+        };
+        if !ok {
+            let message = format!(
+                "Incompatible types assigning '{}' to '{}'",
+                self.describe(value),
+                self.describe(target)
+            );
+            self.error(expr.span(), message);
+        }
+    }
+
+    /// The type of `expr` once its value is used; see `decay`.
+    fn value_of(&mut self, expr: &Expr) -> Option<CType> {
+        self.type_of(expr).map(|ty| decay(&ty))
+    }
+
+    /// The type of `expr` as declared, before it decays.
+    fn type_of(&mut self, expr: &Expr) -> Option<CType> {
+        match expr {
+            Expr::IntLit(_, ty, _) => Some(ty.clone()),
+            Expr::FloatLit(..) => Some(CType::Double),
+            Expr::Enumerator(..) => Some(INT),
+            Expr::StrLit(values, encoding, _) => Some(CType::Array {
+                elem: Box::new(string_elem(*encoding)),
+                size: ArraySize::Fixed(values.len() as u64 + 1),
+            }),
+            Expr::Ident(name, span) => match self.symbols.lookup(name) {
+                Some(info) => Some(info.ty.clone()),
+                None => {
+                    self.error(*span, format!("Use of undeclared identifier '{}'", name));
+                    None
+                }
+            },
+            Expr::BinOp { op, lhs, rhs, span } => self.binop(*op, lhs, rhs, *span),
+            Expr::UnaryOp { op, expr, span } => self.unary(*op, expr, *span),
+            Expr::Call { callee, args, span } => self.call(callee, args, *span),
+            Expr::Index { base, idx, span } => {
+                let base = self.value_of(base);
+                let idx = self.value_of(idx);
+                let (base, idx) = (base?, idx?);
+                match (base.pointee(), idx.pointee()) {
+                    (Some(elem), None) if idx.is_integer() => Some(elem.clone()),
+                    (None, Some(elem)) if base.is_integer() => Some(elem.clone()),
+                    _ => {
+                        self.error(
+                            *span,
+                            String::from("Subscripted value is not an array or pointer"),
+                        );
+                        None
+                    }
+                }
+            }
+            Expr::Member {
+                obj,
+                field,
+                arrow,
+                span,
+            } => self.member(obj, field, *arrow, *span),
+            Expr::Cast { ty, expr, .. } => {
+                self.type_of(expr);
+                Some(ty.clone())
+            }
+            Expr::Ternary {
+                cond, then, els, ..
+            } => {
+                self.condition(cond);
+                let then = self.value_of(then);
+                let els = self.value_of(els);
+                let (then, els) = (then?, els?);
+                if then.is_arithmetic() && els.is_arithmetic() {
+                    Some(usual_arithmetic_conversions(&then, &els))
+                } else {
+                    Some(then)
+                }
+            }
+            Expr::Sizeof(arg, _) => {
+                if let SizeofArg::Expr(expr) = arg {
+                    self.type_of(expr);
+                }
+                Some(UNSIGNED_LONG)
+            }
+            Expr::InitList(items, span) => {
+                for item in items {
+                    self.type_of(item);
+                }
+                self.error(
+                    *span,
+                    String::from("Initializer list used outside an initializer"),
+                );
+                None
+            }
+        }
+    }
+
+    fn binop(&mut self, op: BinOpKind, lhs: &Expr, rhs: &Expr, span: Span) -> Option<CType> {
+        if op == BinOpKind::Comma {
+            self.type_of(lhs);
+            return self.value_of(rhs);
+        }
+        if op.is_assignment() {
+            return self.assignment(op, lhs, rhs, span);
+        }
+        let left = self.value_of(lhs);
+        let right = self.value_of(rhs);
+        self.operands(op, &left?, &right?, lhs, rhs, span)
+    }
+
+    /// The type of `lhs op rhs` for a binary operator other than an
+    /// assignment or the comma.
+    fn operands(
+        &mut self,
+        op: BinOpKind,
+        left: &CType,
+        right: &CType,
+        lhs: &Expr,
+        rhs: &Expr,
+        span: Span,
+    ) -> Option<CType> {
+        let arithmetic = left.is_arithmetic() && right.is_arithmetic();
+        let integer = left.is_integer() && right.is_integer();
+        let ty = match op {
+            BinOpKind::Mul | BinOpKind::Div if arithmetic => {
+                Some(usual_arithmetic_conversions(left, right))
+            }
+            BinOpKind::Mod | BinOpKind::BitAnd | BinOpKind::BitOr | BinOpKind::BitXor
+                if integer =>
+            {
+                Some(usual_arithmetic_conversions(left, right))
+            }
+            BinOpKind::Shl | BinOpKind::Shr if integer => Some(integer_promotions(left)),
+            BinOpKind::Add | BinOpKind::Sub if arithmetic => {
+                Some(usual_arithmetic_conversions(left, right))
+            }
+            BinOpKind::Add | BinOpKind::Sub if left.pointee().is_some() && right.is_integer() => {
+                Some(left.clone())
+            }
+            BinOpKind::Add if left.is_integer() && right.pointee().is_some() => Some(right.clone()),
+            BinOpKind::Sub if compatible_pointers(left, right) => Some(LONG),
+            BinOpKind::Lt
+            | BinOpKind::Gt
+            | BinOpKind::Le
+            | BinOpKind::Ge
+            | BinOpKind::Eq
+            | BinOpKind::Ne => {
+                let equality = matches!(op, BinOpKind::Eq | BinOpKind::Ne);
+                if arithmetic {
+                    // Literals are never negative, so comparing one with an
+                    // unsigned value is fine.
+                    let signed = if is_unsigned(&integer_promotions(left)) {
+                        rhs
+                    } else {
+                        lhs
+                    };
+                    if integer
+                        && is_unsigned(&usual_arithmetic_conversions(left, right))
+                        && is_unsigned(&integer_promotions(left))
+                            != is_unsigned(&integer_promotions(right))
+                        && !matches!(signed, Expr::IntLit(..))
+                    {
+                        let message = format!(
+                            "Comparison of integers of different signs: '{}' and '{}'",
+                            self.describe(left),
+                            self.describe(right)
+                        );
+                        self.warning(span, message);
+                    }
+                    Some(INT)
+                } else if compatible_pointers(left, right)
+                    || equality && left.pointee().is_some() && is_null_constant(rhs)
+                    || equality && right.pointee().is_some() && is_null_constant(lhs)
+                {
+                    Some(INT)
+                } else {
+                    None
+                }
+            }
+            BinOpKind::LogAnd | BinOpKind::LogOr if is_scalar(left) && is_scalar(right) => {
+                Some(INT)
+            }
+            _ => None,
+        };
+        if ty.is_none() {
+            let message = format!(
+                "Invalid operands to binary '{}' ('{}' and '{}')",
+                op.symbol(),
+                self.describe(left),
+                self.describe(right)
+            );
+            self.error(span, message);
+        }
+        ty
+    }
+
+    /// Checks that `lhs` can be assigned to at all, and reports an error if
+    /// not.
+    fn check_modifiable(&mut self, lhs: &Expr, ty: &CType) -> bool {
+        let message = if !is_lvalue(lhs) {
+            "Expression is not assignable"
+        } else if matches!(ty.strip(), CType::Array { .. } | CType::Function { .. }) {
+            "Array or function is not assignable"
+        } else if is_const(ty) {
+            "Cannot assign to a read-only location"
+        } else {
+            return true;
+        };
+        self.error(lhs.span(), String::from(message));
+        false
+    }
+
+    fn assignment(&mut self, op: BinOpKind, lhs: &Expr, rhs: &Expr, span: Span) -> Option<CType> {
+        let target = self.type_of(lhs);
+        let value = self.value_of(rhs);
+        let target = target?;
+        if !self.check_modifiable(lhs, &target) {
+            return None;
+        }
+        let value = value?;
+        match op.compound_op() {
+            Some(op) => {
+                self.operands(op, &decay(&target), &value, lhs, rhs, span)?;
+            }
+            None => self.check_assignable(&target, &value, rhs),
+        }
+        Some(decay(&target))
+    }
+
+    fn unary(&mut self, op: UnaryOpKind, expr: &Expr, span: Span) -> Option<CType> {
+        if op == UnaryOpKind::AddrOf {
+            let ty = self.type_of(expr)?;
+            if !is_lvalue(expr) && !matches!(ty.strip(), CType::Function { .. }) {
+                self.error(span, String::from("Cannot take the address of an rvalue"));
+                return None;
+            }
+            return Some(CType::Pointer {
+                pointee: Box::new(ty),
+                quals: TypeQuals::NONE,
+            });
+        }
+        if matches!(
+            op,
+            UnaryOpKind::PreInc | UnaryOpKind::PreDec | UnaryOpKind::PostInc | UnaryOpKind::PostDec
+        ) {
+            let ty = self.type_of(expr)?;
+            if !self.check_modifiable(expr, &ty) {
+                return None;
+            }
+            let ty = decay(&ty);
+            if !is_scalar(&ty) {
+                let message = format!(
+                    "Cannot increment or decrement a value of type '{}'",
+                    self.describe(&ty)
+                );
+                self.error(span, message);
+                return None;
+            }
+            return Some(ty);
+        }
+
+        let ty = self.value_of(expr)?;
+        let result = match op {
+            UnaryOpKind::Plus | UnaryOpKind::Neg if ty.is_arithmetic() => {
+                Some(integer_promotions(&ty))
+            }
+            UnaryOpKind::BitNot if ty.is_integer() => Some(integer_promotions(&ty)),
+            UnaryOpKind::LogNot if is_scalar(&ty) => Some(INT),
+            UnaryOpKind::Deref => ty.pointee().cloned(),
+            _ => None,
+        };
+        if result.is_none() {
+            let message = format!(
+                "Invalid argument type '{}' to unary expression",
+                self.describe(&ty)
+            );
+            self.error(span, message);
+        }
+        result
+    }
+
+    fn call(&mut self, callee: &Expr, args: &[Expr], span: Span) -> Option<CType> {
+        let callee_ty = self.value_of(callee);
+        let arg_tys: Vec<Option<CType>> = args.iter().map(|arg| self.value_of(arg)).collect();
+        let callee_ty = callee_ty?;
+
+        let Some(CType::Function {
+            ret,
+            params,
+            variadic,
+        }) = callee_ty.pointee().map(CType::strip)
+        else {
+            let message = format!(
+                "Called object of type '{}' is not a function",
+                self.describe(&callee_ty)
+            );
+            self.error(span, message);
+            return None;
+        };
+
+        // An empty list may also be `f()`, which says nothing about the
+        // parameters, so only too few arguments can be told apart there.
+        if args.len() < params.len() || args.len() > params.len() && !variadic && !params.is_empty()
+        {
+            let message = format!(
+                "Too {} arguments to function call, expected {}, have {}",
+                if args.len() < params.len() {
+                    "few"
+                } else {
+                    "many"
+                },
+                params.len(),
+                args.len()
+            );
+            self.error(span, message);
+        }
+        for ((arg, ty), param) in args.iter().zip(&arg_tys).zip(params) {
+            if let Some(ty) = ty {
+                self.check_assignable(param, ty, arg);
+            }
+        }
+        Some(*ret.clone())
+    }
+
+    fn member(&mut self, obj: &Expr, field: &str, arrow: bool, span: Span) -> Option<CType> {
+        let ty = if arrow {
+            self.value_of(obj)?
+        } else {
+            self.type_of(obj)?
+        };
+        let record = if arrow {
+            match ty.pointee() {
+                Some(record) => record.strip(),
+                None => {
+                    let message = format!(
+                        "Member reference type '{}' is not a pointer",
+                        self.describe(&ty)
+                    );
+                    self.error(span, message);
+                    return None;
+                }
+            }
+        } else {
+            ty.strip()
+        };
+        let (CType::Struct(id) | CType::Union(id)) = record else {
+            let message = format!(
+                "Member reference base type '{}' is not a struct or union",
+                self.describe(record)
+            );
+            self.error(span, message);
+            return None;
+        };
+
+        if self.unit.struct_def(*id).fields.is_none() {
+            let message = format!("Incomplete definition of type '{}'", self.describe(record));
+            self.error(span, message);
+            return None;
+        }
+        let found = self.field(*id, field);
+        if found.is_none() {
+            let message = format!("No member named '{}' in '{}'", field, self.describe(record));
+            self.error(span, message);
+        }
+        found
+    }
+
+    /// The type of the member `name` of a struct or union, looking inside
+    /// anonymous members too.
+    fn field(&self, id: StructId, name: &str) -> Option<CType> {
+        for field in self.unit.struct_def(id).fields.iter().flatten() {
+            match (&field.name, field.ty.strip()) {
+                (Some(field_name), _) if field_name == name => return Some(field.ty.clone()),
+                (None, CType::Struct(inner) | CType::Union(inner)) => {
+                    if let Some(ty) = self.field(*inner, name) {
+                        return Some(ty);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex::Lex;
+    use crate::parser::Parser;
+
+    /// The messages of the errors and warnings in `src`, which must parse.
+    fn check(src: &str) -> (Vec<String>, Vec<String>) {
+        let mut lex = Lex::from_source("test.c", src);
+        lex.parse().unwrap();
+        let unit = Parser::new(lex.token_stream()).parse().unwrap();
+        let mut sema = Sema::new(&unit);
+        let errors = sema.check().err().unwrap_or_default();
+        let warnings = sema.warnings().iter().map(|w| w.message.clone()).collect();
+        (errors.into_iter().map(|e| e.message).collect(), warnings)
+    }
+
+    fn errors(src: &str) -> Vec<String> {
+        check(src).0
+    }
+
+    #[test]
+    fn well_typed() {
+        let src = "struct point { int x, y; };\n\
+                   typedef struct point point_t;\n\
+                   int add(int a, int b) { return a + b; }\n\
+                   int main(void) {\n\
+                       point_t p = {1, 2}; point_t *q = &p;\n\
+                       int a[4]; int *r = a + 1; long d = r - a;\n\
+                       char s[] = \"abc\"; const char *t = s;\n\
+                       double f = p.x * 1.5; unsigned u = 3;\n\
+                       q->y += add(p.x, 2); r[2] = *r; r++;\n\
+                       if (q && r != 0 && u > 1u) return f > 0 ? 1 : 0;\n\
+                       return (int)f;\n\
+                   }";
+        assert_eq!(check(src), (Vec::new(), Vec::new()));
+    }
+
+    #[test]
+    fn operand_errors() {
+        let cases = [
+            (
+                "int *f(int *p, int *q) { return p + q; }",
+                "Invalid operands to binary '+' ('int *' and 'int *')",
+            ),
+            (
+                "double f(double x) { return x % 2; }",
+                "Invalid operands to binary '%' ('double' and 'int')",
+            ),
+            (
+                "struct s { int a; }; int f(struct s v) { return v * 2; }",
+                "Invalid operands to binary '*' ('struct s' and 'int')",
+            ),
+            (
+                "int f(int *p, char *q) { return p == q; }",
+                "Invalid operands to binary '==' ('int *' and 'char *')",
+            ),
+            (
+                "int f(int x) { return *x; }",
+                "Invalid argument type 'int' to unary expression",
+            ),
+            (
+                "int f(void) { return y; }",
+                "Use of undeclared identifier 'y'",
+            ),
+            (
+                "int g(int a); int f(void) { return g(1, 2); }",
+                "Too many arguments to function call, expected 1, have 2",
+            ),
+            (
+                "struct s { int a; }; int f(struct s v) { return v.b; }",
+                "No member named 'b' in 'struct s'",
+            ),
+        ];
+        for (src, message) in cases {
+            assert_eq!(errors(src), vec![String::from(message)], "{}", src);
+        }
+        // An error is reported once, not again by each enclosing expression.
+        assert_eq!(errors("int f(void) { return (y + 1) * 2; }").len(), 1);
+    }
+
+    #[test]
+    fn assignments() {
+        let cases = [
+            (
+                "void f(int a) { a + 1 = 2; }",
+                "Expression is not assignable",
+            ),
+            (
+                "void f(void) { const int c = 1; c = 2; }",
+                "Cannot assign to a read-only location",
+            ),
+            (
+                "void f(void) { int a[2]; int b[2]; a = b; }",
+                "Array or function is not assignable",
+            ),
+            (
+                "void f(int *p) { double d = 1; p = d; }",
+                "Incompatible types assigning 'double' to 'int *'",
+            ),
+            (
+                "void f(int *p, long *q) { p = q; }",
+                "Incompatible types assigning 'long *' to 'int *'",
+            ),
+            (
+                "int f(void) { int x = 1; x++ = 2; return x; }",
+                "Expression is not assignable",
+            ),
+            (
+                "void f(void) { return 1; }",
+                "Void function should not return a value",
+            ),
+        ];
+        for (src, message) in cases {
+            assert_eq!(errors(src), vec![String::from(message)], "{}", src);
+        }
+
+        let ok = "void f(int *p, void *v, double d) { int x; p = 0; v = p; p = v; x = d; *p = x; }";
+        assert_eq!(errors(ok), Vec::<String>::new());
+    }
+
+    #[test]
+    fn sign_comparisons() {
+        let (errors, warnings) = check("int f(int a, unsigned b) { return a < b; }");
+        assert!(errors.is_empty());
+        assert_eq!(
+            warnings,
+            vec![String::from(
+                "Comparison of integers of different signs: 'int' and 'unsigned int'"
+            )]
+        );
+
+        // Both operands fit in a long, so nothing is lost.
+        let (_, warnings) = check("int f(long a, unsigned b, short c) { return a < b || c == 1; }");
+        assert!(warnings.is_empty());
+
+        // Suffixes make a literal unsigned; a negated one is not a literal.
+        let (_, warnings) = check("int f(int a) { return (-1 < 1u) + (a < 1U) + (a < 1L); }");
+        assert_eq!(
+            warnings,
+            vec![
+                String::from("Comparison of integers of different signs: 'int' and 'unsigned int'"),
+                String::from("Comparison of integers of different signs: 'int' and 'unsigned int'"),
+            ]
+        );
+    }
+}
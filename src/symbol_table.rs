@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::StorageClass;
+use crate::lex::Span;
+use crate::types::CType;
+
+/// Where a symbol was last declared, and whether that declaration also
+/// defined it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Site {
+    /// A declaration that leaves the definition to somewhere else, such as a
+    /// function prototype, an `extern` object or a tentative definition.
+    DeclaredAt(Span),
+    DefinedAt(Span),
+}
+
+impl Site {
+    pub fn span(self) -> Span {
+        match self {
+            Site::DeclaredAt(span) | Site::DefinedAt(span) => span,
+        }
+    }
+
+    pub fn is_definition(self) -> bool {
+        matches!(self, Site::DefinedAt(_))
+    }
+}
+
+/// What an ordinary identifier has been declared as.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolInfo {
+    pub ty: CType,
+    pub storage: Option<StorageClass>,
+    pub site: Site,
+    /// The value of an enumeration constant; `None` for anything else.
+    pub value: Option<i64>,
+}
+
+impl SymbolInfo {
+    /// Whether the name stands for a type rather than an object or function.
+    pub fn is_typedef(&self) -> bool {
+        self.storage == Some(StorageClass::Typedef)
+    }
+}
+
+/// A name declared twice in one scope in a way C does not allow.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RedefinitionError {
+    pub name: String,
+    pub span: Span,
+    /// The earlier declaration.
+    pub previous: Span,
+}
+
+impl fmt::Display for RedefinitionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Error: \"Redefinition of '{}'\" at bytes {}..{}",
+            self.name, self.span.start, self.span.end
+        )
+    }
+}
+
+pub type Scope = HashMap<String, SymbolInfo>;
+
+/// The ordinary identifiers visible at a point in the source, innermost
+/// scope last. The file scope is always there and is never popped.
+#[derive(Debug, Clone)]
+pub struct SymbolTable {
+    scopes: Vec<Scope>,
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable {
+            scopes: vec![Scope::new()],
+        }
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Scope::new());
+    }
+
+    pub fn pop_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    pub fn is_file_scope(&self) -> bool {
+        self.scopes.len() == 1
+    }
+
+    /// Declares `name` in the innermost scope. Declaring it again there is
+    /// fine as long as both are typedefs or neither is, and at most one of
+    /// them is a definition; a later definition replaces an earlier
+    /// declaration. A typedef may also be defined again as the same type.
+    /// Whether the types of other declarations agree is left to semantic
+    /// analysis.
+    pub fn insert(&mut self, name: &str, info: SymbolInfo) -> Result<(), RedefinitionError> {
+        let scope = self.scopes.last_mut().unwrap();
+        let Some(previous) = scope.get_mut(name) else {
+            scope.insert(String::from(name), info);
+            return Ok(());
+        };
+
+        let same_typedef = info.is_typedef() && previous.ty.strip() == info.ty.strip();
+        if previous.is_typedef() != info.is_typedef()
+            || previous.site.is_definition() && info.site.is_definition() && !same_typedef
+        {
+            return Err(RedefinitionError {
+                name: String::from(name),
+                span: info.site.span(),
+                previous: previous.site.span(),
+            });
+        }
+        if info.site.is_definition() {
+            *previous = info;
+        }
+        Ok(())
+    }
+
+    /// Finds the declaration `name` refers to, searching outwards from the
+    /// innermost scope.
+    pub fn lookup(&self, name: &str) -> Option<&SymbolInfo> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    pub fn lookup_current_scope(&self, name: &str) -> Option<&SymbolInfo> {
+        self.scopes.last().unwrap().get(name)
+    }
+
+    /// The type `name` stands for, if it is a typedef name in scope.
+    pub fn typedef(&self, name: &str) -> Option<&CType> {
+        self.lookup(name)
+            .filter(|info| info.is_typedef())
+            .map(|info| &info.ty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::INT;
+
+    fn info(storage: Option<StorageClass>, site: Site) -> SymbolInfo {
+        SymbolInfo {
+            ty: INT,
+            storage,
+            site,
+            value: None,
+        }
+    }
+
+    fn span(start: usize) -> Span {
+        Span {
+            start,
+            end: start + 1,
+        }
+    }
+
+    #[test]
+    fn scopes() {
+        let mut table = SymbolTable::new();
+        let typedef = info(Some(StorageClass::Typedef), Site::DefinedAt(span(0)));
+        table.insert("T", typedef.clone()).unwrap();
+        assert!(table.is_file_scope());
+        assert_eq!(table.typedef("T"), Some(&INT));
+
+        table.push_scope();
+        assert!(table.lookup_current_scope("T").is_none());
+        table
+            .insert("T", info(None, Site::DefinedAt(span(1))))
+            .unwrap();
+        assert!(table.typedef("T").is_none());
+        assert!(!table.lookup("T").unwrap().is_typedef());
+
+        table.pop_scope();
+        assert_eq!(table.lookup("T"), Some(&typedef));
+        table.pop_scope();
+        assert!(table.is_file_scope());
+        assert!(table.lookup("U").is_none());
+    }
+
+    #[test]
+    fn redefinitions() {
+        let mut table = SymbolTable::new();
+        table
+            .insert("x", info(None, Site::DeclaredAt(span(0))))
+            .unwrap();
+        table
+            .insert("x", info(None, Site::DefinedAt(span(1))))
+            .unwrap();
+        table
+            .insert(
+                "x",
+                info(Some(StorageClass::Extern), Site::DeclaredAt(span(2))),
+            )
+            .unwrap();
+        assert_eq!(table.lookup("x").unwrap().site, Site::DefinedAt(span(1)));
+
+        assert_eq!(
+            table.insert("x", info(None, Site::DefinedAt(span(3)))),
+            Err(RedefinitionError {
+                name: String::from("x"),
+                span: span(3),
+                previous: span(1),
+            })
+        );
+        assert!(table
+            .insert(
+                "x",
+                info(Some(StorageClass::Typedef), Site::DefinedAt(span(4)))
+            )
+            .is_err());
+
+        // A typedef may be repeated, but not as another type.
+        let typedef = info(Some(StorageClass::Typedef), Site::DefinedAt(span(5)));
+        table.insert("T", typedef.clone()).unwrap();
+        table.insert("T", typedef).unwrap();
+        let long = SymbolInfo {
+            ty: crate::types::LONG,
+            ..info(Some(StorageClass::Typedef), Site::DefinedAt(span(6)))
+        };
+        assert!(table.insert("T", long).is_err());
+    }
+}
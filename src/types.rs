@@ -0,0 +1,405 @@
+use std::ops::{BitOr, BitOrAssign};
+
+use crate::lex::Encoding;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Signedness {
+    Signed,
+    Unsigned,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntSize {
+    Short,
+    Int,
+    Long,
+    LongLong,
+}
+
+/// A set of type qualifiers, combined with `|`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TypeQuals(u8);
+
+impl TypeQuals {
+    pub const NONE: TypeQuals = TypeQuals(0);
+    pub const CONST: TypeQuals = TypeQuals(1);
+    pub const VOLATILE: TypeQuals = TypeQuals(1 << 1);
+    pub const RESTRICT: TypeQuals = TypeQuals(1 << 2);
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether every qualifier in `other` is also in `self`.
+    pub fn contains(self, other: TypeQuals) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for TypeQuals {
+    type Output = TypeQuals;
+
+    fn bitor(self, rhs: TypeQuals) -> TypeQuals {
+        TypeQuals(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for TypeQuals {
+    fn bitor_assign(&mut self, rhs: TypeQuals) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// The length of an array type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArraySize {
+    Fixed(u64),
+    /// `int a[]`, completed later by an initializer or not at all.
+    Incomplete,
+    /// A variable length array, whose size is only known at run time.
+    Variable,
+}
+
+/// Identifies a struct or union definition. Two struct types are the same
+/// type only if they share an id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StructId(pub usize);
+
+/// Identifies an enum definition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EnumId(pub usize);
+
+/// A C type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CType {
+    Void,
+    Bool,
+    Char(Signedness),
+    Int {
+        size: IntSize,
+        sign: Signedness,
+    },
+    Float,
+    Double,
+    LongDouble,
+    /// `quals` qualify the pointer itself, as in `int *const p`.
+    Pointer {
+        pointee: Box<CType>,
+        quals: TypeQuals,
+    },
+    Array {
+        elem: Box<CType>,
+        size: ArraySize,
+    },
+    Function {
+        ret: Box<CType>,
+        params: Vec<CType>,
+        variadic: bool,
+    },
+    Struct(StructId),
+    Union(StructId),
+    Enum(EnumId),
+    /// A name introduced by `typedef`, along with the type it stands for.
+    Typedef(String, Box<CType>),
+    Qualified {
+        inner: Box<CType>,
+        quals: TypeQuals,
+    },
+}
+
+pub const INT: CType = CType::Int {
+    size: IntSize::Int,
+    sign: Signedness::Signed,
+};
+
+pub const LONG: CType = CType::Int {
+    size: IntSize::Long,
+    sign: Signedness::Signed,
+};
+
+pub const UNSIGNED_LONG: CType = CType::Int {
+    size: IntSize::Long,
+    sign: Signedness::Unsigned,
+};
+
+pub const CHAR: CType = CType::Char(Signedness::Signed);
+
+/// The element type of a string literal with `encoding` on an LP64 Linux
+/// target: `wchar_t` is `int`, `char16_t` is `unsigned short` and
+/// `char32_t` is `unsigned int`.
+pub fn string_elem(encoding: Encoding) -> CType {
+    match encoding {
+        Encoding::None | Encoding::Utf8 => CHAR,
+        Encoding::Wide => INT,
+        Encoding::Utf16 => CType::Int {
+            size: IntSize::Short,
+            sign: Signedness::Unsigned,
+        },
+        Encoding::Utf32 => CType::Int {
+            size: IntSize::Int,
+            sign: Signedness::Unsigned,
+        },
+    }
+}
+
+impl CType {
+    /// The type with typedef names and qualifiers looked through.
+    pub fn strip(&self) -> &CType {
+        let mut ty = self;
+        while let CType::Typedef(_, inner) | CType::Qualified { inner, .. } = ty {
+            ty = inner;
+        }
+        ty
+    }
+
+    /// The type pointed to, if this is a pointer.
+    pub fn pointee(&self) -> Option<&CType> {
+        match self.strip() {
+            CType::Pointer { pointee, .. } => Some(pointee),
+            _ => None,
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self.strip(),
+            CType::Bool | CType::Char(_) | CType::Int { .. } | CType::Enum(_)
+        )
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(
+            self.strip(),
+            CType::Float | CType::Double | CType::LongDouble
+        )
+    }
+
+    pub fn is_arithmetic(&self) -> bool {
+        self.is_integer() || self.is_float()
+    }
+
+    /// The width in bits of the values of an integer or pointer type, and
+    /// whether they are signed. A word holding such a value has it sign- or
+    /// zero-extended to 64 bits.
+    pub fn int_repr(&self) -> Option<(u32, bool)> {
+        match self.strip() {
+            CType::Bool => Some((1, false)),
+            CType::Char(sign) => Some((8, *sign == Signedness::Signed)),
+            CType::Int { size, sign } => {
+                Some((size.width() as u32 * 8, *sign == Signedness::Signed))
+            }
+            CType::Enum(_) => Some((32, true)),
+            CType::Pointer { .. } => Some((64, false)),
+            _ => None,
+        }
+    }
+}
+
+impl IntSize {
+    /// The integer conversion rank; `_Bool` and `char` rank below all of
+    /// these.
+    fn rank(self) -> u8 {
+        match self {
+            IntSize::Short => 1,
+            IntSize::Int => 2,
+            IntSize::Long => 3,
+            IntSize::LongLong => 4,
+        }
+    }
+
+    /// Size in bytes, on an LP64 target.
+    pub fn width(self) -> u8 {
+        match self {
+            IntSize::Short => 2,
+            IntSize::Int => 4,
+            IntSize::Long | IntSize::LongLong => 8,
+        }
+    }
+}
+
+/// The integer promotions of C11 6.3.1.1: `_Bool`, `char`, `short` and
+/// enums, signed or not, become `int`, since `int` can hold all their
+/// values. Every other type is returned as it is, without typedef names or
+/// qualifiers.
+pub fn integer_promotions(t: &CType) -> CType {
+    match t.strip() {
+        CType::Bool
+        | CType::Char(_)
+        | CType::Enum(_)
+        | CType::Int {
+            size: IntSize::Short,
+            ..
+        } => INT,
+        ty => ty.clone(),
+    }
+}
+
+/// The usual arithmetic conversions of C11 6.3.1.8: the type both operands
+/// of an arithmetic operator are converted to. Both must be arithmetic
+/// types.
+pub fn usual_arithmetic_conversions(a: &CType, b: &CType) -> CType {
+    for float in [CType::LongDouble, CType::Double, CType::Float] {
+        if *a.strip() == float || *b.strip() == float {
+            return float;
+        }
+    }
+    let (
+        CType::Int {
+            size: size_a,
+            sign: sign_a,
+        },
+        CType::Int {
+            size: size_b,
+            sign: sign_b,
+        },
+    ) = (integer_promotions(a), integer_promotions(b))
+    else {
+        return INT;
+    };
+
+    // The same signedness: the greater rank wins.
+    if sign_a == sign_b {
+        let size = if size_a.rank() >= size_b.rank() {
+            size_a
+        } else {
+            size_b
+        };
+        return CType::Int { size, sign: sign_a };
+    }
+
+    // Otherwise the unsigned type wins unless the signed one ranks higher
+    // and can hold all its values, and if it cannot, both become the
+    // unsigned version of the signed type.
+    let (signed, unsigned) = if sign_a == Signedness::Signed {
+        (size_a, size_b)
+    } else {
+        (size_b, size_a)
+    };
+    let (size, sign) = if unsigned.rank() >= signed.rank() {
+        (unsigned, Signedness::Unsigned)
+    } else if signed.width() > unsigned.width() {
+        (signed, Signedness::Signed)
+    } else {
+        (signed, Signedness::Unsigned)
+    };
+    CType::Int { size, sign }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn type_quals() {
+        let mut quals = TypeQuals::CONST | TypeQuals::VOLATILE;
+        assert!(quals.contains(TypeQuals::CONST));
+        assert!(!quals.contains(TypeQuals::RESTRICT));
+        assert!(!quals.contains(TypeQuals::CONST | TypeQuals::RESTRICT));
+
+        quals |= TypeQuals::RESTRICT;
+        assert!(quals.contains(TypeQuals::CONST | TypeQuals::RESTRICT));
+        assert!(TypeQuals::default().is_empty());
+        assert_eq!(TypeQuals::default(), TypeQuals::NONE);
+    }
+
+    fn int(size: IntSize, sign: Signedness) -> CType {
+        CType::Int { size, sign }
+    }
+
+    #[test]
+    fn integer_promotion() {
+        use IntSize::*;
+        use Signedness::*;
+        let cases = [
+            (CType::Bool, INT),
+            (CType::Char(Signed), INT),
+            (CType::Char(Unsigned), INT),
+            (int(Short, Signed), INT),
+            (int(Short, Unsigned), INT),
+            (CType::Enum(EnumId(0)), INT),
+            (INT, INT),
+            (int(Int, Unsigned), int(Int, Unsigned)),
+            (int(Long, Signed), int(Long, Signed)),
+            (int(Long, Unsigned), int(Long, Unsigned)),
+            (int(LongLong, Signed), int(LongLong, Signed)),
+            (int(LongLong, Unsigned), int(LongLong, Unsigned)),
+            (CType::Float, CType::Float),
+            (CType::Double, CType::Double),
+            (CType::LongDouble, CType::LongDouble),
+        ];
+        for (ty, promoted) in cases {
+            assert_eq!(integer_promotions(&ty), promoted, "{:?}", ty);
+        }
+
+        let qualified = CType::Qualified {
+            inner: Box::new(CType::Typedef(
+                String::from("u8"),
+                Box::new(CType::Char(Unsigned)),
+            )),
+            quals: TypeQuals::CONST,
+        };
+        assert_eq!(integer_promotions(&qualified), INT);
+        let size_t = CType::Typedef(String::from("size_t"), Box::new(int(Long, Unsigned)));
+        assert_eq!(integer_promotions(&size_t), int(Long, Unsigned));
+    }
+
+    #[test]
+    fn arithmetic_conversions() {
+        use IntSize::*;
+        use Signedness::*;
+        // What each operand type promotes to, as an index into `promoted`.
+        let small = [
+            (CType::Bool, 0),
+            (CType::Char(Signed), 0),
+            (CType::Char(Unsigned), 0),
+            (int(Short, Signed), 0),
+            (int(Short, Unsigned), 0),
+        ];
+        let promoted = [
+            INT,
+            int(Int, Unsigned),
+            int(Long, Signed),
+            int(Long, Unsigned),
+            int(LongLong, Signed),
+            int(LongLong, Unsigned),
+        ];
+        // The common type of each pair of promoted types, in the same order.
+        let common = [
+            [0, 1, 2, 3, 4, 5],
+            [1, 1, 2, 3, 4, 5],
+            [2, 2, 2, 3, 4, 5],
+            [3, 3, 3, 3, 5, 5],
+            [4, 4, 4, 5, 4, 5],
+            [5, 5, 5, 5, 5, 5],
+        ];
+        let integers: Vec<(CType, usize)> = small
+            .into_iter()
+            .chain(promoted.iter().cloned().zip(0..))
+            .collect();
+
+        for (a, i) in &integers {
+            for (b, j) in &integers {
+                let expected = &promoted[common[*i][*j]];
+                assert_eq!(
+                    usual_arithmetic_conversions(a, b),
+                    *expected,
+                    "{:?} and {:?}",
+                    a,
+                    b
+                );
+            }
+        }
+
+        let floats = [CType::Float, CType::Double, CType::LongDouble];
+        for (rank, float) in floats.iter().enumerate() {
+            for (other, _) in &integers {
+                assert_eq!(usual_arithmetic_conversions(float, other), *float);
+                assert_eq!(usual_arithmetic_conversions(other, float), *float);
+            }
+            for (other_rank, other) in floats.iter().enumerate() {
+                let expected = &floats[rank.max(other_rank)];
+                assert_eq!(usual_arithmetic_conversions(float, other), *expected);
+            }
+        }
+    }
+}